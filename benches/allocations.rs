@@ -0,0 +1,60 @@
+// Heap allocations of the interpreter loop for the example programs, run with `cargo bench --bench allocations`.
+// Only the run itself is counted, not loading and starting the program. Programs without recursion get their whole
+// stack when starting, so running them shouldn't allocate at all; the recursive ones only when the stack grows.
+// Numbers the programs read are always 3 and their output is discarded.
+use pl0_vm_rs::pl0_vm::PL0VM;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::sink;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// the system allocator, counting allocations and reallocations
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn main() {
+    let mut files: Vec<_> = std::fs::read_dir("cl0").expect("run from the crate directory").filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    files.sort();
+    let mut allocating = false;
+    for file in files {
+        let vm = match PL0VM::from_file(&file.to_string_lossy()) {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let mut execution = match vm.start() {
+            Some(val) => val,
+            None => continue,
+        };
+        execution.feed_inputs([3; 100]);
+        execution.close_input();
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        execution.run(&mut sink());
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        let instructions = execution.metrics().instructions;
+        let recursive = vm.stack_usage().map(|usage| usage.total.is_none()).unwrap_or(true);
+        println!("{:<24} {:>10} instructions  {:>6} allocations  {:>8.4} per instruction{}", file.display(), instructions,
+            allocations, allocations as f64 / instructions.max(1) as f64, if recursive { "  (recursive)" } else { "" });
+        allocating |= !recursive && allocations > 0;
+    }
+    if allocating {
+        eprintln!("programs without recursion allocated while running");
+        std::process::exit(1);
+    }
+}
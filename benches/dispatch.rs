@@ -0,0 +1,48 @@
+// Time of the interpreter loop for the example programs, run with `cargo bench`.
+// The programs are started once and then run repeatedly from there with their output discarded,
+// numbers they read are always 3. Every program runs once with the normal steps and once with --fast.
+use pl0_vm_rs::pl0_vm::{Execution, PL0VM};
+use std::io::sink;
+use std::time::{Duration, Instant};
+
+// every program runs at least this long, so short ones are measured often enough
+const MIN_TIME: Duration = Duration::from_secs(1);
+
+// the number of executed instructions
+fn run(start: &Execution, fast: bool) -> usize {
+    let mut execution = start.clone();
+    if fast {
+        execution.run_fast(&mut sink());
+    } else {
+        execution.run(&mut sink());
+    }
+    execution.metrics().instructions
+}
+
+fn main() {
+    let mut files: Vec<_> = std::fs::read_dir("cl0").expect("run from the crate directory").filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    files.sort();
+    for file in files {
+        let vm = match PL0VM::from_file(&file.to_string_lossy()) {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let mut execution = match vm.start() {
+            Some(val) => val,
+            None => continue,
+        };
+        execution.feed_inputs([3; 100]);
+        execution.close_input();
+        for fast in [false, true] {
+            let start = Instant::now();
+            let (mut runs, mut instructions) = (0, 0);
+            while start.elapsed() < MIN_TIME {
+                instructions += run(&execution, fast);
+                runs += 1;
+            }
+            let elapsed = start.elapsed();
+            println!("{:<24} {:<6} {:>8} runs  {:>10.2} µs/run  {:>8.2} ns/instruction", file.display(), if fast { "fast" } else { "normal" },
+                runs, elapsed.as_secs_f64() * 1e6 / runs as f64, elapsed.as_secs_f64() * 1e9 / instructions as f64);
+        }
+    }
+}
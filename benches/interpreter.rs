@@ -0,0 +1,46 @@
+// Criterion benchmarks of the interpreter loop, run with `cargo bench --bench interpreter`. Criterion keeps the
+// results of the last run in target/criterion and reports when a program got slower than there.
+// The programs in benches/programs each stress one part of the interpreter:
+//   loop.cl0       arithmetic and jumps in main, adding up 0..100000
+//   recursion.cl0  a procedure calling itself 10000 times deep, with a local variable in every frame
+//   strings.cl0    PutString and OutputValue 2000 times each
+// The example programs in cl0 run as well. All programs are started once and then run from there with their output
+// discarded, numbers they read are always 3.
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use pl0_vm_rs::pl0_vm::PL0VM;
+use std::io::sink;
+use std::path::PathBuf;
+
+fn programs(dir: &str) -> Vec<PathBuf> {
+    let mut files: Vec<_> = std::fs::read_dir(dir).expect("run from the crate directory").filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "cl0"))
+        .collect();
+    files.sort();
+    files
+}
+
+fn interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpreter");
+    for file in programs("benches/programs").into_iter().chain(programs("cl0")) {
+        let vm = match PL0VM::from_file(&file.to_string_lossy()) {
+            Ok(val) => val,
+            Err(_) => continue,
+        };
+        let mut start = match vm.start() {
+            Some(val) => val,
+            None => continue,
+        };
+        start.feed_inputs([3; 100]);
+        start.close_input();
+        // throughput in executed instructions, so programs of different length compare
+        let mut execution = start.clone();
+        execution.run(&mut sink());
+        group.throughput(Throughput::Elements(execution.metrics().instructions as u64));
+        let name = file.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        group.bench_function(name, |b| b.iter_batched(|| start.clone(), |mut execution| execution.run(&mut sink()), BatchSize::SmallInput));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+use crate::pl0_vm::{capture_errors, error, Step, PL0VM};
+use crate::replay::Replay;
+use rust_i18n::t;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// Running many programs at once, for grading a lot of submissions: every file gets its own VM on one of the worker
+// threads, its output and error messages are collected instead of printed. All programs get the same seed and read
+// the same numbers, reading more of them than given is a runtime error since there is no stdin to wait for.
+
+// what running one file did
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    pub file: String,
+    // how the program stopped, None if it couldn't be loaded or didn't pass the checks before running
+    pub state: Option<Step>,
+    // everything the program printed
+    pub output: Vec<u8>,
+    // error messages, one per line, as they would have gone to stderr
+    pub errors: String,
+}
+
+// run the files on up to jobs threads (at least one), the results are in the order of the files
+pub fn run_batch(files: &[String], jobs: usize, replay: &Replay, fast: bool) -> Vec<BatchResult> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![None; files.len()]);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let file = match files.get(i) {
+                    Some(val) => val,
+                    None => break,
+                };
+                let result = run_file(file, replay, fast);
+                results.lock().expect("a worker panicked")[i] = Some(result);
+            });
+        }
+    });
+    results.into_inner().expect("a worker panicked").into_iter().map(|result| result.expect("every file was run")).collect()
+}
+
+fn run_file(file: &str, replay: &Replay, fast: bool) -> BatchResult {
+    let mut output = vec![];
+    let (state, errors) = capture_errors(|| {
+        let mut vm = match PL0VM::from_file(file) {
+            Ok(val) => val,
+            Err(err) => {
+                error(&format!("{} {}", t!("file_error", file = file), err));
+                return None;
+            }
+        };
+        vm.set_seed(replay.seed);
+        let mut execution = vm.start()?;
+        execution.feed_inputs(replay.inputs.iter().copied());
+        execution.close_input();
+        Some(if fast { execution.run_fast(&mut output) } else { execution.run(&mut output) })
+    });
+    BatchResult { file: file.to_string(), state, output, errors }
+}
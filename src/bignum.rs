@@ -0,0 +1,251 @@
+use crate::opcodes::OpCode;
+use rust_i18n::t;
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+// Arbitrary-precision integers for the bignum mode (feature bignum, --bignum): the arithmetic and comparisons work on
+// numbers of any size instead of wrapping at the data width, so e.g. large factorials come out right. Stack cells
+// stay i64, a number that doesn't fit into one is kept in the Bigs of the execution until the run ends and the cell
+// holds a reference to it, i64::MIN + its index. Other operations see these references as they are, the bit operations
+// refuse them.
+
+// cells below this are references
+const PLAIN_MIN: i64 = i64::MIN + (1 << 32);
+
+// sign and magnitude, the limbs are base 2^32 with the lowest first and no zeros at the end (0 has none)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    fn from_i64(val: i64) -> BigInt {
+        let mag = val.unsigned_abs();
+        BigInt::new(val < 0, vec![mag as u32, (mag >> 32) as u32])
+    }
+
+    fn new(negative: bool, mut limbs: Vec<u32>) -> BigInt {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigInt { negative: negative && !limbs.is_empty(), limbs }
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        if self.limbs.len() > 2 {
+            return None;
+        }
+        let mag = self.limbs.iter().rev().fold(0u64, |acc, &limb| acc << 32 | limb as u64);
+        if self.negative { 0i64.checked_sub_unsigned(mag) } else { i64::try_from(mag).ok() }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    fn neg(mut self) -> BigInt {
+        self.negative = !self.negative && !self.is_zero();
+        self
+    }
+
+    fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, mag_add(&self.limbs, &other.limbs));
+        }
+        match mag_cmp(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::new(other.negative, mag_sub(&other.limbs, &self.limbs)),
+            _ => BigInt::new(self.negative, mag_sub(&self.limbs, &other.limbs)),
+        }
+    }
+
+    fn mul(&self, other: &BigInt) -> BigInt {
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let sum = limbs[i + j] as u64 + a as u64 * b as u64 + carry;
+                limbs[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        BigInt::new(self.negative != other.negative, limbs)
+    }
+
+    // quotient rounded towards 0 and remainder with the sign of self, like i64, None for a division by 0
+    fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        // bit by bit, the numbers of exercises don't get big enough for anything faster to matter
+        let mut quotient = vec![0u32; self.limbs.len()];
+        let mut rem: Vec<u32> = vec![];
+        for bit in (0..self.limbs.len() * 32).rev() {
+            rem = mag_add(&rem, &rem);
+            if self.limbs[bit / 32] >> (bit % 32) & 1 == 1 {
+                rem = mag_add(&rem, &[1]);
+            }
+            if mag_cmp(&rem, &other.limbs) != Ordering::Less {
+                rem = mag_sub(&rem, &other.limbs);
+                quotient[bit / 32] |= 1 << (bit % 32);
+            }
+        }
+        Some((BigInt::new(self.negative != other.negative, quotient), BigInt::new(self.negative, rem)))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => mag_cmp(&self.limbs, &other.limbs),
+            (true, true) => mag_cmp(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // groups of 9 digits, the lowest first
+        let mut groups = vec![];
+        let mut mag = self.limbs.clone();
+        while !mag.is_empty() {
+            let mut rem = 0u64;
+            for limb in mag.iter_mut().rev() {
+                let cur = rem << 32 | *limb as u64;
+                *limb = (cur / 1_000_000_000) as u32;
+                rem = cur % 1_000_000_000;
+            }
+            while mag.last() == Some(&0) {
+                mag.pop();
+            }
+            groups.push(rem);
+        }
+        let mut text = if self.negative { "-".to_string() } else { String::new() };
+        match groups.split_last() {
+            Some((first, rest)) => {
+                text += &first.to_string();
+                for group in rest.iter().rev() {
+                    text += &format!("{group:09}");
+                }
+            },
+            None => text += "0",
+        }
+        f.pad(&text)
+    }
+}
+
+fn mag_cmp(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn mag_add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let cur = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(cur as u32);
+        carry = cur >> 32;
+    }
+    if carry > 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+// a - b with a >= b
+fn mag_sub(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut diff = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut cur = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = (cur < 0) as i64;
+        if cur < 0 {
+            cur += 1 << 32;
+        }
+        diff.push(cur as u32);
+    }
+    while diff.last() == Some(&0) {
+        diff.pop();
+    }
+    diff
+}
+
+// the numbers of an execution that don't fit into a cell
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Bigs {
+    values: Vec<BigInt>,
+}
+
+impl Bigs {
+    // operations that work on numbers of any size
+    pub fn handles(op: OpCode) -> bool {
+        matches!(op, OpCode::OpAdd | OpCode::OpSubtract | OpCode::OpMultiply | OpCode::OpDivide | OpCode::OpModulo
+            | OpCode::CompareEq | OpCode::CompareNotEq | OpCode::CompareLT | OpCode::CompareGT
+            | OpCode::CompareLTEq | OpCode::CompareGTEq | OpCode::Minusify | OpCode::IsOdd | OpCode::OutputValue)
+    }
+
+    pub fn is_reference(cell: i64) -> bool {
+        cell < PLAIN_MIN
+    }
+
+    pub fn get(&self, cell: i64) -> Result<BigInt, String> {
+        if !Bigs::is_reference(cell) {
+            return Ok(BigInt::from_i64(cell));
+        }
+        self.values.get((cell - i64::MIN) as usize).cloned().ok_or_else(|| t!("pl0.error.big_reference", val = cell).to_string())
+    }
+
+    // the cell for the number, a reference if it doesn't fit
+    fn store(&mut self, val: BigInt) -> i64 {
+        match val.to_i64() {
+            Some(cell) if !Bigs::is_reference(cell) => cell,
+            _ => {
+                self.values.push(val);
+                i64::MIN + (self.values.len() - 1) as i64
+            },
+        }
+    }
+
+    pub fn unary(&mut self, op: OpCode, cell: i64) -> Result<i64, String> {
+        let val = self.get(cell)?;
+        Ok(match op {
+            OpCode::Minusify => self.store(val.neg()),
+            OpCode::IsOdd => val.limbs.first().is_some_and(|limb| limb & 1 == 1) as i64,
+            _ => unreachable!("{op} is no unary operation"),
+        })
+    }
+
+    pub fn binary(&mut self, op: OpCode, left: i64, right: i64) -> Result<i64, String> {
+        let (left, right) = (self.get(left)?, self.get(right)?);
+        let division = || left.div_rem(&right).ok_or_else(|| t!("pl0.error.division_by_zero").to_string());
+        Ok(match op {
+            OpCode::OpAdd => self.store(left.add(&right)),
+            OpCode::OpSubtract => self.store(left.add(&right.clone().neg())),
+            OpCode::OpMultiply => self.store(left.mul(&right)),
+            OpCode::OpDivide => {
+                let (quotient, _) = division()?;
+                self.store(quotient)
+            },
+            OpCode::OpModulo => {
+                let (_, rem) = division()?;
+                self.store(rem)
+            },
+            OpCode::CompareEq => (left == right) as i64,
+            OpCode::CompareNotEq => (left != right) as i64,
+            OpCode::CompareLT => (left < right) as i64,
+            OpCode::CompareGT => (left > right) as i64,
+            OpCode::CompareLTEq => (left <= right) as i64,
+            OpCode::CompareGTEq => (left >= right) as i64,
+            _ => unreachable!("{op} is no binary operation"),
+        })
+    }
+}
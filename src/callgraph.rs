@@ -0,0 +1,80 @@
+use crate::opcodes::OpCode;
+use crate::pl0_vm::PL0VM;
+use crate::diagln;
+use rust_i18n::t;
+
+// Which procedures call which other procedures, built from the CallProc and CallProcArgs instructions of the code.
+// Calls of procedures that don't exist are left out, the verifier reports them.
+#[derive(Debug, Default, Clone)]
+pub struct CallGraph {
+    // procedure ID => IDs of the procedures it calls, each only once, in the order of the first call
+    pub calls: Vec<Vec<usize>>,
+}
+
+impl CallGraph {
+    pub fn callees(&self, proc_id: usize) -> &[usize] {
+        self.calls.get(proc_id).map(|callees| callees.as_slice()).unwrap_or_default()
+    }
+
+    // all procedures that can be reached by calls starting in the given one (not including itself, unless it is recursive)
+    pub fn reachable_from(&self, proc_id: usize) -> Vec<bool> {
+        let mut reached = vec![false; self.calls.len()];
+        let mut pending = self.callees(proc_id).to_vec();
+        while let Some(id) = pending.pop() {
+            if reached[id] { continue; }
+            reached[id] = true;
+            pending.extend(self.callees(id));
+        }
+        reached
+    }
+
+    // Groups of procedures that call each other (directly or through others) and procedures calling themselves,
+    // each sorted by ID. A program without cycles never nests calls deeper than the number of procedures.
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        let reachable: Vec<Vec<bool>> = (0..self.calls.len()).map(|id| self.reachable_from(id)).collect();
+        let mut cycles: Vec<Vec<usize>> = vec![];
+        for (id, reached) in reachable.iter().enumerate() {
+            if !reached[id] || cycles.iter().any(|cycle| cycle.contains(&id)) { continue; }
+            cycles.push((id..self.calls.len()).filter(|&other| reached[other] && reachable[other][id]).collect());
+        }
+        cycles
+    }
+
+    pub fn is_recursive(&self, proc_id: usize) -> bool {
+        self.reachable_from(proc_id).get(proc_id).copied().unwrap_or_default()
+    }
+}
+
+impl PL0VM {
+    pub fn call_graph(&self) -> Result<CallGraph, String> {
+        let layout = self.procedure_layout()?;
+        let instructions = self.decode_code()?;
+        let mut graph = CallGraph { calls: vec![vec![]; layout.len()] };
+        for ((caller, _), procedure) in layout.iter().zip(instructions.chunk_by(|_, instr| instr.op != OpCode::EntryProc)) {
+            for instr in procedure.iter().filter(|instr| instr.op.is_call()) {
+                let callee = self.read_operand(instr.arg_addr(0), instr.wide).expect("length checked while decoding");
+                match usize::try_from(callee) {
+                    Ok(callee) if callee < layout.len() && !graph.calls[*caller].contains(&callee) => graph.calls[*caller].push(callee),
+                    _ => {},
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    // for --callgraph: the procedures every procedure calls, then all recursion cycles
+    pub fn print_call_graph(&self) {
+        let graph = match self.call_graph() {
+            Ok(val) => val,
+            Err(err) => return diagln!("{}", err),
+        };
+        let labels = |ids: &[usize]| ids.iter().map(|&id| self.proc_label(id)).collect::<Vec<_>>().join(", ");
+        for (id, callees) in graph.calls.iter().enumerate() {
+            let callees = if callees.is_empty() { "-".to_string() } else { labels(callees) };
+            diagln!("{} -> {}", self.proc_label(id), callees);
+        }
+        for cycle in graph.cycles() {
+            diagln!("{}", t!("callgraph.cycle", procedures = labels(&cycle)));
+        }
+    }
+}
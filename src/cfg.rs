@@ -0,0 +1,102 @@
+use crate::decoder::Instruction;
+use crate::opcodes::OpCode;
+use crate::pl0_vm::PL0VM;
+use rust_i18n::t;
+
+// Control flow graph of one procedure: the code is split into basic blocks, sequences of instructions that
+// always run from the first to the last, connected by jumps and by falling through to the next block.
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    // addresses of the instructions in the block
+    pub instructions: Vec<usize>,
+    // indexes of the blocks that can run next, the jump target of a JumpIfFalse comes first
+    pub successors: Vec<usize>,
+}
+
+impl PL0VM {
+    pub fn control_flow_graph(&self, proc_id: usize) -> Result<Vec<BasicBlock>, String> {
+        let procedure = self.procedure_instructions(proc_id)?;
+        // first instructions of blocks: the EntryProc, jump targets and everything following a jump or return
+        let mut leaders = vec![false; procedure.len()];
+        leaders[0] = true;
+        for index in 0..procedure.len() {
+            let successors = self.successors(&procedure, index);
+            if successors != [index + 1] {
+                successors.iter().for_each(|&next| leaders[next] = true);
+                if index + 1 < procedure.len() { leaders[index + 1] = true; }
+            }
+        }
+        let starts: Vec<usize> = (0..procedure.len()).filter(|&index| leaders[index]).collect();
+
+        Ok(starts.iter().enumerate().map(|(i, &start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(procedure.len());
+            BasicBlock {
+                instructions: procedure[start..end].iter().map(|instr| instr.addr).collect(),
+                successors: self.successors(&procedure, end - 1).iter()
+                    .map(|next| starts.iter().position(|start| start == next).expect("successors start blocks"))
+                    .collect(),
+            }
+        }).collect())
+    }
+
+    // the control flow graph of the procedure in the DOT language of Graphviz
+    pub fn control_flow_graph_dot(&self, proc_id: usize) -> Result<String, String> {
+        let blocks = self.control_flow_graph(proc_id)?;
+        let procedure = self.procedure_instructions(proc_id)?;
+        let mut dot = format!("digraph \"{}\" {{\n    node [shape=box, fontname=monospace];\n", escape(&self.proc_label(proc_id)));
+        for (i, block) in blocks.iter().enumerate() {
+            let lines: String = block.instructions.iter()
+                .map(|addr| procedure.iter().find(|instr| instr.addr == *addr).expect("blocks only contain instructions of the procedure"))
+                .map(|instr| format!("{}\\l", escape(&self.instruction_text(instr))))
+                .collect();
+            dot += &format!("    b{i} [label=\"{lines}\"];\n");
+        }
+        for (i, block) in blocks.iter().enumerate() {
+            let last = procedure.iter().find(|instr| Some(&instr.addr) == block.instructions.last()).expect("blocks are never empty");
+            for (j, &next) in block.successors.iter().enumerate() {
+                // the jump target of JumpIfFalse is listed first
+                let label = match (last.op, block.successors.len(), j) {
+                    (OpCode::JumpIfFalse, 2, 0) => " [label=\"false\"]",
+                    (OpCode::JumpIfFalse, 2, _) => " [label=\"true\"]",
+                    _ => "",
+                };
+                dot += &format!("    b{i} -> b{next}{label};\n");
+            }
+        }
+        dot += "}\n";
+        Ok(dot)
+    }
+
+    fn procedure_instructions(&self, proc_id: usize) -> Result<Vec<Instruction>, String> {
+        let instructions = self.decode_code()?;
+        let layout = self.procedure_layout()?;
+        let range = match layout.iter().find(|(id, _)| *id == proc_id) {
+            Some((_, range)) => range.clone(),
+            None => return Err(t!("cfg.invalid_procedure", id = proc_id).to_string()),
+        };
+        Ok(instructions.iter().filter(|instr| range.contains(&instr.addr)).cloned().collect())
+    }
+
+    // one line of the listing, without the bytes
+    pub(crate) fn instruction_text(&self, instr: &Instruction) -> String {
+        let name = if instr.wide { format!("{} {}", OpCode::Wide, instr.op) } else { instr.op.to_string() };
+        let args = match instr.op {
+            OpCode::PutString => {
+                let bytes = &self.program()[(instr.addr + 1)..(instr.addr + instr.len - 1)];
+                format!(" \"{}\"", self.decode_string(bytes).unwrap_or_else(|_| String::from_utf8_lossy(bytes)))
+            },
+            OpCode::Jump | OpCode::JumpIfFalse => match self.jump_target(instr) {
+                Some(target) => format!(" => {:04X}", target),
+                None => String::new(),
+            },
+            _ => (0..instr.op.arg_count())
+                .map(|i| format!(" {}", self.read_operand(instr.arg_addr(i), instr.wide).expect("length checked while decoding")))
+                .collect(),
+        };
+        format!("{:04X}: {}{}", instr.addr, name, args)
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,54 @@
+use crate::pl0_vm::{Execution, PL0VM};
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+
+// Core files hold the state of a program that stopped with a runtime error, as JSON: the whole program file
+// (so the dump can be inspected without it) and everything that changes while running.
+#[derive(Serialize, Deserialize)]
+pub struct CoreDump {
+    // the program file as hex, including all sections
+    pub(crate) program: String,
+    // address of the instruction that caused the error
+    pub(crate) fault: Option<usize>,
+    pub(crate) pc: usize,
+    pub(crate) fp: usize,
+    pub(crate) procedure: usize,
+    pub(crate) depth: usize,
+    // procedure ID => start of its latest frame
+    pub(crate) frame_ptrs: Vec<usize>,
+    // one value of the architecture per entry, like Execution::stack_values
+    pub(crate) stack: Vec<i64>,
+    // numbers the program read until the error
+    pub(crate) inputs: Vec<i64>,
+}
+
+impl CoreDump {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("core dumps can always be serialized")
+    }
+    pub fn from_json(json: &str) -> Result<CoreDump, String> {
+        serde_json::from_str(json).map_err(|err| t!("coredump.invalid", err = err).to_string())
+    }
+
+    // the program the dump was made of
+    pub fn load_program(&self) -> Result<PL0VM, String> {
+        match crate::json::from_hex(&self.program) {
+            Some(bytes) => Ok(PL0VM::from_bytes(bytes)),
+            None => Err(t!("coredump.invalid", err = "program").to_string()),
+        }
+    }
+    // the execution as it was when the dump was made, vm has to be the program from load_program
+    pub fn restore<'a>(&self, vm: &'a PL0VM) -> Result<Execution<'a>, String> {
+        let mut execution = vm.start().ok_or_else(|| t!("coredump.invalid", err = "program").to_string())?;
+        match execution.restore(self) {
+            true => Ok(execution),
+            false => Err(t!("coredump.invalid", err = "state").to_string()),
+        }
+    }
+}
+
+impl Execution<'_> {
+    pub fn to_core_dump(&self) -> CoreDump {
+        self.core_dump()
+    }
+}
@@ -0,0 +1,66 @@
+use crate::pl0_vm::{Step, PL0VM, START_FAILED_STATUS};
+use crate::diagln;
+use rust_i18n::t;
+use std::collections::BTreeSet;
+use std::io::stdout;
+
+// Addresses of the instructions that were executed, collected over one or more runs of the same program.
+// Coverage files hold them as hex, one per line, so the runs of a test suite can be merged. Lines starting
+// with # are comments.
+#[derive(Debug, Default, Clone)]
+pub struct Coverage {
+    pub executed: BTreeSet<usize>,
+}
+
+impl Coverage {
+    pub fn parse(text: &str) -> Result<Coverage, String> {
+        let executed = text.lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| usize::from_str_radix(line, 16).map_err(|_| t!("coverage.invalid_line", line = i + 1, text = line).to_string()))
+            .collect::<Result<_, _>>()?;
+        Ok(Coverage { executed })
+    }
+
+    pub fn format(&self, program: &str) -> String {
+        let mut text = format!("# {}\n", t!("coverage.header", file = program));
+        for addr in &self.executed {
+            text += &format!("{:04X}\n", addr);
+        }
+        text
+    }
+}
+
+impl PL0VM {
+    // run like execute and add the addresses of all executed instructions to coverage, returns the status like execute
+    pub fn execute_covered(&self, coverage: &mut Coverage) -> i64 {
+        let mut execution = match self.start() {
+            Some(val) => val,
+            None => return START_FAILED_STATUS,
+        };
+        loop {
+            coverage.executed.insert(execution.pc());
+            if execution.step(&mut stdout()) != Step::Running {
+                break;
+            }
+        }
+        execution.status()
+    }
+
+    // for --coverage: the code with every instruction that was never executed marked, then how much was executed
+    pub fn print_coverage(&self, coverage: &Coverage) {
+        let instructions = match self.decode_code() {
+            Ok(val) => val,
+            Err(err) => return diagln!("{}", err),
+        };
+        diagln!();
+        for instr in instructions {
+            let marker = if coverage.executed.contains(&instr.addr) { "     " } else { "#####" };
+            diagln!("{} {}", marker, self.instruction_text(instr));
+        }
+        let executed = instructions.iter().filter(|instr| coverage.executed.contains(&instr.addr)).count();
+        let percent = if instructions.is_empty() { 100.0 } else { executed as f64 * 100.0 / instructions.len() as f64 };
+        diagln!("{}", t!("coverage.summary", executed = executed, total = instructions.len(), percent = percent:{:.1}));
+    }
+}
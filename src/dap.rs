@@ -0,0 +1,339 @@
+use crate::debugger::Debugger;
+use crate::decoder::decode_instruction;
+use crate::expr::Expr;
+use crate::pl0_vm::{Step, CALL_FRAME_SIZE, PL0VM};
+use rust_i18n::t;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{stdin, stdout, BufRead, Read, Write};
+use std::path::Path;
+
+// Debug Adapter Protocol server for --dap: the requests of an editor come in on stdin, responses and events go
+// out on stdout, each message is JSON with a Content-Length header. There is a single thread (ID 1), every
+// active procedure call is a stack frame and every frame has one scope with the values of its stack part.
+// Program output is sent as output events, the numbers the program reads come from the "inputs" array of the
+// launch arguments, as stdin is taken by the protocol.
+struct Server<'a> {
+    vm: &'a PL0VM,
+    debugger: Debugger<'a>,
+    // sequence number of the next message sent
+    seq: i64,
+    // breakpoint numbers of the debugger set for each source file, replaced by the next setBreakpoints for it
+    source_breakpoints: HashMap<String, Vec<usize>>,
+    instruction_breakpoints: Vec<usize>,
+    // from the launch arguments: stop in front of the first instruction instead of running to the first breakpoint
+    stop_on_entry: bool,
+}
+
+const THREAD_ID: i64 = 1;
+
+fn read_message() -> Option<Value> {
+    let mut stdin = stdin().lock();
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if stdin.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(val) = line.strip_prefix("Content-Length:") {
+            length = val.trim().parse().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    stdin.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+impl Server<'_> {
+    fn send(&mut self, mut message: Value) {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        let body = message.to_string();
+        let mut out = stdout().lock();
+        write!(out, "Content-Length: {}\r\n\r\n{}", body.len(), body).expect("Could not write to stdout");
+        out.flush().expect("Could not write to stdout");
+    }
+    fn respond(&mut self, request: &Value, result: Result<Value, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response);
+    }
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    // add the source location of an instruction to a stack frame or breakpoint, line 0 without a line table
+    fn locate(&self, addr: usize, mut value: Value) -> Value {
+        value["line"] = json!(0);
+        if let Some((file, line)) = self.vm.source_location(addr) {
+            value["source"] = json!({ "name": Path::new(file).file_name().and_then(|name| name.to_str()).unwrap_or(file), "path": file });
+            value["line"] = json!(line);
+        }
+        value
+    }
+
+    // Run instructions while keep_going says so, sending the program output, until a breakpoint or watchpoint
+    // stops execution or the program ends. The first instruction always runs.
+    fn run(&mut self, reason: &str, keep_going: impl Fn(&Debugger) -> bool) {
+        let mut output = vec![];
+        let mut state;
+        let mut stopped_at_breakpoint = false;
+        loop {
+            state = self.debugger.step(&mut output);
+            if state != Step::Running {
+                break;
+            }
+            if self.debugger.at_stop() {
+                stopped_at_breakpoint = true;
+                break;
+            }
+            if !keep_going(&self.debugger) {
+                break;
+            }
+        }
+        if !output.is_empty() {
+            self.event("output", json!({ "category": "stdout", "output": String::from_utf8_lossy(&output) }));
+        }
+        match state {
+            Step::Running => {
+                let reason = if stopped_at_breakpoint { "breakpoint" } else { reason };
+                self.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }));
+            },
+            Step::Finished | Step::Error => {
+                self.event("exited", json!({ "exitCode": if state == Step::Finished { 0 } else { 1 } }));
+                self.event("terminated", json!({}));
+            },
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.debugger.execution().map(|execution| execution.call_depth()).unwrap_or_default()
+    }
+
+    // instruction breakpoints of one kind are replaced as a whole: the previous ones are disabled, the new ones added
+    fn replace_breakpoints(&mut self, previous: &[usize], requested: Vec<(Option<usize>, Option<&str>)>) -> (Vec<usize>, Vec<Value>) {
+        for number in previous {
+            self.debugger.set_breakpoint_enabled(*number, false);
+        }
+        let mut numbers = vec![];
+        let mut results = vec![];
+        for (addr, condition) in requested {
+            let condition = condition.filter(|condition| !condition.trim().is_empty()).map(Expr::parse).transpose();
+            match (addr, condition) {
+                (Some(addr), Ok(condition)) if decode_instruction(self.vm.program(), addr).is_ok() => {
+                    let number = self.debugger.add_breakpoint(addr);
+                    self.debugger.set_breakpoint_condition(number, condition);
+                    numbers.push(number);
+                    results.push(self.locate(addr, json!({ "id": number, "verified": true, "instructionReference": format!("0x{:04X}", addr) })));
+                },
+                (_, Err(err)) => results.push(json!({ "verified": false, "message": err })),
+                _ => results.push(json!({ "verified": false, "message": t!("dap.no_code") })),
+            }
+        }
+        (numbers, results)
+    }
+
+    // address of the first instruction generated for the line of the source file, by the line table
+    fn line_addr(&self, path: &str, line: u64) -> Option<usize> {
+        let table = self.vm.line_table();
+        let file_name = Path::new(path).file_name();
+        let file = table.files.iter().position(|file| file == path || Path::new(file).file_name() == file_name)?;
+        table.entries.iter().find(|entry| entry.file as usize == file && entry.line as u64 == line).map(|entry| entry.addr as usize)
+    }
+
+    fn stack_trace(&self) -> Value {
+        let execution = match self.debugger.execution() {
+            Some(val) => val,
+            None => return json!({ "stackFrames": [], "totalFrames": 0 }),
+        };
+        let frames = execution.backtrace();
+        let mut pc = execution.pc();
+        let mut result = vec![];
+        for (i, frame) in frames.iter().enumerate() {
+            result.push(self.locate(pc, json!({
+                "id": i,
+                "name": self.vm.proc_label(frame.procedure),
+                "column": 0,
+                "instructionPointerReference": format!("0x{:04X}", pc),
+            })));
+            // the caller continues at the return address, which is where it is in its code now
+            pc = frame.return_addr.unwrap_or_default();
+        }
+        json!({ "stackFrames": result, "totalFrames": frames.len() })
+    }
+
+    // the values of a frame: its variables and operand stack, up to the call information of the next call
+    fn variables(&self, frame_id: usize) -> Value {
+        let execution = match self.debugger.execution() {
+            Some(val) => val,
+            None => return json!({ "variables": [] }),
+        };
+        let frames = execution.backtrace();
+        let frame = match frames.get(frame_id) {
+            Some(val) => val,
+            None => return json!({ "variables": [] }),
+        };
+        let size = self.vm.data_size();
+        let values = execution.stack_values();
+        let end = match frame_id.checked_sub(1) {
+            Some(inner) => frames[inner].frame_ptr.saturating_sub(CALL_FRAME_SIZE),
+            None => values.len() * size,
+        };
+        let variables: Vec<Value> = (frame.frame_ptr..end).step_by(size).map(|addr| {
+            let offset = addr - frame.frame_ptr;
+            let name = match u16::try_from(offset).ok().and_then(|offset| self.vm.symbols().variable(frame.procedure as u16, offset)) {
+                Some(name) => format!("{:04X} ({})", offset, name),
+                None => format!("{:04X}", offset),
+            };
+            json!({ "name": name, "value": values.get(addr / size).map(|val| val.to_string()).unwrap_or_default(), "variablesReference": 0, "memoryReference": format!("0x{:04X}", addr) })
+        }).collect();
+        json!({ "variables": variables })
+    }
+
+    // handle one request, false once the session ends
+    fn handle(&mut self, request: &Value) -> bool {
+        let args = &request["arguments"];
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                self.respond(request, Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsConditionalBreakpoints": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsStepBack": true,
+                    "supportsTerminateRequest": true,
+                })));
+                self.event("initialized", json!({}));
+            },
+            "launch" => {
+                let inputs = args["inputs"].as_array().map(|inputs| inputs.iter().filter_map(Value::as_i64).collect()).unwrap_or_default();
+                self.debugger.replace_input(inputs);
+                self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or_default();
+                match self.debugger.restart() {
+                    true => self.respond(request, Ok(json!({}))),
+                    false => self.respond(request, Err(t!("dap.cannot_start").to_string())),
+                }
+            },
+            "setBreakpoints" => {
+                let path = args["source"]["path"].as_str().unwrap_or_default().to_string();
+                let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+                let requested = requested.iter().map(|breakpoint| (breakpoint["line"].as_u64().and_then(|line| self.line_addr(&path, line)), breakpoint["condition"].as_str())).collect();
+                let previous = self.source_breakpoints.remove(&path).unwrap_or_default();
+                let (numbers, results) = self.replace_breakpoints(&previous, requested);
+                self.source_breakpoints.insert(path, numbers);
+                self.respond(request, Ok(json!({ "breakpoints": results })));
+            },
+            "setInstructionBreakpoints" => {
+                let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+                let requested = requested.iter().map(|breakpoint| {
+                    let addr = breakpoint["instructionReference"].as_str().and_then(|reference| usize::from_str_radix(reference.trim_start_matches("0x"), 16).ok());
+                    let offset = breakpoint["offset"].as_i64().unwrap_or_default();
+                    (addr.and_then(|addr| addr.checked_add_signed(offset as isize)), breakpoint["condition"].as_str())
+                }).collect();
+                let previous = std::mem::take(&mut self.instruction_breakpoints);
+                let (numbers, results) = self.replace_breakpoints(&previous, requested);
+                self.instruction_breakpoints = numbers;
+                self.respond(request, Ok(json!({ "breakpoints": results })));
+            },
+            "configurationDone" => {
+                self.respond(request, Ok(json!({})));
+                match self.debugger.execution() {
+                    // launched with stopOnEntry, or a breakpoint is on the first instruction
+                    Some(_) if self.stop_on_entry || self.debugger.at_stop() => {
+                        self.event("stopped", json!({ "reason": "entry", "threadId": THREAD_ID, "allThreadsStopped": true }));
+                    },
+                    Some(_) => self.run("breakpoint", |_| true),
+                    None => self.event("terminated", json!({})),
+                }
+            },
+            "threads" => self.respond(request, Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }))),
+            "stackTrace" => {
+                let body = self.stack_trace();
+                self.respond(request, Ok(body));
+            },
+            "scopes" => {
+                let frame_id = args["frameId"].as_u64().unwrap_or_default();
+                // variable references are frame ID + 1, as 0 means no variables
+                self.respond(request, Ok(json!({ "scopes": [{ "name": t!("dap.frame_scope"), "variablesReference": frame_id + 1, "expensive": false }] })));
+            },
+            "variables" => {
+                let body = match args["variablesReference"].as_u64().and_then(|reference| reference.checked_sub(1)) {
+                    Some(frame_id) => self.variables(frame_id as usize),
+                    None => json!({ "variables": [] }),
+                };
+                self.respond(request, Ok(body));
+            },
+            "evaluate" => {
+                let result = match (Expr::parse(args["expression"].as_str().unwrap_or_default()), self.debugger.execution()) {
+                    (Err(err), _) => Err(err),
+                    (Ok(_), None) => Err(t!("debugger.not_running").to_string()),
+                    (Ok(expr), Some(execution)) => expr.evaluate(execution).map(|val| json!({ "result": val.to_string(), "variablesReference": 0 })),
+                };
+                self.respond(request, result);
+            },
+            "continue" => {
+                self.respond(request, Ok(json!({ "allThreadsContinued": true })));
+                self.run("breakpoint", |_| true);
+            },
+            "stepIn" => {
+                self.respond(request, Ok(json!({})));
+                self.run("step", |_| false);
+            },
+            "next" => {
+                // calls are run completely, until the execution is back in the frame it started in
+                self.respond(request, Ok(json!({})));
+                let depth = self.depth();
+                self.run("step", move |debugger| debugger.execution().is_some_and(|execution| execution.call_depth() > depth));
+            },
+            "stepOut" => {
+                self.respond(request, Ok(json!({})));
+                let depth = self.depth();
+                self.run("step", move |debugger| debugger.execution().is_some_and(|execution| execution.call_depth() >= depth));
+            },
+            "stepBack" => {
+                let result = self.debugger.reverse_step();
+                self.respond(request, if result { Ok(json!({})) } else { Err(t!("debugger.at_start").to_string()) });
+                if result {
+                    self.event("stopped", json!({ "reason": "step", "threadId": THREAD_ID, "allThreadsStopped": true }));
+                }
+            },
+            "reverseContinue" => {
+                let result = self.debugger.reverse_continue();
+                self.respond(request, if result { Ok(json!({})) } else { Err(t!("debugger.at_start").to_string()) });
+                if result {
+                    let reason = if self.debugger.at_stop() { "breakpoint" } else { "entry" };
+                    self.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }));
+                }
+            },
+            // instructions run until the next stop in one go, so there is nothing to pause
+            "pause" => self.respond(request, Ok(json!({}))),
+            "disconnect" | "terminate" => {
+                self.respond(request, Ok(json!({})));
+                return false;
+            },
+            command => self.respond(request, Err(t!("dap.unsupported", command = command).to_string())),
+        }
+        true
+    }
+}
+
+// for --dap: serve requests until the editor disconnects or stdin ends
+pub fn run(vm: &PL0VM) {
+    let mut server = Server { vm, debugger: Debugger::new(vm), seq: 1, source_breakpoints: HashMap::new(), instruction_breakpoints: vec![], stop_on_entry: false };
+    while let Some(request) = read_message() {
+        if !server.handle(&request) {
+            break;
+        }
+    }
+}
@@ -0,0 +1,615 @@
+use crate::decoder::decode_instruction;
+use crate::expr::Expr;
+use crate::opcodes::OpCode;
+use crate::pl0_vm::{Execution, StackWrite, Step, PL0VM};
+use rust_i18n::t;
+use std::io::{sink, stdin, stdout, BufRead, Write};
+
+// instructions between two snapshots of the execution, going back replays at most this many
+const SNAPSHOT_INTERVAL: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    // address of the instruction execution stops in front of
+    pub addr: usize,
+    // set for breakpoints on the entry of a procedure, whose EntryProc is at addr
+    pub procedure: Option<usize>,
+    // disabled breakpoints are kept, but don't stop execution
+    pub enabled: bool,
+    // only stop if the expression isn't 0 (or can't be evaluated)
+    pub condition: Option<Expr>,
+}
+
+// what a watchpoint watches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchTarget {
+    // absolute address in the stack
+    Stack(usize),
+    // variable at an address in the frame of a procedure, wherever that frame currently is
+    Variable(usize, i64),
+}
+
+impl WatchTarget {
+    // 0x<hex> for an absolute stack address, or var(<procedure ID>, <address>) like in conditions
+    pub fn parse(text: &str) -> Option<WatchTarget> {
+        if let Some(hex) = text.strip_prefix("0x") {
+            return usize::from_str_radix(hex, 16).ok().map(WatchTarget::Stack);
+        }
+        match Expr::parse(text).ok()? {
+            Expr::Variable(proc_id, addr) => match (*proc_id, *addr) {
+                (Expr::Number(proc_id), Expr::Number(addr)) => Some(WatchTarget::Variable(usize::try_from(proc_id).ok()?, addr)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// Runs a program instruction by instruction and stops in front of breakpoints, on top of Execution::step.
+// It can also go back: snapshots of the execution are kept every SNAPSHOT_INTERVAL instructions, earlier
+// states are reached by replaying from the snapshot before them, with the same inputs and without output.
+pub struct Debugger<'a> {
+    vm: &'a PL0VM,
+    // None before the program was started with restart
+    execution: Option<Execution<'a>>,
+    // breakpoints are numbered by their position, starting at 1
+    breakpoints: Vec<Breakpoint>,
+    // execution stops after an instruction wrote to one of them, numbered like breakpoints
+    watchpoints: Vec<WatchTarget>,
+    // execution stops in front of every instruction with one of these op codes
+    op_breakpoints: Vec<OpCode>,
+    // instructions run since the start
+    steps: usize,
+    // snapshot i is the execution before instruction i * SNAPSHOT_INTERVAL
+    snapshots: Vec<Execution<'a>>,
+    // numbers the program reads instead of stdin, if set with replace_input
+    inputs: Option<Vec<i64>>,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(vm: &'a PL0VM) -> Debugger<'a> {
+        Debugger { vm, execution: None, breakpoints: vec![], watchpoints: vec![], op_breakpoints: vec![], steps: 0, snapshots: vec![], inputs: None }
+    }
+    // debug an execution that was started elsewhere, e.g. restored from a core dump,
+    // a restart runs the program from the beginning with the numbers it has read so far
+    pub fn attach(vm: &'a PL0VM, execution: Execution<'a>) -> Debugger<'a> {
+        let inputs = execution.inputs().to_vec();
+        Debugger { execution: Some(execution), inputs: Some(inputs), ..Debugger::new(vm) }
+    }
+
+    pub fn execution(&self) -> Option<&Execution<'a>> {
+        self.execution.as_ref()
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+    // add an enabled breakpoint in front of the instruction at addr and return its number,
+    // an existing breakpoint at the same address is enabled instead
+    pub fn add_breakpoint(&mut self, addr: usize) -> usize {
+        self.insert_breakpoint(Breakpoint { addr, procedure: None, enabled: true, condition: None })
+    }
+    // break on the EntryProc of the procedure, None if there is no procedure with the ID
+    pub fn add_procedure_breakpoint(&mut self, proc_id: usize) -> Option<usize> {
+        let addr = self.vm.procedure_layout().ok()?.iter().find(|(id, _)| *id == proc_id).map(|(_, range)| range.start)?;
+        Some(self.insert_breakpoint(Breakpoint { addr, procedure: Some(proc_id), enabled: true, condition: None }))
+    }
+    fn insert_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        match self.breakpoints.iter().position(|other| other.addr == breakpoint.addr) {
+            Some(i) => {
+                self.breakpoints[i].enabled = true;
+                i + 1
+            },
+            None => {
+                self.breakpoints.push(breakpoint);
+                self.breakpoints.len()
+            },
+        }
+    }
+    // enable or disable the breakpoint with the number, false if it doesn't exist
+    pub fn set_breakpoint_enabled(&mut self, number: usize, enabled: bool) -> bool {
+        match number.checked_sub(1).and_then(|i| self.breakpoints.get_mut(i)) {
+            Some(breakpoint) => {
+                breakpoint.enabled = enabled;
+                true
+            },
+            None => false,
+        }
+    }
+    // None removes the condition, so the breakpoint always stops - false if the breakpoint doesn't exist
+    pub fn set_breakpoint_condition(&mut self, number: usize, condition: Option<Expr>) -> bool {
+        match number.checked_sub(1).and_then(|i| self.breakpoints.get_mut(i)) {
+            Some(breakpoint) => {
+                breakpoint.condition = condition;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn op_breakpoints(&self) -> &[OpCode] {
+        &self.op_breakpoints
+    }
+    // stop in front of every instruction with the op code, false if it already does
+    pub fn add_op_breakpoint(&mut self, op: OpCode) -> bool {
+        if self.op_breakpoints.contains(&op) {
+            return false;
+        }
+        self.op_breakpoints.push(op);
+        true
+    }
+    // false if there was no breakpoint on the op code
+    pub fn remove_op_breakpoint(&mut self, op: OpCode) -> bool {
+        let len = self.op_breakpoints.len();
+        self.op_breakpoints.retain(|other| *other != op);
+        self.op_breakpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[WatchTarget] {
+        &self.watchpoints
+    }
+    // watch the target and return the number of the watchpoint, the same target is only watched once
+    pub fn add_watchpoint(&mut self, target: WatchTarget) -> usize {
+        match self.watchpoints.iter().position(|other| *other == target) {
+            Some(i) => i + 1,
+            None => {
+                self.watchpoints.push(target);
+                self.watchpoints.len()
+            },
+        }
+    }
+
+    // start the program again from the beginning, false if it can't run (the reason is printed)
+    pub fn restart(&mut self) -> bool {
+        self.execution = self.vm.start();
+        if let (Some(execution), Some(inputs)) = (self.execution.as_mut(), &self.inputs) {
+            execution.feed_inputs(inputs.iter().copied());
+            execution.close_input();
+        }
+        self.steps = 0;
+        self.snapshots.clear();
+        self.execution.is_some()
+    }
+
+    // queue a number for the program to read, before stdin
+    pub fn feed_input(&mut self, input: i64) {
+        if let Some(execution) = self.execution.as_mut() {
+            execution.feed_inputs([input]);
+        }
+    }
+
+    // from the next restart on, the program reads these numbers instead of stdin, reading more is a runtime error
+    pub fn replace_input(&mut self, inputs: Vec<i64>) {
+        self.inputs = Some(inputs);
+    }
+
+    // number of instructions run since the start
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    // run one instruction, starting the program first if necessary
+    pub fn step(&mut self, out: &mut dyn Write) -> Step {
+        if self.execution.is_none() && !self.restart() {
+            return Step::Error;
+        }
+        let execution = self.execution.as_mut().expect("started above");
+        if execution.state() != Step::Running {
+            return execution.state();
+        }
+        // after going back, the snapshots up to the latest instruction ever run still exist
+        if self.snapshots.len() * SNAPSHOT_INTERVAL == self.steps {
+            self.snapshots.push(execution.clone());
+        }
+        self.steps += 1;
+        execution.step(out)
+    }
+
+    // run until the next breakpoint or watchpoint (Running is returned then) or until the program ends,
+    // at least one instruction runs, so continuing from a breakpoint doesn't stop immediately
+    pub fn resume(&mut self, out: &mut dyn Write) -> Step {
+        self.run_while(out, |_| true)
+    }
+
+    // step over: run one instruction, a call is run completely (stopping at breakpoints in it)
+    pub fn next(&mut self, out: &mut dyn Write) -> Step {
+        let depth = self.call_depth();
+        self.run_while(out, |execution| execution.call_depth() > depth)
+    }
+
+    // step out: run until the current procedure returned to its caller, in main until the program ends
+    pub fn finish(&mut self, out: &mut dyn Write) -> Step {
+        let depth = self.call_depth();
+        self.run_while(out, |execution| execution.call_depth() >= depth)
+    }
+
+    fn call_depth(&self) -> usize {
+        self.execution.as_ref().map(|execution| execution.call_depth()).unwrap_or_default()
+    }
+
+    // run instructions as long as keep_going says so, at least one, until a breakpoint or watchpoint
+    // stops execution (the reason is printed) or the program ends
+    fn run_while(&mut self, out: &mut dyn Write, keep_going: impl Fn(&Execution) -> bool) -> Step {
+        loop {
+            let state = self.step(out);
+            let execution = match &self.execution {
+                Some(val) if state == Step::Running => val,
+                _ => return state,
+            };
+            if self.stops_at(execution, true) || !keep_going(execution) {
+                return state;
+            }
+        }
+    }
+
+    // Go back to the state after the given number of instructions, false if the program wasn't started
+    // or hasn't run that far. Inputs read after that point are queued again, so going forward repeats them.
+    pub fn go_to_step(&mut self, target: usize) -> bool {
+        let (execution, snapshot) = match (&self.execution, self.snapshots.get(target / SNAPSHOT_INTERVAL)) {
+            (Some(execution), Some(snapshot)) if target <= self.steps => (execution, snapshot),
+            _ => return false,
+        };
+        let mut replay = snapshot.clone();
+        replay.feed_inputs(execution.inputs()[snapshot.inputs().len()..].to_vec());
+        for _ in (target / SNAPSHOT_INTERVAL * SNAPSHOT_INTERVAL)..target {
+            replay.step(&mut sink());
+        }
+        self.execution = Some(replay);
+        self.steps = target;
+        true
+    }
+
+    // undo the last instruction, false if there is none
+    pub fn reverse_step(&mut self) -> bool {
+        self.steps > 0 && self.go_to_step(self.steps - 1)
+    }
+
+    // go back to the latest earlier state where a breakpoint or watchpoint stopped (or would have stopped)
+    // execution, or to the start if there is none, false if there is nothing to go back to
+    pub fn reverse_continue(&mut self) -> bool {
+        let execution = match &self.execution {
+            Some(val) if self.steps > 0 => val,
+            _ => return false,
+        };
+        // the snapshots are searched from the latest one, replaying each up to the next one
+        for (i, snapshot) in self.snapshots.iter().enumerate().take((self.steps - 1) / SNAPSHOT_INTERVAL + 1).rev() {
+            let start = i * SNAPSHOT_INTERVAL;
+            let mut replay = snapshot.clone();
+            replay.feed_inputs(execution.inputs()[snapshot.inputs().len()..].to_vec());
+            let mut found = None;
+            for pos in start..self.steps.min(start + SNAPSHOT_INTERVAL) {
+                if pos > start {
+                    replay.step(&mut sink());
+                }
+                if pos > 0 && self.stops_at(&replay, false) {
+                    found = Some(pos);
+                }
+            }
+            if let Some(pos) = found {
+                self.go_to_step(pos);
+                let execution = self.execution.as_ref().expect("replayed above");
+                self.stops_at(execution, true);
+                return true;
+            }
+        }
+        self.go_to_step(0)
+    }
+
+    // whether a breakpoint or watchpoint stops the execution where it is now, without printing anything
+    pub fn at_stop(&self) -> bool {
+        self.execution.as_ref().is_some_and(|execution| self.stops_at(execution, false))
+    }
+
+    // whether a breakpoint or watchpoint stops the execution in its current state, report prints why
+    fn stops_at(&self, execution: &Execution, report: bool) -> bool {
+        // both are checked, so both are reported
+        let watchpoint = self.hit_watchpoint(execution, report);
+        self.at_breakpoint(execution, report) || self.at_op_breakpoint(execution) || watchpoint
+    }
+
+    fn at_op_breakpoint(&self, execution: &Execution) -> bool {
+        !self.op_breakpoints.is_empty() && decode_instruction(self.vm.program(), execution.pc()).is_ok_and(|instr| self.op_breakpoints.contains(&instr.op))
+    }
+
+    // whether an enabled breakpoint at the program counter stops execution, conditions that can't be
+    // evaluated stop it too (with the reason printed), so the problem can be seen
+    fn at_breakpoint(&self, execution: &Execution, report: bool) -> bool {
+        self.breakpoints.iter().filter(|breakpoint| breakpoint.enabled && breakpoint.addr == execution.pc()).any(|breakpoint| {
+            match breakpoint.condition.as_ref().map(|condition| condition.evaluate(execution)) {
+                None => true,
+                Some(Ok(val)) => val != 0,
+                Some(Err(err)) => {
+                    if report { println!("{}", t!("debugger.condition_error", err = err)); }
+                    true
+                },
+            }
+        })
+    }
+
+    // whether the last instruction wrote to a watched address, which is printed with the old and new value
+    fn hit_watchpoint(&self, execution: &Execution, report: bool) -> bool {
+        let StackWrite { addr, old, new } = match execution.last_write() {
+            Some(val) => val,
+            None => return false,
+        };
+        let number = self.watchpoints.iter().position(|target| match *target {
+            WatchTarget::Stack(watched) => watched == addr,
+            WatchTarget::Variable(proc_id, var) => execution.variable_addr(proc_id, var) == Some(addr),
+        });
+        match number {
+            Some(i) => {
+                if report { println!("{}", t!("debugger.watchpoint_hit", number = i + 1, addr = addr:{:04X}, old = old, new = new)); }
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Add a breakpoint on a target: an address of an instruction as hex (starting with 0x), or the
+    // EntryProc of a procedure given by its ID or its name from the debug symbols. Returns its number.
+    pub fn break_at(&mut self, target: &str) -> Option<usize> {
+        if let Some(hex) = target.strip_prefix("0x") {
+            let addr = usize::from_str_radix(hex, 16).ok()?;
+            self.vm.decode_code().ok()?.iter().find(|instr| instr.addr == addr)?;
+            return Some(self.add_breakpoint(addr));
+        }
+        let proc_id = match target.parse::<usize>() {
+            Ok(id) => id,
+            Err(_) => self.vm.symbols().procedures.iter().find(|(_, name)| *name == target).map(|(&id, _)| id as usize)?,
+        };
+        self.add_procedure_breakpoint(proc_id)
+    }
+
+    fn print_location(&self) {
+        let execution = match &self.execution {
+            Some(val) => val,
+            None => return,
+        };
+        match execution.state() {
+            Step::Running => match decode_instruction(self.vm.program(), execution.pc()) {
+                Ok(instr) => println!("{}", t!("debugger.stopped", instruction = self.vm.instruction_text(&instr))),
+                Err(err) => println!("{}", err),
+            },
+            Step::Finished => println!("{}", t!("debugger.finished")),
+            Step::Error => match execution.fault_addr().map(|addr| decode_instruction(self.vm.program(), addr)) {
+                Some(Ok(instr)) => println!("{}", t!("debugger.error_at", instruction = self.vm.instruction_text(&instr))),
+                _ => println!("{}", t!("debugger.error")),
+            },
+        }
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() && self.op_breakpoints.is_empty() {
+            return println!("{}", t!("debugger.no_breakpoints"));
+        }
+        for op in &self.op_breakpoints {
+            println!("{}", t!("debugger.op_breakpoint", op = op));
+        }
+        for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+            let procedure = match breakpoint.procedure {
+                Some(proc_id) => format!(" {}", t!("debugger.procedure", id = self.vm.proc_label(proc_id))),
+                None => String::new(),
+            };
+            let condition = match &breakpoint.condition {
+                Some(condition) => format!(" if {condition}"),
+                None => String::new(),
+            };
+            let disabled = if breakpoint.enabled { String::new() } else { format!(" {}", t!("debugger.disabled")) };
+            println!("{}: {:04X}{}{}{}", i + 1, breakpoint.addr, procedure, condition, disabled);
+        }
+    }
+
+    fn print_watchpoints(&self) {
+        if self.watchpoints.is_empty() {
+            return println!("{}", t!("debugger.no_watchpoints"));
+        }
+        for (i, target) in self.watchpoints.iter().enumerate() {
+            match *target {
+                WatchTarget::Stack(addr) => println!("{}: {:04X}", i + 1, addr),
+                WatchTarget::Variable(proc_id, addr) => println!("{}: {}", i + 1, t!("debugger.watched_variable", addr = format!("{:04X}{}", addr, self.vm.var_suffix(proc_id, addr as i32)), id = self.vm.proc_label(proc_id))),
+            }
+        }
+    }
+
+    // cells values of the architecture in front of and after the frame pointer, as bytes in hex and as numbers,
+    // with a line where each active frame starts
+    fn print_dump(&self, cells: usize) {
+        let execution = match &self.execution {
+            Some(val) => val,
+            None => return println!("{}", t!("debugger.not_running")),
+        };
+        let size = self.vm.data_size();
+        let fp = execution.frame_pointer();
+        let start = fp.saturating_sub(cells * size) / size * size;
+        let end = execution.stack_len().min(fp + cells * size);
+        let frames = execution.backtrace();
+        let values = execution.stack_values();
+        for addr in (start..end).step_by(size) {
+            if let Some(frame) = frames.iter().find(|frame| frame.frame_ptr == addr) {
+                println!("-- {} --", t!("debugger.frame", id = self.vm.proc_label(frame.procedure), fp = addr:{:04X}));
+            }
+            let val = match values.get(addr / size) {
+                Some(val) => *val,
+                None => break,
+            };
+            // the bytes of the value as the architecture stores it
+            let hex = val.to_le_bytes()[..size].iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ");
+            let marker = if addr == fp { " <<< fp" } else { "" };
+            println!("{:04X}: {}  {}{}", addr, hex, val, marker);
+        }
+    }
+
+    fn print_backtrace(&self) {
+        let execution = match &self.execution {
+            Some(val) => val,
+            None => return println!("{}", t!("debugger.not_running")),
+        };
+        for (i, frame) in execution.backtrace().iter().enumerate() {
+            let returns = match frame.return_addr {
+                Some(addr) => format!(", {}", t!("debugger.returns_to", addr = addr:{:04X})),
+                None => String::new(),
+            };
+            println!("#{} {}{}", i, t!("debugger.frame", id = self.vm.proc_label(frame.procedure), fp = frame.frame_ptr:{:04X}), returns);
+        }
+    }
+
+    fn print_stack(&self) {
+        let execution = match &self.execution {
+            Some(val) => val,
+            None => return println!("{}", t!("debugger.not_running")),
+        };
+        let size = self.vm.data_size();
+        for (i, val) in execution.stack_values().iter().enumerate() {
+            let marker = if i * size == execution.frame_pointer() { " <<< fp" } else { "" };
+            println!("{:04X}: {}{}", i * size, val, marker);
+        }
+    }
+}
+
+// the command line of the debugger, for --debugger
+pub fn run(vm: &PL0VM) {
+    println!("{}", t!("debugger.intro"));
+    prompt(Debugger::new(vm));
+}
+
+// for --inspect-core: the prompt starts where the program stopped
+pub fn run_attached<'a>(vm: &'a PL0VM, execution: Execution<'a>) {
+    let debugger = Debugger::attach(vm, execution);
+    println!("{}", t!("debugger.intro"));
+    debugger.print_location();
+    prompt(debugger);
+}
+
+fn prompt(mut debugger: Debugger) {
+    loop {
+        print!("(pl0) ");
+        stdout().flush().expect("Could not write to stdout");
+        let mut line = String::new();
+        // end of input quits, like the quit command
+        if stdin().lock().read_line(&mut line).unwrap_or_default() == 0 || !command(&mut debugger, &line) {
+            break;
+        }
+    }
+}
+
+// for --command-file and --ex: run the commands like they were entered, then quit,
+// the commands are printed after the prompt so the output reads like an interactive session
+pub fn run_script(vm: &PL0VM, commands: &[String]) {
+    let mut debugger = Debugger::new(vm);
+    for line in commands {
+        println!("(pl0) {}", line);
+        if !command(&mut debugger, line) {
+            break;
+        }
+    }
+}
+
+// run one command line, false if the debugger should quit
+fn command(debugger: &mut Debugger, line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next()) {
+        (None, _) => {},
+        (Some("break" | "b"), Some(target)) => {
+            // break <target> if <condition>
+            let condition = match words.next() {
+                Some("if") => match Expr::parse(&words.collect::<Vec<_>>().join(" ")) {
+                    Ok(condition) => Some(condition),
+                    Err(err) => {
+                        println!("{}", err);
+                        return true;
+                    },
+                },
+                _ => None,
+            };
+            match debugger.break_at(target) {
+                Some(number) => {
+                    debugger.set_breakpoint_condition(number, condition);
+                    let addr = debugger.breakpoints()[number - 1].addr;
+                    println!("{}", t!("debugger.breakpoint_set", number = number, addr = addr:{:04X}));
+                },
+                None => println!("{}", t!("debugger.invalid_target", target = target)),
+            }
+        },
+        (Some("breakpoints"), _) => debugger.print_breakpoints(),
+        (Some(command @ ("break-op" | "delete-op")), Some(name)) => match OpCode::from_name(name) {
+            Some(op) if command == "break-op" => {
+                debugger.add_op_breakpoint(op);
+                println!("{}", t!("debugger.op_breakpoint_set", op = op));
+            },
+            Some(op) => if !debugger.remove_op_breakpoint(op) {
+                println!("{}", t!("debugger.no_op_breakpoint", op = op));
+            },
+            None => println!("{}", t!("debugger.invalid_op", op = name)),
+        },
+        (Some("watch" | "w"), Some(first)) => {
+            // var(0, 2) may be split into several words
+            let text = std::iter::once(first).chain(words).collect::<Vec<_>>().join(" ");
+            match WatchTarget::parse(&text) {
+                Some(target) => println!("{}", t!("debugger.watchpoint_set", number = debugger.add_watchpoint(target))),
+                None => println!("{}", t!("debugger.invalid_watch_target", target = text)),
+            }
+        },
+        (Some("watchpoints"), _) => debugger.print_watchpoints(),
+        (Some(command @ ("enable" | "disable")), number) => {
+            match number.and_then(|number| number.parse().ok()) {
+                Some(number) if debugger.set_breakpoint_enabled(number, command == "enable") => {},
+                _ => println!("{}", t!("debugger.invalid_breakpoint", number = number.unwrap_or_default())),
+            }
+        },
+        (Some("run" | "r"), _) => {
+            if debugger.restart() {
+                debugger.resume(&mut stdout());
+                debugger.print_location();
+            }
+        },
+        (Some("step" | "s"), _) => {
+            debugger.step(&mut stdout());
+            debugger.print_location();
+        },
+        (Some("next" | "n"), _) => {
+            debugger.next(&mut stdout());
+            debugger.print_location();
+        },
+        (Some("finish" | "f"), _) => {
+            debugger.finish(&mut stdout());
+            debugger.print_location();
+        },
+        (Some("continue" | "c"), _) => match debugger.execution() {
+            Some(_) => {
+                debugger.resume(&mut stdout());
+                debugger.print_location();
+            },
+            None => println!("{}", t!("debugger.not_running")),
+        },
+        (Some("reverse-step" | "rs"), _) => match debugger.reverse_step() {
+            true => debugger.print_location(),
+            false => println!("{}", t!("debugger.at_start")),
+        },
+        (Some("reverse-continue" | "rc"), _) => match debugger.reverse_continue() {
+            true => debugger.print_location(),
+            false => println!("{}", t!("debugger.at_start")),
+        },
+        (Some("stack"), _) => debugger.print_stack(),
+        (Some("dump" | "x"), cells) => match cells.map(|cells| cells.parse()) {
+            None => debugger.print_dump(8),
+            Some(Ok(cells)) => debugger.print_dump(cells),
+            Some(Err(_)) => println!("{}", t!("debugger.invalid_cells", cells = cells.unwrap_or_default())),
+        },
+        (Some("backtrace" | "bt"), _) => debugger.print_backtrace(),
+        (Some("print" | "p"), Some(first)) => {
+            let text = std::iter::once(first).chain(words).collect::<Vec<_>>().join(" ");
+            match (Expr::parse(&text), debugger.execution()) {
+                (Err(err), _) => println!("{}", err),
+                (Ok(_), None) => println!("{}", t!("debugger.not_running")),
+                (Ok(expr), Some(execution)) => match expr.evaluate(execution) {
+                    Ok(val) => println!("{} = {}", expr, val),
+                    Err(err) => println!("{}", err),
+                },
+            }
+        },
+        (Some("quit" | "q"), _) => return false,
+        (Some("help" | "h"), _) => println!("{}", t!("debugger.help")),
+        (Some(command), _) => println!("{}", t!("debugger.unknown_command", command = command)),
+    }
+    true
+}
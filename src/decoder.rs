@@ -0,0 +1,365 @@
+use crate::opcodes::OpCode;
+use crate::pl0_vm::{arg_size, ARG_SIZE, PL0VM};
+use rust_i18n::t;
+use std::ops::Range;
+
+// one decoded instruction of the code region
+#[derive(Debug, Clone)]
+pub(crate) struct Instruction {
+    pub addr: usize,
+    // including the op code byte and a Wide prefix
+    pub len: usize,
+    pub op: OpCode,
+    // the instruction has a Wide prefix, so its arguments are 4 bytes long
+    pub wide: bool,
+}
+
+// The structure of a loaded program, parsed once while loading and shared by the analyses and by starting an
+// execution, see PL0VM::program_image
+#[derive(Debug)]
+pub(crate) struct ProgramImage {
+    // ID and position of every procedure, in the order of the image
+    pub procedures: Vec<(usize, Range<usize>)>,
+    // all instructions of the procedures, in the order of their addresses
+    pub instructions: Vec<Instruction>,
+    // where the constants start
+    pub code_end: usize,
+    pub constants: Vec<i64>,
+}
+
+// an instruction prepared for running, with its arguments already read, see PL0VM::predecode
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Decoded {
+    pub addr: usize,
+    pub op: OpCode,
+    pub wide: bool,
+    pub len: usize,
+    // unused arguments are 0
+    pub args: [i32; 3],
+    // index of the instruction Jump and JumpIfFalse go to, or of the EntryProc CallProc goes to, None for other instructions
+    pub target: Option<usize>,
+    // this instruction and the ones after it as one, see Superinstruction
+    pub fused: Option<Superinstruction>,
+    // a CallProc or CallProcArgs right in front of a ReturnProc (or of a ReturnValue returning its result), outside of main: the callee takes over the frame of the caller
+    // instead of getting a new one, so tail recursion runs in constant stack space. Only if neither the callee
+    // nor anything it calls accesses the frame of the caller through a global variable, and the caller never takes the
+    // address of one of its own variables.
+    pub tail: bool,
+}
+
+// Sequences the compiler generates a lot, run by one handler instead of one dispatch per instruction.
+// Only Execution::run uses them, single steps run the instructions on their own. The instructions are still
+// part of the code, so jumping to the middle of a sequence works as usual.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Superinstruction {
+    // PushConstant c, StoreValue
+    StoreConstant { c: usize },
+    // PushConstant c, a binary operation: top of the stack op c
+    ConstantOp { c: usize, op: OpCode },
+    // PushValueLocalVar/PushValueMainVar addr, PushConstant c, a binary operation: variable op c
+    VariableConstantOp { main: bool, addr: usize, c: usize, op: OpCode },
+}
+
+impl Superinstruction {
+    // number of instructions it replaces
+    pub fn count(&self) -> usize {
+        match self {
+            Superinstruction::StoreConstant { .. } | Superinstruction::ConstantOp { .. } => 2,
+            Superinstruction::VariableConstantOp { .. } => 3,
+        }
+    }
+
+    // the superinstruction starting with the first of the instructions, if any
+    fn find(instrs: &[Decoded]) -> Option<Superinstruction> {
+        // negative arguments are left to the normal handlers, they report them
+        let arg = |instr: &Decoded| usize::try_from(instr.args[0]).ok();
+        match instrs {
+            [var, constant, op, ..] if matches!(var.op, OpCode::PushValueLocalVar | OpCode::PushValueMainVar)
+                && constant.op == OpCode::PushConstant && op.op.is_binary() =>
+                Some(Superinstruction::VariableConstantOp { main: var.op == OpCode::PushValueMainVar, addr: arg(var)?, c: arg(constant)?, op: op.op }),
+            [constant, store, ..] if constant.op == OpCode::PushConstant && store.op == OpCode::StoreValue =>
+                Some(Superinstruction::StoreConstant { c: arg(constant)? }),
+            [constant, op, ..] if constant.op == OpCode::PushConstant && op.op.is_binary() =>
+                Some(Superinstruction::ConstantOp { c: arg(constant)?, op: op.op }),
+            _ => None,
+        }
+    }
+}
+
+// the code of a program as it is run: all instructions next to each other, in the order of their addresses
+pub(crate) struct Code {
+    pub instrs: Vec<Decoded>,
+    // address => index of the instruction starting there, None for addresses inside of an instruction or the constants
+    index: Vec<Option<usize>>,
+}
+
+impl Code {
+    // index of the instruction at the address
+    pub fn at(&self, addr: usize) -> Option<usize> {
+        self.index.get(addr).copied().flatten()
+    }
+}
+
+impl Instruction {
+    // position of the argument with the given index
+    pub fn arg_addr(&self, index: usize) -> usize {
+        self.addr + 1 + self.wide as usize + index * arg_size(self.wide)
+    }
+}
+
+// decode the instruction starting at addr, including a Wide prefix
+pub(crate) fn decode_instruction(code: &[u8], addr: usize) -> Result<Instruction, String> {
+    let read_op = |pos: usize| -> Result<OpCode, String> {
+        let byte = match code.get(pos) {
+            Some(val) => *val,
+            None => return Err(truncated(code.len(), &t!("pl0.layout.instruction", addr = addr:{:04X}))),
+        };
+        OpCode::try_from(byte).map_err(|_| t!("pl0.unknown_opcode", op = byte:{:02X}).to_string())
+    };
+    let mut op = read_op(addr)?;
+    let wide = op == OpCode::Wide;
+    if wide {
+        op = read_op(addr + 1)?;
+        if op.arg_count() == 0 {
+            return Err(t!("pl0.error.invalid_wide", addr = addr:{:04X}).to_string());
+        }
+    }
+    let start = addr + wide as usize;
+    let len = match op {
+        OpCode::PutString => code.get(start + 1..).and_then(|rest| rest.iter().position(|&b| b == 0)).map(|len| 1 + len + 1),
+        _ => Some(1 + op.arg_count() * arg_size(wide)).filter(|len| start + len <= code.len()),
+    };
+    match len {
+        Some(len) => Ok(Instruction { addr, len: wide as usize + len, op, wide }),
+        None => Err(truncated(code.len(), &t!("pl0.layout.instruction", addr = addr:{:04X}))),
+    }
+}
+
+fn truncated(offset: usize, what: &str) -> String {
+    t!("pl0.layout.truncated", offset = offset:{:04X}, what = what).to_string()
+}
+
+impl PL0VM {
+    // Check the structure of the image before anything else reads it: the header and the start and length of every
+    // procedure. The declared lengths have to tile the code region: every procedure ends exactly where its last
+    // instruction ends and the next EntryProc appears (left over bytes after the last complete constant are only
+    // reported by the analyzer). The instructions and constants are read on the way, so nothing has to go over
+    // the image again.
+    pub(crate) fn parse_program(&self) -> Result<ProgramImage, String> {
+        let len = self.program.len();
+        let procedure_count = match self.read_arg(0) {
+            Some(val) => val,
+            None => return Err(truncated(len, &t!("pl0.layout.procedure_count"))),
+        };
+        if self.read_arg(ARG_SIZE).is_none() {
+            return Err(truncated(len, &t!("pl0.layout.arch")));
+        }
+        if procedure_count <= 0 {
+            return Err(t!("pl0.layout.no_procedures", count = procedure_count).to_string());
+        }
+
+        let mut seen = vec![false; procedure_count as usize];
+        let mut procedures = vec![];
+        let mut instructions = vec![];
+        let mut pc = 2 * ARG_SIZE;
+        for i in 0..procedure_count {
+            let entry = decode_instruction(&self.program, pc)?;
+            if entry.op != OpCode::EntryProc {
+                return Err(t!("pl0.layout.no_entry_proc", i = i, addr = pc:{:04X}).to_string());
+            }
+            let proc_len = self.read_operand(entry.arg_addr(0), entry.wide).expect("length checked while decoding");
+            let proc_id = self.read_operand(entry.arg_addr(1), entry.wide).expect("length checked while decoding");
+            match seen.get_mut(proc_id as usize) {
+                Some(seen) if proc_id >= 0 && !*seen => *seen = true,
+                _ => return Err(t!("pl0.layout.invalid_procedure_id", id = proc_id, addr = pc:{:04X}, count = procedure_count).to_string()),
+            }
+            if proc_len < entry.len as i32 {
+                return Err(t!("pl0.layout.invalid_procedure_length", id = proc_id, addr = pc:{:04X}, len = proc_len).to_string());
+            }
+            let end = pc + proc_len as usize;
+            if end > len {
+                return Err(truncated(len, &t!("pl0.layout.procedure", id = proc_id)));
+            }
+
+            let mut instr_pc = pc + entry.len;
+            instructions.push(entry);
+            while instr_pc < end {
+                let instr = decode_instruction(&self.program[..end], instr_pc).map_err(|_| match decode_instruction(&self.program, instr_pc) {
+                    Ok(_) => t!("pl0.layout.crosses_procedure_end", addr = instr_pc:{:04X}, id = proc_id, end = end:{:04X}).to_string(),
+                    Err(err) => err,
+                })?;
+                if instr.op == OpCode::EntryProc {
+                    return Err(t!("pl0.layout.entry_proc_inside", id = proc_id, end = end:{:04X}, addr = instr_pc:{:04X}).to_string());
+                }
+                instr_pc += instr.len;
+                instructions.push(instr);
+            }
+            procedures.push((proc_id as usize, pc..end));
+            pc = end;
+        }
+        // left over bytes after the last complete constant are ignored
+        let constants = (0..((len - pc) / self.data_size()))
+            .map(|i| self.read_data(pc + self.data_size() * i).expect("length checked above").i64())
+            .collect();
+        Ok(ProgramImage { procedures, instructions, code_end: pc, constants })
+    }
+
+    // parse the image again after it was changed
+    pub(crate) fn reparse(&mut self) {
+        self.layout = Some(self.parse_program());
+    }
+
+    // the structure of the program, parsed while loading - Err if it is invalid
+    pub(crate) fn program_image(&self) -> Result<&ProgramImage, String> {
+        match &self.layout {
+            Some(Ok(image)) => Ok(image),
+            Some(Err(err)) => Err(err.clone()),
+            None => Err(t!("pl0.invalid_file").to_string()),
+        }
+    }
+
+    // the ID and position of every procedure, in the order of the image
+    pub(crate) fn procedure_layout(&self) -> Result<&[(usize, Range<usize>)], String> {
+        self.program_image().map(|image| &image.procedures[..])
+    }
+
+    // all procedures of the program decoded, stopping in front of the constants
+    pub(crate) fn decode_code(&self) -> Result<&[Instruction], String> {
+        self.program_image().map(|image| &image.instructions[..])
+    }
+
+    // decode the code once before running it, so the instructions aren't parsed again every time they run
+    pub(crate) fn predecode(&self) -> Result<Code, String> {
+        let mut instrs = vec![];
+        let mut index = vec![None; self.program.len()];
+        for instr in self.decode_code()? {
+            let mut args = [0; 3];
+            for (i, arg) in args.iter_mut().enumerate().take(instr.op.arg_count()) {
+                *arg = self.read_operand(instr.arg_addr(i), instr.wide).expect("length checked while decoding");
+            }
+            index[instr.addr] = Some(instrs.len());
+            instrs.push(Decoded { addr: instr.addr, op: instr.op, wide: instr.wide, len: instr.len, args, target: None, fused: None, tail: false });
+        }
+        // procedure ID => index of its EntryProc
+        let mut entries = vec![None; self.read_arg(0).unwrap_or_default().max(0) as usize];
+        for (i, instr) in instrs.iter().enumerate().filter(|(_, instr)| instr.op == OpCode::EntryProc) {
+            entries[instr.args[1] as usize] = Some(i);
+        }
+        // resolved once, so running them doesn't calculate any addresses - the verifier already reported targets that don't exist
+        for instr in instrs.iter_mut().filter(|instr| matches!(instr.op, OpCode::Jump | OpCode::JumpIfFalse) || instr.op.is_call()) {
+            instr.target = match instr.op {
+                OpCode::CallProc | OpCode::CallProcArgs => usize::try_from(instr.args[0]).ok().and_then(|id| entries.get(id).copied().flatten()),
+                // jumps are relative to the end of the instruction
+                _ => (instr.addr + instr.len).checked_add_signed(instr.args[0] as isize).and_then(|addr| index.get(addr).copied().flatten()),
+            };
+            if instr.target.is_none() {
+                let problem = match instr.op {
+                    OpCode::CallProc | OpCode::CallProcArgs => t!("verifier.procedure", id = instr.args[0]),
+                    _ => t!("verifier.jump_target", offset = instr.args[0]),
+                };
+                return Err(format!("{:04X}: {} - {}", instr.addr, instr.op, problem));
+            }
+        }
+        if self.tail_calls {
+            self.mark_tail_calls(&mut instrs)?;
+        }
+        let fused: Vec<_> = (0..instrs.len()).map(|i| Superinstruction::find(&instrs[i..])).collect();
+        for (instr, fused) in instrs.iter_mut().zip(fused) {
+            instr.fused = fused;
+        }
+        Ok(Code { instrs, index })
+    }
+    fn mark_tail_calls(&self, instrs: &mut [Decoded]) -> Result<(), String> {
+        let graph = self.call_graph()?;
+        // procedure ID => IDs of the procedures whose frames it accesses through global variables
+        let mut globals = vec![vec![]; graph.calls.len()];
+        // procedure ID => whether it takes the address of one of its own variables, which may still be used after its
+        // frame was handed to the callee, be it on the stack, as parameter or stored somewhere
+        let mut own_addresses = vec![false; graph.calls.len()];
+        let mut proc_id = 0;
+        for instr in instrs.iter() {
+            match instr.op {
+                OpCode::EntryProc => proc_id = instr.args[1] as usize,
+                OpCode::PushValueGlobalVar | OpCode::PushAddressGlobalVar => globals[proc_id].push(instr.args[1]),
+                _ => {},
+            }
+            match instr.op {
+                OpCode::PushAddressLocalVar => own_addresses[proc_id] = true,
+                OpCode::PushAddressGlobalVar if instr.args[1] as usize == proc_id => own_addresses[proc_id] = true,
+                _ => {},
+            }
+        }
+        for i in 0..instrs.len().saturating_sub(1) {
+            match instrs[i].op {
+                OpCode::EntryProc => proc_id = instrs[i].args[1] as usize,
+                // returning the result of a call is a tail call too
+                op if op.is_call() && proc_id != 0 && !own_addresses[proc_id] && (instrs[i + 1].op == OpCode::ReturnProc
+                    || instrs[i + 1].op == OpCode::ReturnValue && self.returns_value(instrs[i].args[0])) => {
+                    let callee = instrs[i].args[0] as usize;
+                    let mut reached = graph.reachable_from(callee);
+                    reached[callee] = true;
+                    instrs[i].tail = !reached.iter().zip(&globals).any(|(reached, globals)| *reached && globals.contains(&(proc_id as i32)));
+                },
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opcodes::OpCode;
+    use crate::pl0_vm::PL0VM;
+
+    // procedure 1 calls procedure 2 with the code in front of the call, procedure 2 sets its variable at offset 4 to 5,
+    // stores 7 to the address in its first variable and outputs its variable at offset 4; main has one variable and calls procedure 1
+    fn program(caller: &[u8], call: &[u8]) -> PL0VM {
+        let mut bytes = vec![0x03, 0x00, 0x04, 0x00, 0x1A];
+        bytes.extend_from_slice(&(8 + caller.len() as i16 + call.len() as i16).to_le_bytes());
+        bytes.extend_from_slice(&[0x01, 0x00, 0x08, 0x00]);
+        bytes.extend_from_slice(caller);
+        bytes.extend_from_slice(call);
+        bytes.push(0x17);
+        bytes.extend_from_slice(&[
+            0x1A, 0x1A, 0x00, 0x02, 0x00, 0x08, 0x00,
+            0x03, 0x04, 0x00, 0x06, 0x00, 0x00, 0x07,
+            0x00, 0x00, 0x00, 0x06, 0x01, 0x00, 0x07,
+            0x00, 0x04, 0x00, 0x08, 0x17,
+            0x1A, 0x0B, 0x00, 0x00, 0x00, 0x04, 0x00, 0x16, 0x01, 0x00, 0x17,
+            0x05, 0x00, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00,
+        ]);
+        PL0VM::from_bytes(bytes)
+    }
+
+    fn tail_calls(vm: &PL0VM) -> Vec<bool> {
+        vm.predecode().unwrap().instrs.iter().filter(|instr| instr.op.is_call()).map(|instr| instr.tail).collect()
+    }
+
+    #[test]
+    fn marks_tail_calls() {
+        // the address procedure 2 stores to is that of main's frame, outside of the frames of the calls
+        let vm = program(&[0x04, 0x00, 0x00], &[0x46, 0x02, 0x00, 0x01, 0x00]);
+        assert_eq!(tail_calls(&vm), [true, false]);
+        assert_eq!(vm.execute_captured(), b"5\n");
+    }
+
+    #[test]
+    fn no_tail_call_with_address_of_own_variable() {
+        // the address of the variable at offset 4 of procedure 1 is passed on, with the frame handed to procedure 2
+        // it would be the one of its own variable at offset 4
+        let mut vm = program(&[0x03, 0x04, 0x00], &[0x46, 0x02, 0x00, 0x01, 0x00]);
+        assert_eq!(vm.execute_captured(), b"5\n");
+        assert_eq!(tail_calls(&vm), [false, false]);
+        vm.set_tail_calls(false);
+        assert_eq!(vm.execute_captured(), b"5\n");
+    }
+
+    #[test]
+    fn no_tail_call_with_address_on_stack() {
+        // PushAddressLocalVar 4, Pop in front of a plain CallProc 2: the address could have been kept anywhere
+        let vm = program(&[0x03, 0x04, 0x00, 0x1C, 0x04, 0x00, 0x00], &[0x46, 0x02, 0x00, 0x01, 0x00]);
+        assert_eq!(tail_calls(&vm), [false, false]);
+        assert!(vm.predecode().unwrap().instrs.iter().all(|instr| instr.op != OpCode::CallProc || !instr.tail));
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::{Arguments, Display};
+use std::io::{stderr, stdout, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+// Everything the VM prints itself - the analysis, reports like the profile and the coverage, the input prompt and the
+// messages of the command line - goes to stderr with diag! and diagln!, so stdout only has what the program printed
+// and can be compared with an expected output or piped on. --diagnostics=stdout puts them back on stdout.
+static TO_STDOUT: AtomicBool = AtomicBool::new(false);
+
+pub fn set_stdout(enabled: bool) {
+    TO_STDOUT.store(enabled, Ordering::Relaxed);
+}
+
+// whether the analysis listing has ANSI colors, --color
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    // if the diagnostics go to a terminal and NO_COLOR isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+static COLOR: AtomicU8 = AtomicU8::new(ColorMode::Auto as u8);
+
+pub fn set_color(mode: ColorMode) {
+    COLOR.store(mode as u8, Ordering::Relaxed);
+}
+
+fn colored() -> bool {
+    match COLOR.load(Ordering::Relaxed) {
+        mode if mode == ColorMode::Always as u8 => true,
+        mode if mode == ColorMode::Never as u8 => false,
+        _ => std::env::var_os("NO_COLOR").is_none()
+            && if TO_STDOUT.load(Ordering::Relaxed) { stdout().is_terminal() } else { stderr().is_terminal() },
+    }
+}
+
+// parts of the analysis listing with their own color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Part {
+    Mnemonic,
+    Operand,
+    String,
+    // procedure starts and jump labels
+    Header,
+    // notes like unreachable code
+    Note,
+}
+
+// the text in the color of the part, unchanged without colors - pad it before, the escape codes have a width too
+pub(crate) fn paint(text: impl Display, part: Part) -> String {
+    if !colored() {
+        return text.to_string();
+    }
+    let code = match part {
+        Part::Mnemonic => "36",
+        Part::Operand => "33",
+        Part::String => "32",
+        Part::Header => "1;35",
+        Part::Note => "2",
+    };
+    format!("\x1b[{code}m{text}\x1b[0m")
+}
+
+// used by diag! and diagln!
+pub fn write(args: Arguments) {
+    let result = if TO_STDOUT.load(Ordering::Relaxed) { stdout().write_fmt(args) } else { stderr().write_fmt(args) };
+    result.expect("Could not write diagnostics");
+}
+
+// print! for diagnostics
+#[macro_export]
+macro_rules! diag {
+    ($($arg:tt)*) => { $crate::diag::write(format_args!($($arg)*)) };
+}
+
+// println! for diagnostics
+#[macro_export]
+macro_rules! diagln {
+    () => { $crate::diag::write(format_args!("\n")) };
+    ($($arg:tt)*) => { $crate::diag::write(format_args!("{}\n", format_args!($($arg)*))) };
+}
@@ -0,0 +1,248 @@
+use crate::pl0_vm::Execution;
+use rust_i18n::t;
+use std::fmt::Display;
+
+// Expressions over the state of a running program, for conditional breakpoints of the debugger:
+//   numbers (decimal or hex with 0x), var(<procedure ID>, <address>), const(<index>),
+//   + - * / with the usual precedence, unary minus, parentheses, and one comparison (== != < > <= >=).
+// Comparisons result in 1 (true) or 0 (false), and like JumpIfFalse, every value except 0 counts as true.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    // value of the variable at an address in the frame of a procedure
+    Variable(Box<Expr>, Box<Expr>),
+    Constant(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, BinaryOp, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add, Subtract, Multiply, Divide,
+    Eq, NotEq, LT, GT, LTEq, GTEq,
+}
+
+impl BinaryOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+", BinaryOp::Subtract => "-", BinaryOp::Multiply => "*", BinaryOp::Divide => "/",
+            BinaryOp::Eq => "==", BinaryOp::NotEq => "!=", BinaryOp::LT => "<", BinaryOp::GT => ">", BinaryOp::LTEq => "<=", BinaryOp::GTEq => ">=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    // operators and parentheses
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 13] = ["==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "(", ")", ","];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let len = if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        } else {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            let word = &rest[..len];
+            if len == 0 {
+                return Err(t!("expr.unexpected", token = rest.chars().next().unwrap_or_default()).to_string());
+            }
+            let number = match word.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16).ok(),
+                None => word.parse().ok(),
+            };
+            match number {
+                Some(number) => tokens.push(Token::Number(number)),
+                None if word.starts_with(|c: char| c.is_ascii_digit()) => return Err(t!("expr.unexpected", token = word).to_string()),
+                None => tokens.push(Token::Name(word.to_string())),
+            }
+            len
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+// recursive descent parser, one function per precedence level
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_symbol(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Symbol(symbol)) => Some(symbol),
+            _ => None,
+        }
+    }
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        match self.peek_symbol() {
+            Some(found) if found == symbol => {
+                self.pos += 1;
+                Ok(())
+            },
+            _ => Err(self.unexpected()),
+        }
+    }
+    fn unexpected(&self) -> String {
+        match self.tokens.get(self.pos) {
+            Some(Token::Number(number)) => t!("expr.unexpected", token = number),
+            Some(Token::Name(name)) => t!("expr.unexpected", token = name),
+            Some(Token::Symbol(symbol)) => t!("expr.unexpected", token = symbol),
+            None => t!("expr.unexpected_end"),
+        }.to_string()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.sum()?;
+        let op = match self.peek_symbol() {
+            Some("==") => BinaryOp::Eq,
+            Some("!=") => BinaryOp::NotEq,
+            Some("<") => BinaryOp::LT,
+            Some(">") => BinaryOp::GT,
+            Some("<=") => BinaryOp::LTEq,
+            Some(">=") => BinaryOp::GTEq,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        Ok(Expr::Binary(Box::new(left), op, Box::new(self.sum()?)))
+    }
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut left = self.product()?;
+        loop {
+            let op = match self.peek_symbol() {
+                Some("+") => BinaryOp::Add,
+                Some("-") => BinaryOp::Subtract,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Binary(Box::new(left), op, Box::new(self.product()?));
+        }
+    }
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        loop {
+            let op = match self.peek_symbol() {
+                Some("*") => BinaryOp::Multiply,
+                Some("/") => BinaryOp::Divide,
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            left = Expr::Binary(Box::new(left), op, Box::new(self.unary()?));
+        }
+    }
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.peek_symbol() == Some("-") {
+            self.pos += 1;
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = match self.tokens.get(self.pos) {
+            Some(val) => val.clone(),
+            None => return Err(self.unexpected()),
+        };
+        match token {
+            Token::Number(number) => {
+                self.pos += 1;
+                Ok(Expr::Number(number))
+            },
+            Token::Symbol("(") => {
+                self.pos += 1;
+                let expr = self.comparison()?;
+                self.expect(")")?;
+                Ok(expr)
+            },
+            Token::Name(name) => {
+                self.pos += 1;
+                self.expect("(")?;
+                let expr = match name.as_str() {
+                    "var" => {
+                        let proc_id = self.comparison()?;
+                        self.expect(",")?;
+                        Expr::Variable(Box::new(proc_id), Box::new(self.comparison()?))
+                    },
+                    "const" => Expr::Constant(Box::new(self.comparison()?)),
+                    _ => return Err(t!("expr.unknown_function", name = name).to_string()),
+                };
+                self.expect(")")?;
+                Ok(expr)
+            },
+            Token::Symbol(_) => Err(self.unexpected()),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let mut parser = Parser { tokens: tokenize(text)?, pos: 0 };
+        let expr = parser.comparison()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(parser.unexpected());
+        }
+        Ok(expr)
+    }
+
+    pub fn evaluate(&self, execution: &Execution) -> Result<i64, String> {
+        Ok(match self {
+            Expr::Number(number) => *number,
+            Expr::Variable(proc_id, addr) => {
+                let (proc_id, addr) = (proc_id.evaluate(execution)?, addr.evaluate(execution)?);
+                match execution.variable(proc_id as usize, addr) {
+                    Some(val) if proc_id >= 0 => val,
+                    _ => return Err(t!("expr.invalid_variable", id = proc_id, addr = addr).to_string()),
+                }
+            },
+            Expr::Constant(index) => {
+                let index = index.evaluate(execution)?;
+                match execution.constant(index as usize) {
+                    Some(val) if index >= 0 => val,
+                    _ => return Err(t!("pl0.invalid_constant", c = index).to_string()),
+                }
+            },
+            Expr::Negate(expr) => expr.evaluate(execution)?.wrapping_neg(),
+            Expr::Binary(left, op, right) => {
+                let (left, right) = (left.evaluate(execution)?, right.evaluate(execution)?);
+                match op {
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Subtract => left.wrapping_sub(right),
+                    BinaryOp::Multiply => left.wrapping_mul(right),
+                    BinaryOp::Divide if right == 0 => return Err(t!("pl0.error.division_by_zero").to_string()),
+                    BinaryOp::Divide => left.wrapping_div(right),
+                    BinaryOp::Eq => (left == right) as i64,
+                    BinaryOp::NotEq => (left != right) as i64,
+                    BinaryOp::LT => (left < right) as i64,
+                    BinaryOp::GT => (left > right) as i64,
+                    BinaryOp::LTEq => (left <= right) as i64,
+                    BinaryOp::GTEq => (left >= right) as i64,
+                }
+            },
+        })
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // nested operations are put in parentheses, so the precedence doesn't matter when reading them
+        let nested = |expr: &Expr| match expr {
+            Expr::Binary(..) => format!("({expr})"),
+            _ => expr.to_string(),
+        };
+        match self {
+            Expr::Number(number) => write!(f, "{number}"),
+            Expr::Variable(proc_id, addr) => write!(f, "var({proc_id}, {addr})"),
+            Expr::Constant(index) => write!(f, "const({index})"),
+            Expr::Negate(expr) => write!(f, "-{}", nested(expr)),
+            Expr::Binary(left, op, right) => write!(f, "{} {} {}", nested(left), op.symbol(), nested(right)),
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use rust_i18n::t;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+
+// Files of a program for FileOpen, FileRead, FileAtEnd, FileWrite and FileClose, only inside the directory the VM
+// allows (--allow-fs), so names are relative to it and can't leave it, not even through symbolic links. Files hold
+// numbers separated by whitespace, written one per line. Files opened for reading are read completely right away and
+// writes go to the file immediately, so nothing is left open and executions can still be cloned.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Files {
+    // handle - 1 => the file, None once closed
+    open: Vec<Option<OpenFile>>,
+}
+
+#[derive(Debug, Clone)]
+enum OpenFile {
+    Read { numbers: Vec<i64>, next: usize },
+    Write(PathBuf),
+}
+
+// modes of FileOpen
+const READ: i64 = 0;
+const WRITE: i64 = 1;
+const APPEND: i64 = 2;
+
+impl Files {
+    // handle of the file, the errors are runtime error messages
+    pub fn open(&mut self, root: Option<&Path>, name: &str, mode: i64) -> Result<i64, String> {
+        let root = root.ok_or_else(|| t!("files.not_allowed", file = name).to_string())?;
+        let path = sandboxed(root, name).ok_or_else(|| t!("files.invalid_name", file = name).to_string())?;
+        let failed = |err: std::io::Error| t!("files.open_failed", file = name, err = err).to_string();
+        let file = match mode {
+            READ => {
+                let text = std::fs::read_to_string(&path).map_err(failed)?;
+                let numbers = text.split_whitespace()
+                    .map(|word| word.parse().map_err(|_| t!("files.invalid_number", file = name, text = word).to_string()))
+                    .collect::<Result<_, _>>()?;
+                OpenFile::Read { numbers, next: 0 }
+            },
+            WRITE | APPEND => {
+                OpenOptions::new().create(true).write(true).truncate(mode == WRITE).append(mode == APPEND).open(&path).map_err(failed)?;
+                OpenFile::Write(path)
+            },
+            _ => return Err(t!("files.invalid_mode", mode = mode).to_string()),
+        };
+        self.open.push(Some(file));
+        Ok(self.open.len() as i64)
+    }
+
+    fn get(&mut self, handle: i64) -> Result<&mut OpenFile, String> {
+        usize::try_from(handle - 1).ok()
+            .and_then(|index| self.open.get_mut(index)?.as_mut())
+            .ok_or_else(|| t!("files.invalid_handle", handle = handle).to_string())
+    }
+
+    pub fn read(&mut self, handle: i64) -> Result<i64, String> {
+        match self.get(handle)? {
+            OpenFile::Read { numbers, next } => {
+                let val = *numbers.get(*next).ok_or_else(|| t!("files.end", handle = handle).to_string())?;
+                *next += 1;
+                Ok(val)
+            },
+            OpenFile::Write(_) => Err(t!("files.not_readable", handle = handle).to_string()),
+        }
+    }
+
+    // no more numbers to read, files opened for writing are always at their end
+    pub fn at_end(&mut self, handle: i64) -> Result<bool, String> {
+        Ok(match self.get(handle)? {
+            OpenFile::Read { numbers, next } => *next >= numbers.len(),
+            OpenFile::Write(_) => true,
+        })
+    }
+
+    pub fn write(&mut self, handle: i64, val: i64) -> Result<(), String> {
+        let path = match self.get(handle)? {
+            OpenFile::Write(path) => path,
+            OpenFile::Read { .. } => return Err(t!("files.not_writable", handle = handle).to_string()),
+        };
+        OpenOptions::new().append(true).open(&*path)
+            .and_then(|mut file| writeln!(file, "{}", val))
+            .map_err(|err| t!("files.write_failed", file = path.display(), err = err).to_string())
+    }
+
+    pub fn close(&mut self, handle: i64) -> Result<(), String> {
+        self.get(handle)?;
+        self.open[handle as usize - 1] = None;
+        Ok(())
+    }
+}
+
+// the path of the file inside root, None if the name would leave it
+fn sandboxed(root: &Path, name: &str) -> Option<PathBuf> {
+    let relative = Path::new(name);
+    if name.is_empty() || !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return None;
+    }
+    let root = root.canonicalize().ok()?;
+    let path = root.join(relative);
+    // links inside the directory may point anywhere, so the real location has to be checked
+    let parent = path.parent()?.canonicalize().ok()?;
+    let real = match path.canonicalize() {
+        Ok(val) => val,
+        // a new file, its directory decides
+        Err(_) => parent.join(path.file_name()?),
+    };
+    if real.starts_with(&root) { Some(real) } else { None }
+}
@@ -0,0 +1,107 @@
+use crate::pl0_vm::{Step, PL0VM, START_FAILED_STATUS};
+use rust_i18n::t;
+use std::collections::BTreeMap;
+use std::io::stdout;
+
+// Every executed instruction is one sample of the active calls, so the widths of a flame graph are exact
+// instruction counts. The folded format (one line "main;a;b <count>" per call path) is read by the usual
+// flame graph tools, the SVG is a simple flame graph without them.
+
+const SVG_WIDTH: f64 = 1200.0;
+const ROW_HEIGHT: usize = 16;
+
+// one call path with the number of instructions run in it, including the calls it made
+#[derive(Default)]
+struct Node {
+    total: usize,
+    children: BTreeMap<String, Node>,
+}
+
+impl PL0VM {
+    // run like execute and count the instructions run for every call path, keyed by the folded path, with the status
+    // like execute
+    pub fn execute_sampled(&self) -> (BTreeMap<String, usize>, i64) {
+        let mut samples = BTreeMap::new();
+        let mut execution = match self.start() {
+            Some(val) => val,
+            None => return (samples, START_FAILED_STATUS),
+        };
+        let mut calls = vec![self.frame_name(execution.current_procedure())];
+        loop {
+            *samples.entry(calls.join(";")).or_insert(0) += 1;
+            let depth = execution.call_depth();
+            let state = execution.step(&mut stdout());
+            if execution.call_depth() > depth {
+                calls.push(self.frame_name(execution.current_procedure()));
+            } else if execution.call_depth() < depth && calls.len() > 1 {
+                calls.pop();
+            }
+            if state != Step::Running {
+                break;
+            }
+        }
+        (samples, execution.status())
+    }
+
+    fn frame_name(&self, proc_id: usize) -> String {
+        t!("pl0.layout.procedure", id = self.proc_label(proc_id)).replace(';', ",")
+    }
+}
+
+pub fn folded(samples: &BTreeMap<String, usize>) -> String {
+    samples.iter().map(|(path, count)| format!("{} {}\n", path, count)).collect()
+}
+
+pub fn svg(samples: &BTreeMap<String, usize>) -> String {
+    let mut root = Node::default();
+    for (path, count) in samples {
+        root.total += count;
+        let mut node = &mut root;
+        for name in path.split(';') {
+            node = node.children.entry(name.to_string()).or_default();
+            node.total += count;
+        }
+    }
+    let depth = max_depth(&root);
+    let height = (depth + 1) * ROW_HEIGHT;
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{SVG_WIDTH}\" height=\"{height}\" font-family=\"monospace\" font-size=\"11\">\n"
+    );
+    let scale = if root.total == 0 { 0.0 } else { SVG_WIDTH / root.total as f64 };
+    let mut x = 0.0;
+    for (name, child) in &root.children {
+        draw(&mut out, name, child, x, 0, height, scale);
+        x += child.total as f64 * scale;
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+fn max_depth(node: &Node) -> usize {
+    node.children.values().map(|child| 1 + max_depth(child)).max().unwrap_or(0)
+}
+
+// the frame of node and everything it called, the root calls are at the bottom
+fn draw(out: &mut String, name: &str, node: &Node, x: f64, level: usize, height: usize, scale: f64) {
+    let width = node.total as f64 * scale;
+    let y = height - (level + 1) * ROW_HEIGHT;
+    let name = name.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+    // warm colors that stay the same for a procedure across graphs
+    let hash = name.bytes().fold(0usize, |hash, b| hash.wrapping_mul(31).wrapping_add(b as usize));
+    out.push_str(&format!(
+        "<g><title>{name} ({})</title><rect x=\"{x:.2}\" y=\"{y}\" width=\"{width:.2}\" height=\"{}\" fill=\"rgb(230,{},{})\" stroke=\"white\"/>",
+        node.total, ROW_HEIGHT - 1, 80 + hash % 120, hash / 120 % 60,
+    ));
+    // only as much of the name as fits, about 7 pixels per character
+    let chars = ((width - 6.0) / 7.0).max(0.0) as usize;
+    if chars >= 3 {
+        let label: String = name.chars().take(chars).collect();
+        out.push_str(&format!("<text x=\"{:.2}\" y=\"{}\">{label}</text>", x + 3.0, y + ROW_HEIGHT - 4));
+    }
+    out.push_str("</g>\n");
+    let mut child_x = x;
+    for (child_name, child) in &node.children {
+        draw(out, child_name, child, child_x, level + 1, height, scale);
+        child_x += child.total as f64 * scale;
+    }
+}
@@ -0,0 +1,81 @@
+use crate::decoder::Instruction;
+use crate::opcodes::OpCode;
+use crate::pl0_vm::PL0VM;
+
+// Control flow inside of procedures. Procedures are handled as slices of their instructions, starting
+// with the EntryProc, so instructions are referenced by their index in the procedure.
+impl PL0VM {
+    // address a Jump/JumpIfFalse goes to, None for other instructions
+    pub(crate) fn jump_target(&self, instr: &Instruction) -> Option<usize> {
+        match instr.op {
+            OpCode::Jump | OpCode::JumpIfFalse => {
+                let offset = self.read_operand(instr.arg_addr(0), instr.wide).expect("length checked while decoding");
+                (instr.addr + instr.len).checked_add_signed(offset as isize)
+            },
+            _ => None,
+        }
+    }
+
+    // instructions that can run directly after the one with the given index
+    // (jumps leaving the procedure are left out, the verifier reports them)
+    pub(crate) fn successors(&self, procedure: &[Instruction], index: usize) -> Vec<usize> {
+        let instr = &procedure[index];
+        let mut next = vec![];
+        if let Some(target) = self.jump_target(instr) {
+            next.extend(procedure.iter().position(|other| other.addr == target));
+        }
+        if !matches!(instr.op, OpCode::Jump | OpCode::ReturnProc | OpCode::ReturnValue | OpCode::Halt | OpCode::EndOfCode) && index + 1 < procedure.len() {
+            next.push(index + 1);
+        }
+        next
+    }
+
+    // which instructions of the procedure can be reached from its EntryProc
+    pub(crate) fn reachable(&self, procedure: &[Instruction]) -> Vec<bool> {
+        let mut reached = vec![false; procedure.len()];
+        let mut pending = vec![0];
+        while let Some(index) = pending.pop() {
+            if reached[index] { continue; }
+            reached[index] = true;
+            pending.extend(self.successors(procedure, index));
+        }
+        reached
+    }
+
+    // addresses of all instructions that can never run, because no path from the EntryProc of their procedure leads to them
+    pub fn unreachable_code(&self) -> Result<Vec<usize>, String> {
+        let instructions = self.decode_code()?;
+        let mut unreachable = vec![];
+        for procedure in instructions.chunk_by(|_, instr| instr.op != OpCode::EntryProc) {
+            let reached = self.reachable(procedure);
+            unreachable.extend(procedure.iter().zip(reached).filter(|(_, reached)| !reached).map(|(instr, _)| instr.addr));
+        }
+        Ok(unreachable)
+    }
+
+    // Back-edges (jumps to an earlier instruction) whose loop contains no input, no store, no call, nothing giving
+    // another result every time (Clock, Random, files, the environment) and no way out of the procedure: nothing in
+    // the loop can change the state its condition depends on, so once entered it never ends.
+    // Returns the addresses of the jump and its target.
+    pub fn state_invariant_loops(&self) -> Result<Vec<(usize, usize)>, String> {
+        let instructions = self.decode_code()?;
+        let mut loops = vec![];
+        for procedure in instructions.chunk_by(|_, instr| instr.op != OpCode::EntryProc) {
+            for instr in procedure {
+                let target = match self.jump_target(instr) {
+                    Some(target) if target <= instr.addr && procedure.iter().any(|other| other.addr == target) => target,
+                    _ => continue,
+                };
+                let changes_state = procedure.iter()
+                    .filter(|other| (target..=instr.addr).contains(&other.addr))
+                    .any(|other| matches!(other.op, OpCode::InputToAddr | OpCode::GetChar | OpCode::StoreValue | OpCode::Put | OpCode::StoreHeap
+                        | OpCode::CallProc | OpCode::CallProcArgs | OpCode::Clock | OpCode::Random | OpCode::FileRead | OpCode::FileAtEnd
+                        | OpCode::EnvNumber | OpCode::EnvString | OpCode::Halt | OpCode::ReturnProc | OpCode::ReturnValue));
+                if !changes_state {
+                    loops.push((instr.addr, target));
+                }
+            }
+        }
+        Ok(loops)
+    }
+}
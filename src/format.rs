@@ -0,0 +1,162 @@
+// Files may also be gzip compressed as a whole, which is detected by the gzip signature,
+// or written as hex text, see from_hex_text.
+//
+// Layout of bytecode files:
+//   legacy files:  <image>
+//   version 1:     "PL0C" <version: 2 bytes> <image>
+//   version 2:     "PL0C" <version: 2 bytes> <image length: 4 bytes> <image> <sections...>
+// where the image is the original layout (procedure count, architecture, procedures, constants)
+// and every section consists of a 4 byte tag, its data length as 4 bytes and the data itself.
+// All numbers are little endian.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rust_i18n::t;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::ops::Range;
+
+pub const MAGIC: &[u8; 4] = b"PL0C";
+const GZIP_MAGIC: &[u8; 2] = &[0x1F, 0x8B];
+pub const FORMAT_VERSION: u16 = 2;
+
+// CRC32 of the image
+pub const CHECKSUM_TAG: &[u8; 4] = b"CSUM";
+// procedure and variable names, see symbols.rs
+pub const SYMBOLS_TAG: &[u8; 4] = b"SYMS";
+// mapping of code addresses to source lines, see lines.rs
+pub const LINES_TAG: &[u8; 4] = b"LINE";
+// string table for PutStringRef and PushString: null terminated strings, referenced by their position
+pub const STRINGS_TAG: &[u8; 4] = b"STRS";
+// key/value pairs describing where the file comes from, see metadata.rs
+pub const METADATA_TAG: &[u8; 4] = b"META";
+// output the program is expected to print, for --selftest
+pub const EXPECTED_OUTPUT_TAG: &[u8; 4] = b"EOUT";
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub tag: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+pub(crate) struct FileContents {
+    // None for legacy files without header
+    pub version: Option<u16>,
+    // position of the image in the file
+    pub image: Range<usize>,
+    pub sections: Vec<Section>,
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<usize> {
+    Some(u32::from_le_bytes(bytes.get(offset..(offset + 4))?.try_into().ok()?) as usize)
+}
+
+// split the file into header, image and sections - returns None if the file structure is broken
+pub(crate) fn parse(bytes: &[u8]) -> Option<FileContents> {
+    let rest = match bytes.strip_prefix(MAGIC) {
+        Some(rest) => rest,
+        None => return Some(FileContents { version: None, image: 0..bytes.len(), sections: vec![] }),
+    };
+    let version = u16::from_le_bytes(rest.get(0..2)?.try_into().ok()?);
+    let start = MAGIC.len() + 2;
+    let rest = &rest[2..];
+    match version {
+        1 => Some(FileContents { version: Some(1), image: start..bytes.len(), sections: vec![] }),
+        2 => {
+            let len = read_u32(rest, 0)?;
+            rest.get(4..(4 + len))?;
+            let mut rest = &rest[(4 + len)..];
+            let mut sections = vec![];
+            while !rest.is_empty() {
+                let tag = rest.get(0..4)?.try_into().ok()?;
+                let len = read_u32(rest, 4)?;
+                let data = rest.get(8..(8 + len))?.to_vec();
+                sections.push(Section { tag, data });
+                rest = &rest[(8 + len)..];
+            }
+            Some(FileContents { version: Some(2), image: (start + 4)..(start + 4 + len), sections })
+        },
+        0 => None,
+        // layout of future versions is unknown, the version check will reject the file
+        _ => Some(FileContents { version: Some(version), image: 0..0, sections: vec![] }),
+    }
+}
+
+// build a file of the current format version
+pub(crate) fn serialize(image: &[u8], sections: &[Section]) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(FORMAT_VERSION.to_le_bytes());
+    bytes.extend((image.len() as u32).to_le_bytes());
+    bytes.extend(image);
+    for section in sections {
+        bytes.extend(section.tag);
+        bytes.extend((section.data.len() as u32).to_le_bytes());
+        bytes.extend(&section.data);
+    }
+    bytes
+}
+
+// whether the file has to go through decompress or from_hex_text before it can be parsed
+#[cfg(feature = "mmap")]
+pub(crate) fn needs_conversion(bytes: &[u8]) -> bool {
+    bytes.starts_with(GZIP_MAGIC) || is_hex_text(bytes)
+}
+
+// unpack gzip compressed files, other files are returned unchanged
+pub(crate) fn decompress(bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if !bytes.starts_with(GZIP_MAGIC) {
+        return Ok(bytes);
+    }
+    let mut unpacked = vec![];
+    GzDecoder::new(bytes.as_slice()).read_to_end(&mut unpacked)?;
+    Ok(unpacked)
+}
+
+pub(crate) fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::best());
+    encoder.write_all(bytes).expect("writing to a Vec can't fail");
+    encoder.finish().expect("writing to a Vec can't fail")
+}
+
+// Files that only contain text are read as hex dump: pairs of hex digits separated by any whitespace,
+// with comments starting with #, ; or // until the end of the line. Other files are returned unchanged,
+// real bytecode always contains null bytes (at least in the architecture bytes).
+fn is_hex_text(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes).is_ok_and(|text| text.chars().all(|c| !c.is_control() || c.is_whitespace()))
+}
+pub(crate) fn from_hex_text(bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if !is_hex_text(&bytes) {
+        return Ok(bytes);
+    }
+    let text = std::str::from_utf8(&bytes).expect("checked by is_hex_text");
+    let mut parsed = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let code = line.split(['#', ';']).next().unwrap_or_default();
+        let code = code.split("//").next().unwrap_or_default();
+        for token in code.split_whitespace() {
+            let invalid = || Error::new(ErrorKind::InvalidData, t!("pl0.invalid_hex_text", line = i + 1, token = token).to_string());
+            if !token.len().is_multiple_of(2) {
+                return Err(invalid());
+            }
+            for pos in (0..token.len()).step_by(2) {
+                match token.get(pos..(pos + 2)).and_then(|pair| u8::from_str_radix(pair, 16).ok()) {
+                    Some(byte) => parsed.push(byte),
+                    None => return Err(invalid()),
+                }
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+// CRC-32 (IEEE) as used by zip and png
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
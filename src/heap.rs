@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+// The heap of an execution, for Alloc, Free, LoadHeap and StoreHeap: blocks of values apart from the stack, with
+// addresses in bytes like there. Address 0 is never handed out, so programs can use it as null. A new block goes
+// into the first gap between the allocated ones it fits in, so freed space is reused; the heap never grows beyond
+// its limit. Only cells of allocated blocks can be read and written, new blocks are filled with 0.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Heap {
+    cells: Vec<i64>,
+    // first cell => number of cells of the allocated blocks
+    blocks: BTreeMap<usize, usize>,
+    // most cells the blocks may take together, without the unused cell 0
+    limit: usize,
+}
+
+impl Heap {
+    pub fn new(limit: usize) -> Heap {
+        Heap { cells: vec![], blocks: BTreeMap::new(), limit }
+    }
+
+    // first cell of a new block of count cells (at least one), None if it doesn't fit anymore
+    pub fn alloc(&mut self, count: usize) -> Option<usize> {
+        let count = count.max(1);
+        let mut start = 1;
+        for (&block, &len) in &self.blocks {
+            if block - start >= count {
+                break;
+            }
+            start = block + len;
+        }
+        let end = start.checked_add(count).filter(|end| *end <= self.limit + 1)?;
+        if self.cells.len() < end {
+            self.cells.resize(end, 0);
+        }
+        self.cells[start..end].fill(0);
+        self.blocks.insert(start, count);
+        Some(start)
+    }
+
+    // free the block starting at the cell, false if no block starts there
+    pub fn free(&mut self, cell: usize) -> bool {
+        self.blocks.remove(&cell).is_some()
+    }
+
+    fn allocated(&self, cell: usize) -> bool {
+        self.blocks.range(..=cell).next_back().is_some_and(|(&start, &len)| cell < start + len)
+    }
+
+    pub fn get(&self, cell: usize) -> Option<i64> {
+        if self.allocated(cell) { Some(self.cells[cell]) } else { None }
+    }
+
+    // false if the cell isn't part of an allocated block
+    pub fn set(&mut self, cell: usize, val: i64) -> bool {
+        if !self.allocated(cell) {
+            return false;
+        }
+        self.cells[cell] = val;
+        true
+    }
+}
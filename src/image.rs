@@ -0,0 +1,74 @@
+use std::ops::{Deref, DerefMut, Range};
+
+// Storage of the program image: owned bytes, or (with the mmap feature) a region of a memory
+// mapped file. Mapped images are only copied into memory once the program gets modified.
+pub(crate) enum Image {
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap, Range<usize>),
+}
+
+impl Image {
+    // map the file into memory, None if its contents have to be converted first (compressed or hex text)
+    #[cfg(feature = "mmap")]
+    pub fn map_file(filename: &str) -> Result<Option<Image>, std::io::Error> {
+        let file = std::fs::File::open(filename)?;
+        // SAFETY: the file must not be changed by other processes while it is mapped - that's the
+        // accepted trade-off of this backend, which is why it's an optional feature
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        if crate::format::needs_conversion(&map) {
+            return Ok(None);
+        }
+        let len = map.len();
+        Ok(Some(Image::Mapped(map, 0..len)))
+    }
+
+    // restrict the image to the given part of it
+    pub fn narrow(self, range: Range<usize>) -> Image {
+        match self {
+            Image::Owned(mut bytes) => {
+                bytes.truncate(range.end);
+                bytes.drain(..range.start);
+                Image::Owned(bytes)
+            },
+            #[cfg(feature = "mmap")]
+            Image::Mapped(map, old) => Image::Mapped(map, (old.start + range.start)..(old.start + range.end)),
+        }
+    }
+
+    // the bytes for modification, copying them out of the mapped file first if necessary
+    pub fn to_mut(&mut self) -> &mut Vec<u8> {
+        #[cfg(feature = "mmap")]
+        if let Image::Mapped(map, range) = self {
+            *self = Image::Owned(map[range.clone()].to_vec());
+        }
+        match self {
+            Image::Owned(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Image::Mapped(..) => unreachable!("converted above"),
+        }
+    }
+}
+
+impl Default for Image {
+    fn default() -> Image {
+        Image::Owned(vec![])
+    }
+}
+
+impl Deref for Image {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match self {
+            Image::Owned(bytes) => bytes,
+            #[cfg(feature = "mmap")]
+            Image::Mapped(map, range) => &map[range.clone()],
+        }
+    }
+}
+
+impl DerefMut for Image {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.to_mut()
+    }
+}
@@ -0,0 +1,40 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Ctrl+C while a program runs: the first one asks the interpreter to stop before the next instruction, it reports
+// where it was, the active calls and the counters so far (see Execution::run_steps), instead of the process dying in
+// the middle of writing the output. A second one ends the VM right away, e.g. while it waits for input or runs
+// compiled code, which don't look at the request.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+const SIGINT: i32 = 2;
+#[cfg(unix)]
+const SIG_DFL: usize = 0;
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(signum: i32, handler: usize) -> usize;
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: i32) {
+    REQUESTED.store(true, Ordering::SeqCst);
+    unsafe { signal(SIGINT, SIG_DFL); }
+}
+
+// handle Ctrl+C like described above, the pl0_vm_rs binary does it for every mode that runs the program except the
+// interactive debuggers; elsewhere than on Unix it still ends the process
+pub fn install() {
+    #[cfg(unix)]
+    unsafe { signal(SIGINT, on_interrupt as extern "C" fn(i32) as usize); }
+}
+
+// whether Ctrl+C was pressed and not handled yet
+pub(crate) fn pending() -> bool {
+    REQUESTED.load(Ordering::Relaxed)
+}
+
+// whether Ctrl+C was pressed, handling it
+pub(crate) fn take() -> bool {
+    pending() && REQUESTED.swap(false, Ordering::SeqCst)
+}
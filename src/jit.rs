@@ -0,0 +1,761 @@
+use crate::decoder::Decoded;
+use crate::opcodes::OpCode;
+use crate::pl0_vm::{read_number, Execution, NoInput, CALL_FRAME_SIZE, INPUT_TIMEOUT_STATUS, PL0VM, START_FAILED_STATUS};
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{types, AbiParam, Block, BlockCall, FuncRef, InstBuilder, JumpTableData, MemFlags, Value};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+use rust_i18n::t;
+use std::ffi::c_void;
+use std::io::{stdout, BufWriter, Write};
+
+// Compiles whole programs to native code with Cranelift (feature jit), for --jit. Every instruction becomes a block
+// working on the same stack layout as the interpreter (values of the architecture, call information in front of
+// every frame), so Put, Get and Swap see what they would see there. Procedure calls stay inside of the one function:
+// CallProc jumps to the procedure and ReturnProc jumps back through a table of all instruction addresses.
+// Nothing is counted or traced, the debugging modes always use the interpreter.
+
+// values the native stack can hold, the interpreter grows its stack instead
+const STACK_CELLS: i64 = 1 << 24;
+
+// why the compiled code stopped, returned together with the address of the instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exit {
+    Finished = 0,
+    DivisionByZero,
+    StackRead,
+    StackFull,
+    // the address is in State::detail
+    Unaligned,
+    AddressRange,
+    // a shift by more bits than a value has or a negative number of them, the amount is in State::detail
+    ShiftRange,
+    // no number to read, why is in Runtime::input_error
+    Input,
+    // the new program counter is in State::detail
+    InvalidPc,
+}
+
+impl Exit {
+    fn status(self, addr: usize) -> i64 {
+        (self as i64) << 32 | addr as i64
+    }
+    fn from_status(status: i64) -> (Exit, usize) {
+        let exit = match status >> 32 {
+            0 => Exit::Finished,
+            1 => Exit::DivisionByZero,
+            2 => Exit::StackRead,
+            3 => Exit::StackFull,
+            4 => Exit::Unaligned,
+            5 => Exit::AddressRange,
+            6 => Exit::ShiftRange,
+            7 => Exit::Input,
+            _ => Exit::InvalidPc,
+        };
+        (exit, (status & 0xFFFF_FFFF) as usize)
+    }
+}
+
+// what the compiled function gets, it only reads stack and frame_ptrs and writes detail
+#[repr(C)]
+struct State {
+    stack: *mut i64,
+    // procedure ID => start of its latest frame, in bytes like Execution::backtrace
+    frame_ptrs: *mut i64,
+    // the value an error message needs
+    detail: i64,
+    // a Runtime for the functions the code calls
+    runtime: *mut c_void,
+}
+
+// what the functions called by the compiled code need
+struct Runtime<'a> {
+    vm: &'a PL0VM,
+    out: &'a mut dyn Write,
+    // texts of PutString and PutStringRef, by the index the code passes
+    strings: Vec<String>,
+    // why InputToAddr got no number
+    input_error: Option<NoInput>,
+}
+
+extern "C" fn output(runtime: *mut c_void, value: i64) {
+    let runtime = unsafe { &mut *(runtime as *mut Runtime) };
+    runtime.vm.write_value(runtime.out, value);
+}
+
+extern "C" fn print(runtime: *mut c_void, index: i64) {
+    let runtime = unsafe { &mut *(runtime as *mut Runtime) };
+    writeln!(runtime.out, "{}", runtime.strings[index as usize]).expect("Could not write program output");
+}
+
+// wait for the user to input a valid number, like InputToAddr in the interpreter; if there is none, State::detail
+// is set and the error kept for Exit::Input
+extern "C" fn input(state: *mut State, addr: i64) -> i64 {
+    let state = unsafe { &mut *state };
+    let runtime = unsafe { &mut *(state.runtime as *mut Runtime) };
+    match read_number(runtime.vm, &mut *runtime.out, addr as usize) {
+        Ok(num) => num,
+        Err(err) => {
+            runtime.input_error = Some(err);
+            state.detail = 1;
+            0
+        },
+    }
+}
+
+// the stack grows like in the interpreter, with new values set to 0
+extern "C" fn zero(stack: *mut i64, from: i64, to: i64) {
+    for cell in from..to {
+        unsafe { *stack.add(cell as usize) = 0; }
+    }
+}
+
+// builds the function, one instruction at a time
+struct Compiler<'a, 'b> {
+    vm: &'a PL0VM,
+    execution: &'a Execution<'a>,
+    builder: FunctionBuilder<'b>,
+    // first block of every instruction, by its index in the code
+    blocks: Vec<Block>,
+    // returns the status it gets
+    exit: Block,
+    // an invalid program counter as parameter
+    bad_pc: Block,
+    state: Value,
+    stack: Value,
+    frame_ptrs: Value,
+    runtime: Value,
+    // stack size in values, frame pointer in bytes, current procedure
+    sp: Variable,
+    fp: Variable,
+    proc_i: Variable,
+    output: FuncRef,
+    print: FuncRef,
+    input: FuncRef,
+    zero: FuncRef,
+    strings: Vec<String>,
+    // procedure ID => bytes of its variables
+    vars: Vec<i64>,
+    // the procedure of the instructions compiled now
+    proc_id: usize,
+}
+
+impl Compiler<'_, '_> {
+    fn size(&self) -> i64 {
+        self.vm.data_size() as i64
+    }
+    fn shift(&self) -> i64 {
+        self.vm.data_size().trailing_zeros() as i64
+    }
+
+    // go on if ok is true, otherwise stop with the exit
+    fn check(&mut self, ok: Value, exit: Exit, addr: usize, detail: Option<Value>) {
+        let fail = self.builder.create_block();
+        let cont = self.builder.create_block();
+        self.builder.ins().brif(ok, cont, &[], fail, &[]);
+        self.builder.switch_to_block(fail);
+        if let Some(detail) = detail {
+            self.builder.ins().store(MemFlags::trusted(), detail, self.state, std::mem::offset_of!(State, detail) as i32);
+        }
+        let status = self.builder.ins().iconst(types::I64, exit.status(addr));
+        self.builder.ins().jump(self.exit, &[status]);
+        self.builder.switch_to_block(cont);
+    }
+
+    // cut a value down to the data width
+    fn wrap(&mut self, val: Value) -> Value {
+        let ty = match self.size() {
+            1 => types::I8,
+            2 => types::I16,
+            4 => types::I32,
+            _ => return val,
+        };
+        let small = self.builder.ins().ireduce(ty, val);
+        self.builder.ins().sextend(types::I64, small)
+    }
+
+    fn cell_addr(&mut self, cell: Value) -> Value {
+        let offset = self.builder.ins().ishl_imm(cell, 3);
+        self.builder.ins().iadd(self.stack, offset)
+    }
+
+    fn push(&mut self, val: Value, addr: usize) {
+        let sp = self.builder.use_var(self.sp);
+        let ok = self.builder.ins().icmp_imm(IntCC::UnsignedLessThan, sp, STACK_CELLS);
+        self.check(ok, Exit::StackFull, addr, None);
+        let at = self.cell_addr(sp);
+        self.builder.ins().store(MemFlags::trusted(), val, at, 0);
+        let sp = self.builder.ins().iadd_imm(sp, 1);
+        self.builder.def_var(self.sp, sp);
+    }
+
+    fn pop(&mut self, addr: usize) -> Value {
+        let sp = self.builder.use_var(self.sp);
+        let ok = self.builder.ins().icmp_imm(IntCC::NotEqual, sp, 0);
+        self.check(ok, Exit::StackRead, addr, None);
+        let sp = self.builder.ins().iadd_imm(sp, -1);
+        self.builder.def_var(self.sp, sp);
+        let at = self.cell_addr(sp);
+        self.builder.ins().load(types::I64, MemFlags::trusted(), at, 0)
+    }
+
+    // the cell of a stack address, which has to start a value
+    fn cell(&mut self, bytes: Value, exit: Exit, addr: usize) -> Value {
+        let (size, shift) = (self.size(), self.shift());
+        if size > 1 {
+            let rest = self.builder.ins().band_imm(bytes, size - 1);
+            let ok = self.builder.ins().icmp_imm(IntCC::Equal, rest, 0);
+            self.check(ok, exit, addr, Some(bytes));
+        }
+        self.builder.ins().sshr_imm(bytes, shift)
+    }
+
+    // the value at a stack address below the top
+    fn read(&mut self, bytes: Value, addr: usize) -> Value {
+        let cell = self.cell(bytes, Exit::StackRead, addr);
+        let sp = self.builder.use_var(self.sp);
+        let ok = self.builder.ins().icmp(IntCC::UnsignedLessThan, cell, sp);
+        self.check(ok, Exit::StackRead, addr, None);
+        let at = self.cell_addr(cell);
+        self.builder.ins().load(types::I64, MemFlags::trusted(), at, 0)
+    }
+
+    // set the value at a stack address, the stack grows up to it if needed
+    fn write(&mut self, bytes: Value, val: Value, addr: usize) {
+        let cell = self.cell(bytes, Exit::Unaligned, addr);
+        let ok = self.builder.ins().icmp_imm(IntCC::UnsignedLessThan, cell, STACK_CELLS);
+        self.check(ok, Exit::StackFull, addr, None);
+        let end = self.builder.ins().iadd_imm(cell, 1);
+        self.grow(end);
+        let at = self.cell_addr(cell);
+        let val = self.wrap(val);
+        self.builder.ins().store(MemFlags::trusted(), val, at, 0);
+    }
+
+    // the stack holds at least end values, end has to be checked against STACK_CELLS already
+    fn grow(&mut self, end: Value) {
+        let sp = self.builder.use_var(self.sp);
+        self.builder.ins().call(self.zero, &[self.stack, sp, end]);
+        let sp = self.builder.ins().smax(sp, end);
+        self.builder.def_var(self.sp, sp);
+    }
+
+    // push a stack address, it has to fit into a value
+    fn push_address(&mut self, bytes: Value, addr: usize) {
+        if self.size() < 8 {
+            let max = (1i64 << (self.size() * 8 - 1)) - 1;
+            let ok = self.builder.ins().icmp_imm(IntCC::SignedLessThanOrEqual, bytes, max);
+            self.check(ok, Exit::AddressRange, addr, Some(bytes));
+        }
+        self.push(bytes, addr);
+    }
+
+    fn frame_ptr_addr(&mut self, proc_i: Value) -> Value {
+        let offset = self.builder.ins().ishl_imm(proc_i, 3);
+        self.builder.ins().iadd(self.frame_ptrs, offset)
+    }
+
+    // the start of the latest frame of a procedure
+    fn frame_ptr(&mut self, proc_id: i32) -> Value {
+        self.builder.ins().load(types::I64, MemFlags::trusted(), self.frame_ptrs, proc_id * 8)
+    }
+
+    // absolute stack address of a variable
+    fn var_addr(&mut self, base: Value, offset: i32) -> Value {
+        self.builder.ins().iadd_imm(base, offset as i64)
+    }
+
+    fn procedure_id(&self, id: i32, instr: &Decoded) -> Result<i32, String> {
+        if id < 0 || id as usize >= self.execution.procedure_count() {
+            return Err(self.unsupported(instr));
+        }
+        Ok(id)
+    }
+
+    fn unsupported(&self, instr: &Decoded) -> String {
+        t!("jit.unsupported", op = instr.op, addr = instr.addr:{:04X}).to_string()
+    }
+
+    fn jump_to(&mut self, target: Option<usize>, pc: usize) {
+        match target {
+            Some(index) => {
+                self.builder.ins().jump(self.blocks[index], &[]);
+            }
+            None => {
+                let pc = self.builder.ins().iconst(types::I64, pc as i64);
+                self.builder.ins().jump(self.bad_pc, &[pc]);
+            }
+        }
+    }
+
+    fn compile(&mut self, index: usize, instr: &Decoded) -> Result<(), String> {
+        let addr = instr.addr;
+        let end = addr + instr.len;
+        let [arg0, arg1, arg2] = instr.args;
+        match instr.op {
+            OpCode::EntryProc => {
+                let proc_id = self.procedure_id(arg1, instr)?;
+                self.proc_id = proc_id as usize;
+                let fp = self.frame_ptr(proc_id);
+                self.builder.def_var(self.fp, fp);
+                // frames start at a value, so the variables take whole values too
+                let (size, shift) = (self.size(), self.shift());
+                let end = self.builder.ins().iadd_imm(fp, arg2 as i64 + size - 1);
+                let end = self.builder.ins().sshr_imm(end, shift);
+                let ok = self.builder.ins().icmp_imm(IntCC::UnsignedLessThanOrEqual, end, STACK_CELLS);
+                self.check(ok, Exit::StackFull, addr, None);
+                let sp = self.builder.use_var(self.sp);
+                self.builder.ins().call(self.zero, &[self.stack, sp, end]);
+                self.builder.def_var(self.sp, end);
+            }
+            OpCode::ReturnProc => {
+                let proc_i = self.builder.use_var(self.proc_i);
+                let main = self.builder.ins().icmp_imm(IntCC::Equal, proc_i, 0);
+                let finished = self.builder.ins().iconst(types::I64, Exit::Finished.status(addr));
+                let ret = self.builder.create_block();
+                self.builder.ins().brif(main, self.exit, &[finished], ret, &[]);
+                self.builder.switch_to_block(ret);
+
+                // like the interpreter the current frame and not the one in frame_ptrs, recursion may have moved that
+                let shift = self.shift();
+                let frame_ptr = self.builder.use_var(self.fp);
+                let frame = self.builder.ins().sshr_imm(frame_ptr, shift);
+                let info = self.builder.ins().iadd_imm(frame, -((CALL_FRAME_SIZE / self.vm.data_size()) as i64));
+                let sp = self.builder.use_var(self.sp);
+                let info_end = self.builder.ins().iadd_imm(info, 3);
+                let ok = self.builder.ins().icmp(IntCC::UnsignedLessThanOrEqual, info_end, sp);
+                self.check(ok, Exit::StackRead, addr, None);
+                let at = self.cell_addr(info);
+                let pc = self.builder.ins().load(types::I64, MemFlags::trusted(), at, 0);
+                let fp = self.builder.ins().load(types::I64, MemFlags::trusted(), at, 8);
+                let caller = self.builder.ins().load(types::I64, MemFlags::trusted(), at, 16);
+                // the caller indexes frame_ptrs, so it has to be checked even though only a broken stack gets it wrong
+                let ok = self.builder.ins().icmp_imm(IntCC::UnsignedLessThan, caller, self.execution.procedure_count() as i64);
+                self.check(ok, Exit::StackRead, addr, None);
+                // back in a frame of the same procedure, its variables are the ones of that frame again
+                let same = self.builder.ins().icmp(IntCC::Equal, caller, proc_i);
+                let at = self.frame_ptr_addr(proc_i);
+                let old = self.builder.ins().load(types::I64, MemFlags::trusted(), at, 0);
+                let restored = self.builder.ins().select(same, fp, old);
+                self.builder.ins().store(MemFlags::trusted(), restored, at, 0);
+                self.builder.def_var(self.sp, info);
+                self.builder.def_var(self.fp, fp);
+                self.builder.def_var(self.proc_i, caller);
+
+                let table_len = self.vm.program().len();
+                let ok = self.builder.ins().icmp_imm(IntCC::UnsignedLessThan, pc, table_len as i64);
+                let dispatch = self.builder.create_block();
+                self.builder.ins().brif(ok, dispatch, &[], self.bad_pc, &[pc]);
+                self.builder.switch_to_block(dispatch);
+                let bad_pc = self.builder.func.dfg.block_call(self.bad_pc, &[pc]);
+                let targets: Vec<BlockCall> = (0..table_len).map(|addr| match self.execution.code().at(addr) {
+                    Some(index) => self.builder.func.dfg.block_call(self.blocks[index], &[]),
+                    None => bad_pc,
+                }).collect();
+                let table = self.builder.create_jump_table(JumpTableData::new(bad_pc, &targets));
+                let index = self.builder.ins().ireduce(types::I32, pc);
+                self.builder.ins().br_table(index, table);
+                return Ok(());
+            }
+            OpCode::CallProc => {
+                let proc_id = self.procedure_id(arg0, instr)?;
+                let start = match instr.target {
+                    Some(val) => val,
+                    None => return Err(self.unsupported(instr)),
+                };
+                if instr.tail {
+                    // the callee takes over the frame, like in the interpreter
+                    let shift = self.shift();
+                    let fp = self.builder.use_var(self.fp);
+                    let sp = self.builder.ins().sshr_imm(fp, shift);
+                    self.builder.def_var(self.sp, sp);
+                    self.builder.ins().store(MemFlags::trusted(), fp, self.frame_ptrs, proc_id * 8);
+                    let proc_id = self.builder.ins().iconst(types::I64, proc_id as i64);
+                    self.builder.def_var(self.proc_i, proc_id);
+                    self.builder.ins().jump(self.blocks[start], &[]);
+                    return Ok(());
+                }
+                let frame_cells = (CALL_FRAME_SIZE / self.vm.data_size()) as i64;
+                let sp = self.builder.use_var(self.sp);
+                let frame = self.builder.ins().iadd_imm(sp, frame_cells);
+                let ok = self.builder.ins().icmp_imm(IntCC::UnsignedLessThanOrEqual, frame, STACK_CELLS);
+                self.check(ok, Exit::StackFull, addr, None);
+                self.builder.ins().call(self.zero, &[self.stack, sp, frame]);
+                let at = self.cell_addr(sp);
+                let pc = self.builder.ins().iconst(types::I64, end as i64);
+                let fp = self.builder.use_var(self.fp);
+                let proc_i = self.builder.use_var(self.proc_i);
+                self.builder.ins().store(MemFlags::trusted(), pc, at, 0);
+                self.builder.ins().store(MemFlags::trusted(), fp, at, 8);
+                self.builder.ins().store(MemFlags::trusted(), proc_i, at, 16);
+                self.builder.def_var(self.sp, frame);
+                let size = self.size();
+                let frame_ptr = self.builder.ins().imul_imm(frame, size);
+                self.builder.ins().store(MemFlags::trusted(), frame_ptr, self.frame_ptrs, proc_id * 8);
+                let proc_id = self.builder.ins().iconst(types::I64, proc_id as i64);
+                self.builder.def_var(self.proc_i, proc_id);
+                self.builder.ins().jump(self.blocks[start], &[]);
+                return Ok(());
+            }
+
+            OpCode::PushValueLocalVar | OpCode::PushValueMainVar | OpCode::PushValueGlobalVar
+                | OpCode::PushAddressLocalVar | OpCode::PushAddressMainVar | OpCode::PushAddressGlobalVar => {
+                let owner = match instr.op {
+                    OpCode::PushValueLocalVar | OpCode::PushAddressLocalVar => self.proc_id,
+                    OpCode::PushValueMainVar | OpCode::PushAddressMainVar => 0,
+                    _ => self.procedure_id(arg1, instr)? as usize,
+                };
+                // the interpreter reports variables outside of the frame
+                if arg0 < 0 || arg0 as i64 >= self.vars[owner] {
+                    return Err(self.unsupported(instr));
+                }
+                let base = match instr.op {
+                    OpCode::PushValueLocalVar | OpCode::PushAddressLocalVar => self.builder.use_var(self.fp),
+                    _ => self.frame_ptr(owner as i32),
+                };
+                let bytes = self.var_addr(base, arg0);
+                if matches!(instr.op, OpCode::PushValueLocalVar | OpCode::PushValueMainVar | OpCode::PushValueGlobalVar) {
+                    let val = self.read(bytes, addr);
+                    self.push(val, addr);
+                } else {
+                    self.push_address(bytes, addr);
+                }
+            }
+            OpCode::PushConstant => {
+                let val = match usize::try_from(arg0).ok().and_then(|c| self.execution.constant(c)) {
+                    Some(val) => val,
+                    None => return Err(self.unsupported(instr)),
+                };
+                let val = self.builder.ins().iconst(types::I64, val);
+                self.push(val, addr);
+            }
+            OpCode::StoreValue => {
+                let val = self.pop(addr);
+                let bytes = self.pop(addr);
+                self.write(bytes, val, addr);
+            }
+
+            OpCode::OutputValue => {
+                let val = self.pop(addr);
+                self.builder.ins().call(self.output, &[self.runtime, val]);
+            }
+            OpCode::InputToAddr => {
+                let offset = self.pop(addr);
+                let addr_val = self.builder.ins().iconst(types::I64, addr as i64);
+                let call = self.builder.ins().call(self.input, &[self.state, addr_val]);
+                let num = self.builder.inst_results(call)[0];
+                let failed = self.builder.ins().load(types::I64, MemFlags::trusted(), self.state, std::mem::offset_of!(State, detail) as i32);
+                let ok = self.builder.ins().icmp_imm(IntCC::Equal, failed, 0);
+                self.check(ok, Exit::Input, addr, None);
+                let fp = self.builder.use_var(self.fp);
+                let bytes = self.builder.ins().iadd(fp, offset);
+                self.write(bytes, num, addr);
+            }
+
+            OpCode::Minusify => {
+                let val = self.pop(addr);
+                let val = self.builder.ins().ineg(val);
+                let val = self.wrap(val);
+                self.push(val, addr);
+            }
+            OpCode::IsOdd => {
+                let val = self.pop(addr);
+                let bit = self.builder.ins().band_imm(val, 1);
+                self.push(bit, addr);
+            }
+            op @ (OpCode::ShiftLeft | OpCode::ShiftRight | OpCode::ShiftRightLogical) => {
+                let amount = self.pop(addr);
+                let val = self.pop(addr);
+                // negative amounts are beyond the range as unsigned numbers
+                let bits = self.size() * 8;
+                let ok = self.builder.ins().icmp_imm(IntCC::UnsignedLessThan, amount, bits);
+                self.check(ok, Exit::ShiftRange, addr, Some(amount));
+                let val = match op {
+                    OpCode::ShiftLeft => self.builder.ins().ishl(val, amount),
+                    OpCode::ShiftRight => self.builder.ins().sshr(val, amount),
+                    _ => {
+                        let unsigned = self.builder.ins().band_imm(val, (u64::MAX >> (64 - bits)) as i64);
+                        self.builder.ins().ushr(unsigned, amount)
+                    }
+                };
+                let val = self.wrap(val);
+                self.push(val, addr);
+            }
+            OpCode::OpNot => {
+                let val = self.pop(addr);
+                let val = self.builder.ins().bnot(val);
+                self.push(val, addr);
+            }
+            op if op.is_binary() => {
+                let right = self.pop(addr);
+                let left = self.pop(addr);
+                let val = match op {
+                    OpCode::OpAdd => self.builder.ins().iadd(left, right),
+                    OpCode::OpSubtract => self.builder.ins().isub(left, right),
+                    OpCode::OpMultiply => self.builder.ins().imul(left, right),
+                    OpCode::OpDivide => {
+                        let ok = self.builder.ins().icmp_imm(IntCC::NotEqual, right, 0);
+                        self.check(ok, Exit::DivisionByZero, addr, None);
+                        // sdiv traps on the overflow of the smallest value divided by -1, negating wraps instead
+                        let minus_one = self.builder.ins().icmp_imm(IntCC::Equal, right, -1);
+                        let one = self.builder.ins().iconst(types::I64, 1);
+                        let divisor = self.builder.ins().select(minus_one, one, right);
+                        let quotient = self.builder.ins().sdiv(left, divisor);
+                        let negated = self.builder.ins().ineg(left);
+                        self.builder.ins().select(minus_one, negated, quotient)
+                    }
+                    OpCode::OpModulo => {
+                        let ok = self.builder.ins().icmp_imm(IntCC::NotEqual, right, 0);
+                        self.check(ok, Exit::DivisionByZero, addr, None);
+                        // srem traps like sdiv, the remainder of a division by -1 is the one of a division by 1: 0
+                        let minus_one = self.builder.ins().icmp_imm(IntCC::Equal, right, -1);
+                        let one = self.builder.ins().iconst(types::I64, 1);
+                        let divisor = self.builder.ins().select(minus_one, one, right);
+                        self.builder.ins().srem(left, divisor)
+                    }
+                    OpCode::OpAnd => self.builder.ins().band(left, right),
+                    OpCode::OpOr => self.builder.ins().bor(left, right),
+                    OpCode::OpXor => self.builder.ins().bxor(left, right),
+                    OpCode::LogicalAnd | OpCode::LogicalOr => {
+                        let left = self.builder.ins().icmp_imm(IntCC::NotEqual, left, 0);
+                        let right = self.builder.ins().icmp_imm(IntCC::NotEqual, right, 0);
+                        let val = if op == OpCode::LogicalAnd { self.builder.ins().band(left, right) } else { self.builder.ins().bor(left, right) };
+                        self.builder.ins().uextend(types::I64, val)
+                    }
+                    _ => {
+                        let cc = match op {
+                            OpCode::CompareEq => IntCC::Equal,
+                            OpCode::CompareNotEq => IntCC::NotEqual,
+                            OpCode::CompareLT => IntCC::SignedLessThan,
+                            OpCode::CompareGT => IntCC::SignedGreaterThan,
+                            OpCode::CompareLTEq => IntCC::SignedLessThanOrEqual,
+                            _ => IntCC::SignedGreaterThanOrEqual,
+                        };
+                        let cmp = self.builder.ins().icmp(cc, left, right);
+                        self.builder.ins().uextend(types::I64, cmp)
+                    }
+                };
+                let val = self.wrap(val);
+                self.push(val, addr);
+            }
+
+            OpCode::Jump => {
+                let pc = end.checked_add_signed(arg0 as isize).unwrap_or_default();
+                self.jump_to(instr.target, pc);
+                return Ok(());
+            }
+            OpCode::JumpIfFalse => {
+                if index + 1 >= self.blocks.len() {
+                    return Err(self.unsupported(instr));
+                }
+                let val = self.pop(addr);
+                let jump = self.builder.create_block();
+                self.builder.ins().brif(val, self.blocks[index + 1], &[], jump, &[]);
+                self.builder.switch_to_block(jump);
+                let pc = end.checked_add_signed(arg0 as isize).unwrap_or_default();
+                self.jump_to(instr.target, pc);
+                return Ok(());
+            }
+
+            OpCode::PutString | OpCode::PutStringRef => {
+                let bytes = match instr.op {
+                    // without the op code and the terminating 0
+                    OpCode::PutString => &self.vm.program()[(addr + 1)..(end - 1)],
+                    _ => match usize::try_from(arg0).ok().and_then(|i| self.vm.strings().get(i)) {
+                        Some(val) => val.as_slice(),
+                        None => return Err(self.unsupported(instr)),
+                    },
+                };
+                let str = match self.vm.decode_string(bytes) {
+                    Ok(val) => val.into_owned(),
+                    Err(_) => return Err(self.unsupported(instr)),
+                };
+                let index = self.builder.ins().iconst(types::I64, self.strings.len() as i64);
+                self.strings.push(str);
+                self.builder.ins().call(self.print, &[self.runtime, index]);
+            }
+
+            OpCode::Pop => {
+                self.pop(addr);
+            }
+            OpCode::Swap => {
+                let bytes = self.pop(addr);
+                let val = self.read(bytes, addr);
+                self.push(val, addr);
+            }
+            // array accesses can reach the frames of callers, only the interpreter walks them for the bounds check
+            OpCode::Put | OpCode::Get | OpCode::OpAddAddr => return Err(self.unsupported(instr)),
+
+            OpCode::EndOfCode => {
+                let finished = self.builder.ins().iconst(types::I64, Exit::Finished.status(addr));
+                self.builder.ins().jump(self.exit, &[finished]);
+                return Ok(());
+            }
+            _ => return Err(self.unsupported(instr)),
+        }
+        // on to the next instruction, running past the last one is an invalid program counter like in the interpreter
+        let next = Some(index + 1).filter(|next| *next < self.blocks.len());
+        self.jump_to(next, end);
+        Ok(())
+    }
+}
+
+// the compiled program, the module owns its code
+struct Program {
+    module: JITModule,
+    run: extern "C" fn(*mut State) -> i64,
+    strings: Vec<String>,
+}
+
+fn build(vm: &PL0VM, execution: &Execution) -> Result<Program, String> {
+    let mut builder = JITBuilder::new(default_libcall_names()).map_err(|err| err.to_string())?;
+    builder.symbol("pl0_output", output as *const u8);
+    builder.symbol("pl0_print", print as *const u8);
+    builder.symbol("pl0_input", input as *const u8);
+    builder.symbol("pl0_zero", zero as *const u8);
+    let mut module = JITModule::new(builder);
+    let ptr = module.target_config().pointer_type();
+
+    let mut import = |name: &str, params: &[types::Type], returns: bool| {
+        let mut sig = module.make_signature();
+        sig.params.extend(params.iter().map(|ty| AbiParam::new(*ty)));
+        if returns {
+            sig.returns.push(AbiParam::new(types::I64));
+        }
+        module.declare_function(name, Linkage::Import, &sig).map_err(|err| err.to_string())
+    };
+    let output_id = import("pl0_output", &[ptr, types::I64], false)?;
+    let print_id = import("pl0_print", &[ptr, types::I64], false)?;
+    let input_id = import("pl0_input", &[ptr, types::I64], true)?;
+    let zero_id = import("pl0_zero", &[ptr, types::I64, types::I64], false)?;
+
+    let mut sig = module.make_signature();
+    sig.params.push(AbiParam::new(ptr));
+    sig.returns.push(AbiParam::new(types::I64));
+    let run_id = module.declare_function("pl0_run", Linkage::Local, &sig).map_err(|err| err.to_string())?;
+    let mut ctx = module.make_context();
+    ctx.func.signature = sig;
+    let mut builder_ctx = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut ctx.func, &mut builder_ctx);
+
+    let entry = builder.create_block();
+    builder.append_block_params_for_function_params(entry);
+    builder.switch_to_block(entry);
+    let state = builder.block_params(entry)[0];
+    let stack = builder.ins().load(ptr, MemFlags::trusted(), state, std::mem::offset_of!(State, stack) as i32);
+    let frame_ptrs = builder.ins().load(ptr, MemFlags::trusted(), state, std::mem::offset_of!(State, frame_ptrs) as i32);
+    let runtime = builder.ins().load(ptr, MemFlags::trusted(), state, std::mem::offset_of!(State, runtime) as i32);
+    let (sp, fp, proc_i) = (Variable::new(0), Variable::new(1), Variable::new(2));
+    for var in [sp, fp, proc_i] {
+        builder.declare_var(var, types::I64);
+        let zero = builder.ins().iconst(types::I64, 0);
+        builder.def_var(var, zero);
+    }
+
+    let exit = builder.create_block();
+    builder.append_block_param(exit, types::I64);
+    let bad_pc = builder.create_block();
+    builder.append_block_param(bad_pc, types::I64);
+    let code = execution.code();
+    let mut vars = vec![0; execution.procedure_count()];
+    for entry in code.instrs.iter().filter(|instr| instr.op == OpCode::EntryProc) {
+        vars[entry.args[1] as usize] = entry.args[2].max(0) as i64;
+    }
+    let blocks: Vec<Block> = code.instrs.iter().map(|_| builder.create_block()).collect();
+    let start = match code.at(execution.pc()) {
+        Some(val) => val,
+        None => return Err(t!("pl0.error.invalid_pc", pc = execution.pc():{:04X}).to_string()),
+    };
+    builder.ins().jump(blocks[start], &[]);
+
+    let output = module.declare_func_in_func(output_id, builder.func);
+    let print = module.declare_func_in_func(print_id, builder.func);
+    let input = module.declare_func_in_func(input_id, builder.func);
+    let zero = module.declare_func_in_func(zero_id, builder.func);
+    let mut compiler = Compiler {
+        vm, execution, builder, blocks, exit, bad_pc, state, stack, frame_ptrs, runtime, sp, fp, proc_i,
+        output, print, input, zero, strings: vec![], vars, proc_id: 0,
+    };
+    for (index, instr) in code.instrs.iter().enumerate() {
+        compiler.builder.switch_to_block(compiler.blocks[index]);
+        compiler.compile(index, instr)?;
+    }
+
+    let builder = &mut compiler.builder;
+    builder.switch_to_block(bad_pc);
+    let pc = builder.block_params(bad_pc)[0];
+    builder.ins().store(MemFlags::trusted(), pc, state, std::mem::offset_of!(State, detail) as i32);
+    let status = builder.ins().iconst(types::I64, Exit::InvalidPc.status(0));
+    builder.ins().jump(exit, &[status]);
+    builder.switch_to_block(exit);
+    let status = builder.block_params(exit)[0];
+    builder.ins().return_(&[status]);
+    builder.seal_all_blocks();
+    let strings = std::mem::take(&mut compiler.strings);
+    compiler.builder.finalize();
+
+    module.define_function(run_id, &mut ctx).map_err(|err| err.to_string())?;
+    module.clear_context(&mut ctx);
+    module.finalize_definitions().map_err(|err| err.to_string())?;
+    let run = unsafe { std::mem::transmute::<*const u8, extern "C" fn(*mut State) -> i64>(module.get_finalized_function(run_id)) };
+    Ok(Program { module, run, strings })
+}
+
+impl PL0VM {
+    // run like execute, but compiled to native code first; while logging every instruction or if the program uses
+    // something the compiler doesn't support, the interpreter runs it instead
+    pub fn execute_jit(&self) -> i64 {
+        let execution = match self.start() {
+            Some(val) => val,
+            None => return START_FAILED_STATUS,
+        };
+        // the compiled code calculates with plain signed cells and reads stdin
+        if log::log_enabled!(log::Level::Debug) || self.bignum() || self.unsigned || self.stdin_values.is_some() {
+            return self.execute();
+        }
+        let program = match build(self, &execution) {
+            Ok(val) => val,
+            Err(reason) => {
+                log::info!("{}", t!("jit.fallback", reason = reason));
+                return self.execute();
+            }
+        };
+
+        let mut stack = vec![0i64; STACK_CELLS as usize];
+        let mut frame_ptrs = vec![0i64; execution.procedure_count()];
+        let mut out = BufWriter::new(stdout());
+        let mut runtime = Runtime { vm: self, out: &mut out, strings: program.strings, input_error: None };
+        let mut state = State {
+            stack: stack.as_mut_ptr(),
+            frame_ptrs: frame_ptrs.as_mut_ptr(),
+            detail: 0,
+            runtime: &mut runtime as *mut Runtime as *mut c_void,
+        };
+        let (exit, addr) = Exit::from_status((program.run)(&mut state));
+        let detail = state.detail;
+        runtime.out.flush().expect("Could not write program output");
+        match exit {
+            Exit::Finished => {},
+            Exit::DivisionByZero => { self.runtime_error(addr, &t!("pl0.error.division_by_zero")); },
+            Exit::StackRead => { self.runtime_error(addr, &t!("pl0.error.invalid_stack_read")); },
+            Exit::StackFull => { self.runtime_error(addr, &t!("jit.stack_full", count = STACK_CELLS)); },
+            Exit::Unaligned => { self.runtime_error(addr, &t!("pl0.error.unaligned_address", addr = detail)); },
+            Exit::AddressRange => { self.runtime_error(addr, &t!("pl0.error.address_range", addr = detail)); },
+            Exit::ShiftRange => { self.runtime_error(addr, &t!("pl0.error.shift_range", amount = detail, bits = self.data_size() * 8)); },
+            Exit::Input => match runtime.input_error.take() {
+                Some(NoInput::Timeout(limit)) => {
+                    self.runtime_error(addr, &t!("pl0.error.input_timeout", secs = limit.as_secs_f64()));
+                    return INPUT_TIMEOUT_STATUS;
+                },
+                Some(NoInput::Error(err)) => { self.runtime_error(addr, &err); },
+                None => {},
+            },
+            Exit::InvalidPc => { self.runtime_error(detail as usize, &t!("pl0.error.invalid_pc", pc = detail:{:04X})); },
+        }
+        unsafe { program.module.free_memory(); }
+        // Halt isn't compiled, programs using it run in the interpreter
+        0
+    }
+}
@@ -0,0 +1,225 @@
+use crate::format::CHECKSUM_TAG;
+use crate::opcodes::OpCode;
+use crate::pl0_vm::{arg_size, ARG_SIZE, PL0VM};
+use rust_i18n::t;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// JSON representation of a program, for tools that don't want to deal with the byte layout.
+// On import, procedure lengths, the procedure count and (if "target" is given) jump offsets
+// are calculated automatically, so the first argument of EntryProc is ignored.
+#[derive(Serialize, Deserialize)]
+struct JsonProgram {
+    // bytes per value: 1, 2, 4 or 8
+    arch: i16,
+    #[serde(default)]
+    big_endian: bool,
+    code: Vec<JsonInstruction>,
+    constants: Vec<i64>,
+    // additional file sections, tag => data as hex
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    sections: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonInstruction {
+    // only informational, ignored on import
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<usize>,
+    // no path from the EntryProc of the procedure leads to the instruction, also ignored on import
+    #[serde(default, skip_serializing_if = "is_false")]
+    unreachable: bool,
+    op: String,
+    // 4 byte arguments (Wide prefix), normal arguments have to fit into 2 bytes
+    #[serde(default, skip_serializing_if = "is_false")]
+    wide: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<i32>,
+    // contents of PutString
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    string: Option<String>,
+    // index of the instruction a Jump/JumpIfFalse goes to (the length of code means the end of the code)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<usize>,
+}
+
+fn is_false(val: &bool) -> bool {
+    !val
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+pub(crate) fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) { return None; }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..(i + 2))?, 16).ok()).collect()
+}
+
+impl PL0VM {
+    pub fn to_json(&self) -> Result<String, String> {
+        let image = self.program_image()?;
+        let (instructions, code_end) = (&image.instructions, image.code_end);
+        let unreachable = self.unreachable_code()?;
+        let mut code = vec![];
+        for instr in instructions {
+            let mut json = JsonInstruction {
+                address: Some(instr.addr), unreachable: unreachable.contains(&instr.addr), op: format!("{:?}", instr.op),
+                wide: instr.wide, args: vec![], string: None, target: None,
+            };
+            match instr.op {
+                OpCode::PutString => {
+                    let bytes = &self.program[(instr.addr + 1)..(instr.addr + instr.len - 1)];
+                    json.string = Some(self.decode_string(bytes)?.into_owned());
+                },
+                _ => {
+                    json.args = (0..instr.op.arg_count()).map(|i| self.read_operand(instr.arg_addr(i), instr.wide).expect("length checked while decoding")).collect();
+                },
+            }
+            if let OpCode::Jump | OpCode::JumpIfFalse = instr.op {
+                let target = (instr.addr + instr.len).checked_add_signed(json.args[0] as isize);
+                json.target = instructions.iter().position(|other| Some(other.addr) == target)
+                    .or(if target == Some(code_end) { Some(instructions.len()) } else { None });
+            }
+            code.push(json);
+        }
+
+        let constants = image.constants.clone();
+
+        let program = JsonProgram {
+            arch: self.read_arg(ARG_SIZE).unwrap_or_default(),
+            big_endian: self.is_big_endian(),
+            code,
+            constants,
+            sections: self.sections().iter()
+                .filter(|section| &section.tag != CHECKSUM_TAG)
+                .map(|section| (String::from_utf8_lossy(&section.tag).to_string(), to_hex(&section.data)))
+                .collect(),
+        };
+        Ok(serde_json::to_string_pretty(&program).expect("serializing can't fail"))
+    }
+
+    pub fn from_json(json: &str) -> Result<PL0VM, String> {
+        let program: JsonProgram = match serde_json::from_str(json) {
+            Ok(val) => val,
+            Err(err) => return Err(t!("json.parse_error", err = err).to_string()),
+        };
+        if !matches!(program.arch, 1 | 2 | 4 | 8) {
+            return Err(t!("pl0.arch_invalid", arch = program.arch:{:04X}).to_string());
+        }
+        // encoded argument, None if it doesn't fit into its size
+        let arg_bytes = |val: i32, wide: bool| -> Option<Vec<u8>> {
+            Some(match (wide, program.big_endian) {
+                (true, true) => val.to_be_bytes().to_vec(),
+                (true, false) => val.to_le_bytes().to_vec(),
+                (false, true) => i16::try_from(val).ok()?.to_be_bytes().to_vec(),
+                (false, false) => i16::try_from(val).ok()?.to_le_bytes().to_vec(),
+            })
+        };
+
+        // first pass: op codes, addresses and lengths of all instructions
+        let mut ops = vec![];
+        let mut addrs = vec![];
+        let mut lens = vec![];
+        let mut addr = 4;
+        for (i, instr) in program.code.iter().enumerate() {
+            let op = match OpCode::from_name(&instr.op) {
+                Some(op) if op != OpCode::Wide => op,
+                _ => return Err(t!("json.unknown_op", op = instr.op, i = i).to_string()),
+            };
+            if instr.wide && op.arg_count() == 0 {
+                return Err(t!("pl0.error.invalid_wide", addr = addr:{:04X}).to_string());
+            }
+            let len = match op {
+                OpCode::PutString => 1 + instr.string.as_ref().map(|str| str.len()).unwrap_or_default() + 1,
+                _ => instr.wide as usize + 1 + op.arg_count() * arg_size(instr.wide),
+            };
+            let jump_target = matches!(op, OpCode::Jump | OpCode::JumpIfFalse) && instr.target.is_some();
+            if op != OpCode::PutString && instr.args.len() != op.arg_count() && !jump_target {
+                return Err(t!("json.arg_count", op = instr.op, i = i, count = op.arg_count()).to_string());
+            }
+            if op == OpCode::PutString && instr.string.as_ref().is_none_or(|str| str.contains('\0')) {
+                return Err(t!("json.invalid_string", i = i).to_string());
+            }
+            ops.push(op);
+            addrs.push(addr);
+            lens.push(len);
+            addr += len;
+        }
+        let code_end = addr;
+
+        // second pass: encode, with calculated procedure lengths and jump offsets
+        let procedure_count = ops.iter().filter(|op| **op == OpCode::EntryProc).count() as i16;
+        let mut bytes = vec![];
+        bytes.extend(arg_bytes(procedure_count as i32, false).expect("i16 always fits"));
+        bytes.extend(arg_bytes(program.arch as i32, false).expect("i16 always fits"));
+        for (i, (instr, op)) in program.code.iter().zip(&ops).enumerate() {
+            let arg_range = || t!("json.arg_range", i = i).to_string();
+            if instr.wide {
+                bytes.push(OpCode::Wide.into());
+            }
+            bytes.push((*op).into());
+            match op {
+                OpCode::PutString => {
+                    bytes.extend(instr.string.as_ref().expect("checked in first pass").as_bytes());
+                    bytes.push(0);
+                },
+                OpCode::EntryProc => {
+                    let next = ops.iter().enumerate().skip(i + 1).find(|(_, op)| **op == OpCode::EntryProc).map(|(j, _)| addrs[j]).unwrap_or(code_end);
+                    match i32::try_from(next - addrs[i]).ok().and_then(|len| arg_bytes(len, instr.wide)) {
+                        Some(len) => bytes.extend(len),
+                        None => return Err(t!("pl0.patch.proc_length", addr = addrs[i]:{:04X}).to_string()),
+                    }
+                    for &arg in instr.args.iter().skip(1) {
+                        bytes.extend(arg_bytes(arg, instr.wide).ok_or_else(arg_range)?);
+                    }
+                },
+                OpCode::Jump | OpCode::JumpIfFalse if instr.target.is_some() => {
+                    let target = match instr.target.and_then(|target| if target == ops.len() { Some(code_end) } else { addrs.get(target).copied() }) {
+                        Some(val) => val,
+                        None => return Err(t!("json.invalid_target", i = i).to_string()),
+                    };
+                    // offsets are relative to the end of the jump instruction
+                    match i32::try_from(target as isize - (addrs[i] + lens[i]) as isize).ok().and_then(|offset| arg_bytes(offset, instr.wide)) {
+                        Some(offset) => bytes.extend(offset),
+                        None => return Err(t!("pl0.patch.jump_range", addr = addrs[i]:{:04X}).to_string()),
+                    }
+                },
+                _ => {
+                    for &arg in &instr.args {
+                        bytes.extend(arg_bytes(arg, instr.wide).ok_or_else(arg_range)?);
+                    }
+                },
+            }
+        }
+
+        for (i, &constant) in program.constants.iter().enumerate() {
+            let fits = match program.arch {
+                1 => i8::try_from(constant).is_ok(),
+                2 => i16::try_from(constant).is_ok(),
+                4 => i32::try_from(constant).is_ok(),
+                _ => true,
+            };
+            if !fits {
+                return Err(t!("json.constant_range", i = i, val = constant).to_string());
+            }
+            let data = &constant.to_le_bytes()[..(program.arch as usize)];
+            match program.big_endian {
+                true => bytes.extend(data.iter().rev()),
+                false => bytes.extend(data),
+            }
+        }
+
+        let mut pl0vm = PL0VM::from_bytes(bytes);
+        for (tag, data) in &program.sections {
+            match (<[u8; 4]>::try_from(tag.as_bytes()), from_hex(data)) {
+                (Ok(tag), Some(data)) => pl0vm.set_section(&tag, data),
+                _ => return Err(t!("json.invalid_section", tag = tag).to_string()),
+            }
+        }
+        // sections are only parsed while loading, so load the complete file again
+        if !program.sections.is_empty() {
+            pl0vm = PL0VM::from_bytes(pl0vm.to_file_bytes());
+        }
+        Ok(pl0vm)
+    }
+}
@@ -0,0 +1,45 @@
+rust_i18n::i18n!("locales", fallback = "en");
+
+pub mod batch;
+pub mod callgraph;
+pub mod cfg;
+pub mod coredump;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
+pub mod diag;
+pub mod expr;
+pub mod flamegraph;
+pub mod format;
+pub mod interrupt;
+pub mod json;
+pub mod lines;
+pub mod logger;
+pub mod metadata;
+pub mod opcodes;
+pub mod pl0_vm;
+pub mod pool;
+pub mod patch;
+pub mod profile;
+pub mod replay;
+pub mod stackusage;
+pub mod symbols;
+pub mod trace;
+pub mod verifier;
+pub mod xref;
+
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "jit")]
+pub mod jit;
+
+#[cfg(feature = "bignum")]
+mod bignum;
+mod decoder;
+mod files;
+mod flow;
+mod image;
+mod heap;
+mod random;
+mod stack;
+mod telemetry;
@@ -0,0 +1,67 @@
+// Source line table, stored in the LINE section:
+//   <file count: 2 bytes> <file names, each terminated by 0x00>
+//   entries: <address: 4 bytes> <file index: 2 bytes> <line: 4 bytes>
+// Entries are sorted by address, each one applies to all code up to the address of the next one.
+
+#[derive(Debug, Clone)]
+pub struct LineEntry {
+    pub addr: u32,
+    pub file: u16,
+    pub line: u32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct LineTable {
+    pub files: Vec<String>,
+    pub entries: Vec<LineEntry>,
+}
+
+impl LineTable {
+    // source file and line of the code at addr
+    pub fn lookup(&self, addr: usize) -> Option<(&str, u32)> {
+        let index = self.entries.partition_point(|entry| entry.addr as usize <= addr);
+        let entry = self.entries.get(index.checked_sub(1)?)?;
+        Some((self.files.get(entry.file as usize)?, entry.line))
+    }
+
+    // returns None if the section data is malformed
+    pub fn parse(data: &[u8]) -> Option<LineTable> {
+        let file_count = u16::from_le_bytes(data.get(0..2)?.try_into().ok()?);
+        let mut pos = 2;
+        let mut files = vec![];
+        for _ in 0..file_count {
+            let len = data.get(pos..)?.iter().position(|&b| b == 0)?;
+            files.push(String::from_utf8(data[pos..(pos + len)].to_vec()).ok()?);
+            pos += len + 1;
+        }
+
+        let mut entries = vec![];
+        while pos < data.len() {
+            let entry = data.get(pos..(pos + 10))?;
+            entries.push(LineEntry {
+                addr: u32::from_le_bytes(entry[0..4].try_into().ok()?),
+                file: u16::from_le_bytes(entry[4..6].try_into().ok()?),
+                line: u32::from_le_bytes(entry[6..10].try_into().ok()?),
+            });
+            pos += 10;
+        }
+        entries.sort_by_key(|entry| entry.addr);
+        Some(LineTable { files, entries })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = (self.files.len() as u16).to_le_bytes().to_vec();
+        for file in &self.files {
+            bytes.extend(file.as_bytes());
+            bytes.push(0);
+        }
+        let mut entries = self.entries.clone();
+        entries.sort_by_key(|entry| entry.addr);
+        for entry in entries {
+            bytes.extend(entry.addr.to_le_bytes());
+            bytes.extend(entry.file.to_le_bytes());
+            bytes.extend(entry.line.to_le_bytes());
+        }
+        bytes
+    }
+}
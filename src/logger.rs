@@ -0,0 +1,41 @@
+use log::{LevelFilter, Log, Metadata, Record};
+
+// Writes log messages to stderr, so they don't mix with the output of the program. Only the pl0_vm_rs binary installs
+// it, programs embedding the VM see the messages in their own logger.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+// level from --debug or -v, without them RUST_LOG decides, otherwise only warnings and errors are logged
+pub fn init(level: Option<LevelFilter>) {
+    let level = level
+        .or_else(|| std::env::var("RUST_LOG").ok().and_then(|filter| parse_filter(&filter)))
+        .unwrap_or(LevelFilter::Warn);
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+// RUST_LOG in the usual form: comma separated directives, either a level ("debug") or a level for a crate
+// ("pl0_vm_rs=debug"), the last directive that applies wins
+fn parse_filter(filter: &str) -> Option<LevelFilter> {
+    filter.split(',')
+        .filter_map(|directive| match directive.split_once('=') {
+            Some((target, level)) if target.trim() == env!("CARGO_CRATE_NAME") => level.trim().parse().ok(),
+            Some(_) => None,
+            None => directive.trim().parse().ok(),
+        })
+        .next_back()
+}
@@ -1,63 +1,745 @@
+use log::LevelFilter;
 use std::env;
+use std::io::{stdout, ErrorKind, Write};
 use std::process::exit;
-use crate::pl0_vm::PL0VM;
+use std::time::Duration;
+use pl0_vm_rs::batch::run_batch;
+use pl0_vm_rs::coredump::CoreDump;
+use pl0_vm_rs::coverage::Coverage;
+use pl0_vm_rs::pl0_vm::{parse_number, OutputFormat, Step, StringDecoding, PL0VM};
+use pl0_vm_rs::diag::ColorMode;
+use pl0_vm_rs::replay::{format_replay, parse_replay, Replay};
+use pl0_vm_rs::{diag, diagln};
 use rust_i18n::t;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 rust_i18n::i18n!("locales", fallback = "en");
 
-mod pl0_vm;
-mod opcodes;
-
 fn main() {
     let mut analyze_only = false;
-    let mut debug = false;
+    // most detailed log messages to show, None leaves it to RUST_LOG
+    let mut log_level: Option<LevelFilter> = None;
+    let mut debugger = false;
+    let mut dap = false;
+    let mut tui = false;
+    // debugger commands from --command-file and --ex, in the order they were given
+    let mut debugger_script: Vec<String> = vec![];
     let mut help = false;
+    let mut checksum: Option<&str> = None;
+    let mut selftest: Option<&str> = None;
+    let mut compress = false;
+    let mut convert = false;
+    let mut verify = false;
+    let mut callgraph = false;
+    let mut xref = false;
+    let mut cfg = false;
+    // procedure of --cfg, main if not given
+    let mut cfg_proc: Option<&str> = None;
+    // file --cfg writes to, stdout if not given
+    let mut cfg_output: Option<&str> = None;
+    // -o and --proc take the next argument
+    let mut value_for: Option<&str> = None;
+    let mut record: Option<&str> = None;
+    let mut replay: Option<&str> = None;
+    let mut core: Option<&str> = None;
+    let mut trace: Option<&str> = None;
+    let mut chrome_trace: Option<&str> = None;
+    let mut flamegraph: Option<&str> = None;
+    let mut profile = false;
+    let mut stats = false;
+    let mut hot_loops: Option<&str> = None;
+    let mut coverage: Option<&str> = None;
+    let mut inspect_core = false;
+    // compile to native code instead of interpreting, if built with the jit feature
+    let mut jit = false;
+    let mut tail_calls = true;
+    let mut fast = false;
+    let mut heap: Option<&str> = None;
+    let mut seed: Option<&str> = None;
+    let mut virtual_clock = false;
+    let mut prompt: Option<&str> = None;
+    let mut input_retries: Option<&str> = None;
+    let mut stdin_values: Option<&str> = None;
+    let mut input_timeout: Option<&str> = None;
+    let mut string_decoding: Option<&str> = None;
+    let mut output_format = OutputFormat::default();
+    let mut unsigned = false;
+    let mut bignum = false;
+    let mut allow_fs: Option<&str> = None;
+    let mut allow_env: Vec<String> = vec![];
+    // run all files given on this many threads, see batch.rs
+    let mut jobs: Option<&str> = None;
     let mut filename: Option<&str> = None;
+    // every file given, only batch runs use more than the last one
+    let mut files: Vec<String> = vec![];
     let args: Vec<String> = env::args().collect();
 
     let locale = sys_locale::get_locale().unwrap_or_else(|| "en".to_string());
     let lang = &locale[0..2];
     rust_i18n::set_locale(lang);
 
+    // everything after -- is for the program, see set_program_args
+    let (args, program_args) = match args.iter().position(|arg| arg == "--") {
+        Some(i) => (&args[..i], &args[(i + 1)..]),
+        None => (&args[..], &[][..]),
+    };
     args.iter().skip(1).for_each(|arg| {
+        if let Some(flag) = value_for.take() {
+            match flag {
+                "-o" => cfg_output = Some(arg),
+                _ => cfg_proc = Some(arg),
+            }
+            return;
+        }
         if arg == "--analyze" || arg == "-a" {
             analyze_only = true;
         } else if arg == "--debug" || arg == "-d" {
-            debug = true;
+            log_level = Some(LevelFilter::Debug);
+        } else if arg == "--verbose" || arg == "-v" {
+            log_level = log_level.max(Some(LevelFilter::Info));
+        } else if arg == "--debugger" {
+            debugger = true;
+        } else if arg == "--tui" {
+            tui = true;
+        } else if arg == "--dap" {
+            dap = true;
+        } else if let Some(file) = arg.strip_prefix("--command-file=") {
+            match std::fs::read_to_string(file) {
+                // empty lines and comments starting with # are left out
+                Ok(script) => debugger_script.extend(script.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')).map(str::to_string)),
+                Err(_) => {
+                    diagln!("{}", t!("file_error", file = file));
+                    exit(1);
+                },
+            }
+        } else if let Some(command) = arg.strip_prefix("--ex=") {
+            debugger_script.push(command.to_string());
         } else if arg == "--help" || arg == "-h" {
             help = true;
+        } else if arg == "--convert" {
+            convert = true;
+        } else if arg == "--verify" {
+            verify = true;
+        } else if arg == "--callgraph" {
+            callgraph = true;
+        } else if arg == "--xref" {
+            xref = true;
+        } else if arg == "--cfg" {
+            cfg = true;
+        } else if let Some(proc_id) = arg.strip_prefix("--proc=") {
+            cfg_proc = Some(proc_id);
+        } else if arg == "--proc" || arg == "-o" {
+            value_for = Some(arg);
+        } else if let Some(file) = arg.strip_prefix("--record=") {
+            record = Some(file);
+        } else if let Some(file) = arg.strip_prefix("--replay=") {
+            replay = Some(file);
+        } else if let Some(file) = arg.strip_prefix("--core=") {
+            core = Some(file);
+        } else if let Some(file) = arg.strip_prefix("--trace=") {
+            trace = Some(file);
+        } else if let Some(file) = arg.strip_prefix("--chrome-trace=") {
+            chrome_trace = Some(file);
+        } else if let Some(file) = arg.strip_prefix("--flamegraph=") {
+            flamegraph = Some(file);
+        } else if arg == "--stats" {
+            stats = true;
+        } else if arg == "--profile" {
+            profile = true;
+        } else if arg == "--hot-loops" {
+            hot_loops = Some("100");
+        } else if let Some(min) = arg.strip_prefix("--hot-loops=") {
+            hot_loops = Some(min);
+        } else if let Some(file) = arg.strip_prefix("--coverage=") {
+            coverage = Some(file);
+        } else if arg == "--inspect-core" {
+            inspect_core = true;
+        } else if arg == "--jit" {
+            jit = true;
+        } else if arg == "--no-tail-calls" {
+            tail_calls = false;
+        } else if arg == "--fast" {
+            fast = true;
+        } else if let Some(bytes) = arg.strip_prefix("--heap=") {
+            heap = Some(bytes);
+        } else if let Some(number) = arg.strip_prefix("--seed=") {
+            seed = Some(number);
+        } else if arg == "--virtual-clock" {
+            virtual_clock = true;
+        } else if let Some(text) = arg.strip_prefix("--prompt=") {
+            prompt = Some(text);
+        } else if let Some(count) = arg.strip_prefix("--input-retries=") {
+            input_retries = Some(count);
+        } else if let Some(values) = arg.strip_prefix("--stdin-values=") {
+            stdin_values = Some(values);
+        } else if let Some(secs) = arg.strip_prefix("--input-timeout=") {
+            input_timeout = Some(secs);
+        } else if let Some(decoding) = arg.strip_prefix("--strings=") {
+            string_decoding = Some(decoding);
+        } else if arg == "--hex" {
+            output_format.hex = true;
+        } else if arg == "--pad" {
+            output_format.pad = true;
+        } else if arg == "--no-newline" {
+            output_format.no_newline = true;
+        } else if let Some(stream) = arg.strip_prefix("--diagnostics=") {
+            match stream {
+                "stdout" => pl0_vm_rs::diag::set_stdout(true),
+                "stderr" => pl0_vm_rs::diag::set_stdout(false),
+                _ => {
+                    diagln!("{}", t!("invalid_diagnostics", stream = stream));
+                    exit(1);
+                },
+            }
+        } else if let Some(mode) = arg.strip_prefix("--color=") {
+            match mode {
+                "auto" => pl0_vm_rs::diag::set_color(ColorMode::Auto),
+                "always" => pl0_vm_rs::diag::set_color(ColorMode::Always),
+                "never" => pl0_vm_rs::diag::set_color(ColorMode::Never),
+                _ => {
+                    diagln!("{}", t!("invalid_color", mode = mode));
+                    exit(1);
+                },
+            }
+        } else if arg == "--unsigned" {
+            unsigned = true;
+        } else if arg == "--bignum" {
+            bignum = true;
+        } else if let Some(dir) = arg.strip_prefix("--allow-fs=") {
+            allow_fs = Some(dir);
+        } else if let Some(names) = arg.strip_prefix("--allow-env=") {
+            allow_env.extend(names.split(',').filter(|name| !name.is_empty()).map(str::to_string));
+        } else if let Some(count) = arg.strip_prefix("--jobs=") {
+            jobs = Some(count);
+        } else if arg == "--compress" {
+            compress = true;
+        } else if let Some(mode) = arg.strip_prefix("--checksum=") {
+            checksum = Some(mode);
+        } else if arg == "--selftest" {
+            selftest = Some("verify");
+        } else if let Some(mode) = arg.strip_prefix("--selftest=") {
+            selftest = Some(mode);
         } else if arg == "--lang=de" {
             rust_i18n::set_locale("de");
         } else if arg == "--lang=en" {
             rust_i18n::set_locale("en");
         } else {
             filename = Some(arg);
+            files.push(arg.to_string());
         }
     });
 
+    if let Some(flag) = value_for {
+        diagln!("{}", t!("missing_value", flag = flag));
+        exit(1);
+    }
+
+    pl0_vm_rs::logger::init(log_level);
+
     if args.len() <= 1 || help {
         print!("{}", t!("help", version = VERSION));
         exit(0);
     }
 
     if filename.is_none() {
-        println!("{}", t!("no_filename"));
+        diagln!("{}", t!("no_filename"));
+        return;
+    }
+
+    if let Some(jobs) = jobs {
+        let jobs = match jobs.parse() {
+            Ok(val) => val,
+            Err(_) => {
+                diagln!("{}", t!("batch.invalid_jobs", jobs = jobs));
+                exit(1);
+            },
+        };
+        // all programs get the seed and read the numbers of the replay file
+        let replay = match replay.map(|source| std::fs::read_to_string(source).map_err(|_| t!("file_error", file = source).to_string()).and_then(|text| parse_replay(&text))) {
+            Some(Ok(val)) => val,
+            Some(Err(err)) => {
+                diagln!("{}", err);
+                exit(1);
+            },
+            None => Replay::default(),
+        };
+        let results = run_batch(&files, jobs, &replay, fast);
+        for result in &results {
+            println!("{}", t!("batch.header", file = result.file));
+            stdout().write_all(&result.output).expect("Could not write program output");
+            diag!("{}", result.errors);
+        }
+        let finished = results.iter().filter(|result| result.state == Some(Step::Finished)).count();
+        let failed = results.iter().filter(|result| result.state.is_none()).count();
+        diagln!("{}", t!("batch.summary", finished = finished, count = results.len(), errors = results.len() - finished - failed, failed = failed));
+        exit(if finished == results.len() { 0 } else { 1 });
+    }
+
+    // core files contain the program, so they are opened without it
+    if inspect_core {
+        let source = filename.unwrap();
+        let dump = match std::fs::read_to_string(source) {
+            Ok(json) => match CoreDump::from_json(&json) {
+                Ok(dump) => dump,
+                Err(err) => {
+                    diagln!("{}", err);
+                    exit(1);
+                },
+            },
+            Err(_) => {
+                diagln!("{}", t!("file_error", file = source));
+                return;
+            },
+        };
+        let pl0vm = match dump.load_program() {
+            Ok(pl0vm) => pl0vm,
+            Err(err) => {
+                diagln!("{}", err);
+                exit(1);
+            },
+        };
+        match dump.restore(&pl0vm) {
+            Ok(execution) => pl0_vm_rs::debugger::run_attached(&pl0vm, execution),
+            Err(err) => {
+                diagln!("{}", err);
+                exit(1);
+            },
+        }
+        return;
+    }
+
+    // JSON files can only be converted back to bytecode
+    if convert && filename.unwrap().ends_with(".json") {
+        let source = filename.unwrap();
+        let target = format!("{}.cl0", source.trim_end_matches(".json"));
+        let pl0vm = match std::fs::read_to_string(source) {
+            Ok(json) => match PL0VM::from_json(&json) {
+                Ok(pl0vm) => pl0vm,
+                Err(err) => {
+                    diagln!("{}", err);
+                    exit(1);
+                },
+            },
+            Err(_) => {
+                diagln!("{}", t!("file_error", file = source));
+                return;
+            },
+        };
+        match pl0vm.save_to_file(&target) {
+            Ok(_) => diagln!("{}", t!("converted", file = target)),
+            Err(_) => diagln!("{}", t!("file_error", file = target)),
+        }
         return;
     }
 
-    let pl0vm = match PL0VM::from_file(debug, filename.unwrap()) {
+    let mut pl0vm = match PL0VM::from_file(filename.unwrap()) {
         Ok(pl0vm) => pl0vm,
-        Err(_) => {
-            println!("{}", t!("file_error", file = filename.unwrap()));
-            return
+        Err(err) => {
+            diagln!("{}", t!("file_error", file = filename.unwrap()));
+            // contents that couldn't be read, e.g. from hex text files
+            if err.kind() == ErrorKind::InvalidData { diagln!("{}", err); }
+            exit(1);
         }
     };
+    pl0vm.set_tail_calls(tail_calls);
+    if let Some(bytes) = heap {
+        match bytes.parse() {
+            Ok(val) => pl0vm.set_heap_limit(val),
+            Err(_) => {
+                diagln!("{}", t!("invalid_heap", bytes = bytes));
+                exit(1);
+            },
+        }
+    }
+    if let Some(number) = seed {
+        match number.parse() {
+            Ok(val) => pl0vm.set_seed(Some(val)),
+            Err(_) => {
+                diagln!("{}", t!("invalid_seed", seed = number));
+                exit(1);
+            },
+        }
+    }
+    if let Some(text) = prompt {
+        pl0vm.set_prompt(text.to_string());
+    }
+    if let Some(count) = input_retries {
+        match count.parse() {
+            Ok(val) => pl0vm.set_input_retries(val),
+            Err(_) => {
+                diagln!("{}", t!("invalid_input_retries", count = count));
+                exit(1);
+            },
+        }
+    }
+    if let Some(values) = stdin_values {
+        match values.split(',').filter(|value| !value.is_empty()).map(|value| parse_number(value).ok_or(value)).collect::<Result<Vec<i64>, _>>() {
+            Ok(val) => pl0vm.set_stdin_values(Some(val)),
+            Err(value) => {
+                diagln!("{}", t!("invalid_stdin_value", value = value));
+                exit(1);
+            },
+        }
+    }
+    if let Some(secs) = input_timeout {
+        match secs.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok()).filter(|limit| !limit.is_zero()) {
+            Some(limit) => pl0vm.set_input_timeout(Some(limit)),
+            None => {
+                diagln!("{}", t!("invalid_input_timeout", secs = secs));
+                exit(1);
+            },
+        }
+    }
+    if let Some(decoding) = string_decoding {
+        pl0vm.set_string_decoding(match decoding {
+            "error" => StringDecoding::Error,
+            "lossy" => StringDecoding::Lossy,
+            "latin1" => StringDecoding::Latin1,
+            _ => {
+                diagln!("{}", t!("invalid_string_decoding", decoding = decoding));
+                exit(1);
+            },
+        });
+    }
+    pl0vm.set_output_format(output_format);
+    pl0vm.set_unsigned(unsigned);
+    if bignum {
+        #[cfg(feature = "bignum")]
+        pl0vm.set_bignum(true);
+        #[cfg(not(feature = "bignum"))]
+        diagln!("{}", t!("bignum_unavailable"));
+    }
+    // stepping through a program or repeating a run mustn't depend on how fast it goes
+    pl0vm.set_virtual_clock(virtual_clock || debugger || dap || record.is_some() || replay.is_some());
+    match program_args.iter().map(|arg| arg.parse().map_err(|_| arg)).collect::<Result<Vec<i64>, _>>() {
+        Ok(val) => pl0vm.set_program_args(val),
+        Err(arg) => {
+            diagln!("{}", t!("invalid_program_arg", arg = arg));
+            exit(1);
+        },
+    }
+    pl0vm.allow_env(allow_env);
+    if let Some(dir) = allow_fs {
+        if !std::path::Path::new(dir).is_dir() {
+            diagln!("{}", t!("files.invalid_dir", dir = dir));
+            exit(1);
+        }
+        pl0vm.allow_files(Some(dir.into()));
+    }
+
+    if let Some(mode) = checksum {
+        match mode {
+            "add" => {
+                pl0vm.add_checksum();
+                match pl0vm.save_to_file(filename.unwrap()) {
+                    Ok(_) => diagln!("{}", t!("checksum_added", file = filename.unwrap())),
+                    Err(_) => diagln!("{}", t!("file_error", file = filename.unwrap())),
+                }
+            },
+            "verify" => match pl0vm.verify_checksum() {
+                Some(true) => diagln!("{}", t!("checksum_valid")),
+                Some(false) => {
+                    diagln!("{}", t!("pl0.checksum_mismatch"));
+                    exit(1);
+                },
+                None => {
+                    diagln!("{}", t!("checksum_missing"));
+                    exit(1);
+                },
+            },
+            _ => {
+                diagln!("{}", t!("invalid_checksum_mode", mode = mode));
+                exit(1);
+            },
+        }
+        return;
+    }
+
+    if verify {
+        match pl0vm.verify() {
+            Ok(_) => diagln!("{}", t!("verifier.passed")),
+            Err(problems) => {
+                diagln!("{}", t!("verifier.failed"));
+                problems.iter().for_each(|problem| diagln!("{}", problem));
+                exit(1);
+            },
+        }
+        return;
+    }
+
+    if callgraph {
+        pl0vm.print_call_graph();
+        return;
+    }
+
+    if xref {
+        pl0vm.print_cross_references();
+        return;
+    }
+
+    if cfg {
+        let proc_id = cfg_proc.unwrap_or("0");
+        let dot = match proc_id.parse().map_err(|_| t!("cfg.invalid_procedure", id = proc_id).to_string()).and_then(|id| pl0vm.control_flow_graph_dot(id)) {
+            Ok(dot) => dot,
+            Err(err) => {
+                diagln!("{}", err);
+                exit(1);
+            },
+        };
+        match cfg_output {
+            Some(target) => match std::fs::write(target, dot) {
+                Ok(_) => diagln!("{}", t!("converted", file = target)),
+                Err(_) => {
+                    diagln!("{}", t!("file_error", file = target));
+                    exit(1);
+                },
+            },
+            None => print!("{}", dot),
+        }
+        return;
+    }
+
+    if let Some(mode) = selftest {
+        pl0_vm_rs::interrupt::install();
+        match mode {
+            "record" => {
+                let output = pl0vm.execute_captured();
+                pl0vm.set_expected_output(output);
+                match pl0vm.save_to_file(filename.unwrap()) {
+                    Ok(_) => diagln!("{}", t!("selftest_recorded", file = filename.unwrap())),
+                    Err(_) => diagln!("{}", t!("file_error", file = filename.unwrap())),
+                }
+            },
+            "verify" => {
+                let expected = match pl0vm.expected_output() {
+                    Some(val) => val.to_vec(),
+                    None => {
+                        diagln!("{}", t!("selftest_missing"));
+                        exit(1);
+                    },
+                };
+                let output = pl0vm.execute_captured();
+                if output == expected {
+                    diagln!("{}", t!("selftest_passed"));
+                } else {
+                    diagln!("{}", t!("selftest_failed", expected = String::from_utf8_lossy(&expected), output = String::from_utf8_lossy(&output)));
+                    exit(1);
+                }
+            },
+            _ => {
+                diagln!("{}", t!("invalid_selftest_mode", mode = mode));
+                exit(1);
+            },
+        }
+        return;
+    }
+
+    if convert {
+        let target = format!("{}.json", filename.unwrap());
+        let json = match pl0vm.to_json() {
+            Ok(json) => json,
+            Err(err) => {
+                diagln!("{}", err);
+                exit(1);
+            },
+        };
+        match std::fs::write(&target, json) {
+            Ok(_) => diagln!("{}", t!("converted", file = target)),
+            Err(_) => diagln!("{}", t!("file_error", file = target)),
+        }
+        return;
+    }
+
+    if compress {
+        let target = format!("{}.z", filename.unwrap());
+        match pl0vm.save_compressed(&target) {
+            Ok(_) => diagln!("{}", t!("compressed", file = target)),
+            Err(_) => diagln!("{}", t!("file_error", file = target)),
+        }
+        return;
+    }
+
+    if !debugger_script.is_empty() {
+        pl0_vm_rs::interrupt::install();
+        pl0_vm_rs::debugger::run_script(&pl0vm, &debugger_script);
+        return;
+    }
+
+    if dap {
+        pl0_vm_rs::dap::run(&pl0vm);
+        return;
+    }
+
+    if debugger && tui {
+        #[cfg(feature = "tui")]
+        if let Err(err) = pl0_vm_rs::tui::run(&pl0vm) {
+            diagln!("{}", err);
+        }
+        #[cfg(not(feature = "tui"))]
+        diagln!("{}", t!("tui_unavailable"));
+        return;
+    }
+
+    if debugger {
+        pl0_vm_rs::debugger::run(&pl0vm);
+        return;
+    }
+
+    // every mode from here on runs the program, except for the analysis
+    if !analyze_only {
+        pl0_vm_rs::interrupt::install();
+    }
+
+    if let Some(target) = record {
+        let (replay, status) = pl0vm.execute_recording();
+        match std::fs::write(target, format_replay(filename.unwrap(), &replay)) {
+            Ok(_) => diagln!("{}", t!("replay.recorded", file = target, count = replay.inputs.len())),
+            Err(_) => diagln!("{}", t!("file_error", file = target)),
+        }
+        exit_with(status);
+        return;
+    }
+
+    if let Some(source) = replay {
+        let replay = match std::fs::read_to_string(source).map_err(|_| t!("file_error", file = source).to_string()).and_then(|text| parse_replay(&text)) {
+            Ok(val) => val,
+            Err(err) => {
+                diagln!("{}", err);
+                exit(1);
+            },
+        };
+        let (unused, status) = pl0vm.execute_replay(&replay);
+        if unused > 0 {
+            diagln!("{}", t!("replay.unused", count = unused));
+        }
+        exit_with(status);
+        return;
+    }
+
+    if let Some(target) = trace {
+        let result = std::fs::File::create(target).and_then(|file| pl0vm.execute_traced(&mut std::io::BufWriter::new(file)));
+        match result {
+            Ok(status) => exit_with(status),
+            Err(_) => diagln!("{}", t!("file_error", file = target)),
+        }
+        return;
+    }
+
+    if let Some(target) = chrome_trace {
+        let result = std::fs::File::create(target).and_then(|file| pl0vm.execute_chrome_trace(&mut std::io::BufWriter::new(file)));
+        match result {
+            Ok(status) => exit_with(status),
+            Err(_) => diagln!("{}", t!("file_error", file = target)),
+        }
+        return;
+    }
+
+    if let Some(target) = flamegraph {
+        let (samples, status) = pl0vm.execute_sampled();
+        let contents = if target.ends_with(".svg") { pl0_vm_rs::flamegraph::svg(&samples) } else { pl0_vm_rs::flamegraph::folded(&samples) };
+        match std::fs::write(target, contents) {
+            Ok(_) => diagln!("{}", t!("flamegraph_written", file = target)),
+            Err(_) => diagln!("{}", t!("file_error", file = target)),
+        }
+        exit_with(status);
+        return;
+    }
+
+    if stats {
+        let (metrics, status) = pl0vm.execute_metrics();
+        if let Some(metrics) = metrics {
+            diagln!();
+            diagln!("{}", t!("stats.instructions", count = metrics.instructions));
+            diagln!("{}", t!("stats.calls", count = metrics.calls));
+            diagln!("{}", t!("stats.io", inputs = metrics.inputs, outputs = metrics.outputs));
+            diagln!("{}", t!("stats.peak_stack", bytes = metrics.peak_stack));
+        }
+        exit_with(status);
+        return;
+    }
+
+    if profile {
+        let (profile, status) = pl0vm.execute_profiled();
+        pl0vm.print_profile(&profile);
+        exit_with(status);
+        return;
+    }
+
+    if let Some(min) = hot_loops {
+        let min = match min.parse() {
+            Ok(val) => val,
+            Err(_) => {
+                diagln!("{}", t!("profile.invalid_min", min = min));
+                exit(1);
+            },
+        };
+        let (profile, status) = pl0vm.execute_profiled();
+        pl0vm.print_hot_loops(&profile, min);
+        exit_with(status);
+        return;
+    }
+
+    if let Some(target) = coverage {
+        // earlier runs are merged, a missing file is started from scratch
+        let mut collected = match std::fs::read_to_string(target) {
+            Ok(text) => match Coverage::parse(&text) {
+                Ok(val) => val,
+                Err(err) => {
+                    diagln!("{}", err);
+                    exit(1);
+                },
+            },
+            Err(_) => Coverage::default(),
+        };
+        let status = pl0vm.execute_covered(&mut collected);
+        pl0vm.print_coverage(&collected);
+        if std::fs::write(target, collected.format(filename.unwrap())).is_err() {
+            diagln!("{}", t!("file_error", file = target));
+        }
+        exit_with(status);
+        return;
+    }
+
+    if let Some(target) = core {
+        let (execution, status) = pl0vm.execute_until_error();
+        if let Some(execution) = execution {
+            match std::fs::write(target, execution.to_core_dump().to_json()) {
+                Ok(_) => diagln!("{}", t!("coredump.written", file = target)),
+                Err(_) => diagln!("{}", t!("file_error", file = target)),
+            }
+            // e.g. the interrupt keeps its status, other runtime errors end with 1
+            exit_with(status);
+            exit(1);
+        }
+        exit_with(status);
+        return;
+    }
 
     if analyze_only {
         pl0vm.print_analysis();
     } else {
-        pl0vm.execute();
+        let status = if jit {
+            #[cfg(feature = "jit")]
+            let status = pl0vm.execute_jit();
+            #[cfg(not(feature = "jit"))]
+            let status = {
+                diagln!("{}", t!("jit_unavailable"));
+                pl0vm.execute()
+            };
+            status
+        } else if fast {
+            pl0vm.execute_fast()
+        } else {
+            pl0vm.execute()
+        };
+        exit_with(status);
+    }
+}
+
+// the status of Halt is the exit code, as is START_FAILED_STATUS if the program couldn't start, 0 lets main go on
+fn exit_with(status: i64) {
+    if status != 0 {
+        exit(status as i32);
     }
 }
@@ -0,0 +1,58 @@
+// Metadata about the origin of a file, stored in the META section as key/value pairs:
+//   <key> 0x00 <value> 0x00
+// Keys are free-form, the constants below are the ones compilers are expected to write.
+
+// name and version of the compiler that produced the file, e.g. "pl0c 1.2"
+pub const TOOLCHAIN: &str = "toolchain";
+// time of compilation, preferably as RFC 3339 date
+pub const TIMESTAMP: &str = "timestamp";
+// name of the program or source file
+pub const NAME: &str = "name";
+
+#[derive(Debug, Default, Clone)]
+pub struct Metadata {
+    // in file order, keys are unique
+    pub entries: Vec<(String, String)>,
+}
+
+impl Metadata {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter().find(|(other, _)| other == key).map(|(_, value)| value.as_str())
+    }
+    // add the entry or replace the value if the key already exists
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.entries.iter_mut().find(|(other, _)| other == key) {
+            Some(entry) => entry.1 = value.to_string(),
+            None => self.entries.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    // returns None if the section data is malformed
+    pub fn parse(data: &[u8]) -> Option<Metadata> {
+        let read_str = |pos: usize| {
+            let len = data.get(pos..)?.iter().position(|&b| b == 0)?;
+            Some((String::from_utf8(data[pos..(pos + len)].to_vec()).ok()?, pos + len + 1))
+        };
+
+        let mut metadata = Metadata::default();
+        let mut pos = 0;
+        while pos < data.len() {
+            let (key, next) = read_str(pos)?;
+            let (value, next) = read_str(next)?;
+            metadata.set(&key, &value);
+            pos = next;
+        }
+        Some(metadata)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for (key, value) in &self.entries {
+            bytes.extend(key.as_bytes());
+            bytes.push(0);
+            bytes.extend(value.as_bytes());
+            bytes.push(0);
+        }
+        bytes
+    }
+}
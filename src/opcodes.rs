@@ -1,7 +1,7 @@
 use std::fmt::{Display};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
-#[derive(Debug, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 pub enum OpCode {
     // Ein Argument sind die zwei Bytes, die auf den Opcode im Bytecode folgen.
@@ -84,7 +84,214 @@ pub enum OpCode {
     // neue Codes - Funktionsweise unbekannt
     Put = 0x1F,
     Get = 0x20,
-    OpAddAddr = 0x21
+    OpAddAddr = 0x21,
+
+    // Argumente: Index in der String-Tabelle (Abschnitt STRS), Ausgabe wie PutString
+    PutStringRef = 0x22,
+    // Präfix: die Argumente des folgenden Opcodes sind 4 statt 2 Bytes groß,
+    // für Sprünge, Konstanten-IDs und Prozeduren jenseits von 32 KiB
+    Wide = 0x23,
+
+    // Gleitkommazahlen, nur für 32 und 64 Bit: die Werte auf dem Stack sind die Bits eines f32 bzw. f64,
+    // Vergleiche ergeben wie die ganzzahligen true (1) / false (0)
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 + Wert 2
+    FloatAdd = 0x24,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 - Wert 2
+    FloatSubtract = 0x25,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 * Wert 2
+    FloatMultiply = 0x26,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 / Wert 2 (durch 0 ergibt ±inf bzw. NaN)
+    FloatDivide = 0x27,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 == Wert 2
+    FloatCompareEq = 0x28,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 != Wert 2
+    FloatCompareNotEq = 0x29,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 < Wert 2
+    FloatCompareLT = 0x2A,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 > Wert 2
+    FloatCompareGT = 0x2B,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 ≤ Wert 2
+    FloatCompareLTEq = 0x2C,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 ≥ Wert 2
+    FloatCompareGTEq = 0x2D,
+    // auf Stack: oben = Wert → Ergebnis auf Stack: -Wert
+    FloatMinusify = 0x2E,
+    // auf Stack: oben = Wert
+    OutputFloat = 0x2F,
+    // auf Stack: oben = ganze Zahl → Ergebnis auf Stack: dieselbe Zahl als Gleitkommazahl
+    IntToFloat = 0x30,
+    // auf Stack: oben = Gleitkommazahl → Ergebnis auf Stack: ganzzahliger Anteil (NaN ergibt 0, zu große Werte den größten bzw. kleinsten Wert)
+    FloatToInt = 0x31,
+
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Rest von Wert 1 / Wert 2 (mit dem Vorzeichen von Wert 1)
+    OpModulo = 0x32,
+
+    // Bit-Operationen, bei 8 bis 32 Bit auf die Datenbreite begrenzt
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 & Wert 2
+    OpAnd = 0x33,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 | Wert 2
+    OpOr = 0x34,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 ^ Wert 2
+    OpXor = 0x35,
+    // auf Stack: oben = Wert → Ergebnis auf Stack: !Wert (alle Bits invertiert)
+    OpNot = 0x36,
+
+    // Verschiebungen um 0 bis Datenbreite - 1 Bits, andere Anzahlen sind ein Laufzeitfehler
+    // auf Stack: oben = Anzahl, darunter = Wert → Ergebnis auf Stack: Wert << Anzahl (herausgeschobene Bits gehen verloren)
+    ShiftLeft = 0x37,
+    // auf Stack: oben = Anzahl, darunter = Wert → Ergebnis auf Stack: Wert >> Anzahl, mit dem Vorzeichen aufgefüllt
+    ShiftRight = 0x38,
+    // auf Stack: oben = Anzahl, darunter = Wert → Ergebnis auf Stack: Wert >> Anzahl, mit Nullen aufgefüllt
+    ShiftRightLogical = 0x39,
+
+    // Verknüpfung von Wahrheitswerten wie den Ergebnissen der Vergleiche, alles außer 0 gilt als true
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 and Wert 2, true (1) / false (0)
+    LogicalAnd = 0x3A,
+    // auf Stack: oben = Wert 2, darunter = Wert 1 → Ergebnis auf Stack: Wert 1 or Wert 2, true (1) / false (0)
+    LogicalOr = 0x3B,
+
+    // Heap, getrennt vom Stack, Adressen in Bytes wie dort (0 wird nie vergeben)
+    // auf Stack: oben = Größe in Bytes → Ergebnis auf Stack: Adresse des neuen, mit 0 gefüllten Blocks
+    Alloc = 0x3C,
+    // auf Stack: oben = Adresse eines Blocks von Alloc (bei 0 passiert nichts)
+    Free = 0x3D,
+    // auf Stack: oben = Adresse im Heap → Ergebnis auf Stack: Wert an dieser Adresse
+    LoadHeap = 0x3E,
+    // auf Stack: oben = Wert, darunter = Adresse im Heap
+    StoreHeap = 0x3F,
+
+    // Strings: die Werte sind Nummern von Strings, zuerst die der String-Tabelle (Abschnitt STRS), danach die von StringConcat
+    // Argumente: Index in der String-Tabelle → Ergebnis auf Stack: der String
+    PushString = 0x40,
+    // auf Stack: oben = String, Ausgabe wie PutString
+    OutputString = 0x41,
+    // auf Stack: oben = String 2, darunter = String 1 → Ergebnis auf Stack: neuer String aus String 1 und dahinter String 2
+    StringConcat = 0x42,
+    // auf Stack: oben = String 2, darunter = String 1 → Ergebnis auf Stack: -1, 0 oder 1, wenn String 1 (byteweise) vor, gleich oder nach String 2 kommt
+    StringCompare = 0x43,
+
+    // Ein- und Ausgabe einzelner Zeichen
+    // Ergebnis auf Stack: Code des nächsten eingegebenen Zeichens, -1 am Ende der Eingabe
+    GetChar = 0x44,
+    // auf Stack: oben = Code eines Zeichens, Ausgabe des Zeichens ohne Zeilenumbruch
+    PutChar = 0x45,
+
+    // Argumente: Prozedur-ID, Anzahl der Parameter
+    // auf Stack: oben = letzter Parameter, darunter die anderen → Aufruf wie CallProc, die Parameter sind danach
+    // die ersten Variablen der Prozedur (in derselben Reihenfolge, ab Adresse 0)
+    CallProcArgs = 0x46,
+    // auf Stack: oben = Ergebnis → Rückkehr wie ReturnProc, das Ergebnis liegt danach oben auf dem Stack des Aufrufers
+    // (eine Prozedur kehrt entweder immer mit ReturnValue oder immer mit ReturnProc zurück, in main endet das Programm)
+    ReturnValue = 0x47,
+
+    // Ergebnis auf Stack: Pseudozufallszahl von 0 bis zum größten Wert der Datenbreite (reproduzierbar mit --seed)
+    Random = 0x48,
+    // Ergebnis auf Stack: Millisekunden seit dem Start des Programms (bei virtueller Uhr nach der Anzahl ausgeführter Befehle)
+    Clock = 0x49,
+    // auf Stack: oben = Status → beendet das Programm sofort, auch aus Prozeduren heraus, mit diesem Status (sonst 0)
+    Halt = 0x4A,
+    // Argumente: Index der Meldung in der String-Tabelle, -1 für keine
+    // auf Stack: oben = Wert → Laufzeitfehler mit der Meldung, wenn der Wert 0 ist
+    Assert = 0x4B,
+
+    // Dateien, nur im mit --allow-fs erlaubten Verzeichnis: Zahlen durch Leerraum getrennt, eine pro Zeile geschrieben
+    // auf Stack: oben = Modus (0 lesen, 1 schreiben, 2 anhängen), darunter = Name als String → Ergebnis auf Stack: Handle
+    FileOpen = 0x4C,
+    // auf Stack: oben = Handle → Ergebnis auf Stack: nächste Zahl der Datei
+    FileRead = 0x4D,
+    // auf Stack: oben = Handle → Ergebnis auf Stack: true (1), wenn keine Zahl mehr zu lesen ist, sonst false (0)
+    FileAtEnd = 0x4E,
+    // auf Stack: oben = Wert, darunter = Handle
+    FileWrite = 0x4F,
+    // auf Stack: oben = Handle
+    FileClose = 0x50,
+
+    // Zahlen, die dem Programm auf der Kommandozeile nach -- übergeben wurden
+    // Ergebnis auf Stack: Anzahl der Argumente
+    ArgCount = 0x51,
+    // auf Stack: oben = Index (ab 0) → Ergebnis auf Stack: das Argument
+    GetArg = 0x52,
+
+    // Umgebungsvariablen, nur die mit --allow-env erlaubten
+    // auf Stack: oben = Name als String → Ergebnis auf Stack: Wert der Variable als Zahl (0, wenn sie nicht gesetzt ist)
+    EnvNumber = 0x53,
+    // auf Stack: oben = Name als String → Ergebnis auf Stack: neuer String mit dem Wert der Variable (leer, wenn sie nicht gesetzt ist)
+    EnvString = 0x54,
+}
+
+impl OpCode {
+    // Opcode anhand seines Namens (wie bei Display) finden
+    pub fn from_name(name: &str) -> Option<OpCode> {
+        (0..=u8::MAX).filter_map(|byte| OpCode::try_from(byte).ok()).find(|op| format!("{:?}", op) == name)
+    }
+
+    // Anzahl der Argumente, die auf den Opcode folgen (PutString hat stattdessen einen null-terminierten String)
+    pub fn arg_count(&self) -> usize {
+        match self {
+            OpCode::PushValueLocalVar | OpCode::PushValueMainVar
+                | OpCode::PushAddressLocalVar | OpCode::PushAddressMainVar
+                | OpCode::PushConstant | OpCode::CallProc
+                | OpCode::Jump | OpCode::JumpIfFalse
+                | OpCode::PutStringRef | OpCode::PushString | OpCode::Assert => 1,
+            OpCode::PushValueGlobalVar | OpCode::PushAddressGlobalVar | OpCode::CallProcArgs => 2,
+            OpCode::EntryProc => 3,
+            _ => 0,
+        }
+    }
+
+    // Operatoren mit 2 Faktoren, die zwei Werte vom Stack durch das Ergebnis ersetzen
+    pub fn is_binary(&self) -> bool {
+        matches!(self, OpCode::OpAdd | OpCode::OpSubtract | OpCode::OpMultiply | OpCode::OpDivide | OpCode::OpModulo
+            | OpCode::OpAnd | OpCode::OpOr | OpCode::OpXor | OpCode::LogicalAnd | OpCode::LogicalOr
+            | OpCode::CompareEq | OpCode::CompareNotEq | OpCode::CompareLT | OpCode::CompareGT
+            | OpCode::CompareLTEq | OpCode::CompareGTEq)
+    }
+
+    // Operatoren mit 2 Gleitkomma-Faktoren
+    pub fn is_float_binary(&self) -> bool {
+        matches!(self, OpCode::FloatAdd | OpCode::FloatSubtract | OpCode::FloatMultiply | OpCode::FloatDivide
+            | OpCode::FloatCompareEq | OpCode::FloatCompareNotEq | OpCode::FloatCompareLT | OpCode::FloatCompareGT
+            | OpCode::FloatCompareLTEq | OpCode::FloatCompareGTEq)
+    }
+
+    // Befehle, die mit Gleitkommazahlen arbeiten (nur für 32 und 64 Bit)
+    pub fn is_float(&self) -> bool {
+        self.is_float_binary() || matches!(self, OpCode::FloatMinusify | OpCode::OutputFloat | OpCode::IntToFloat | OpCode::FloatToInt)
+    }
+
+    // Aufrufe einer Prozedur, mit dem ersten Argument als ID
+    pub fn is_call(&self) -> bool {
+        matches!(self, OpCode::CallProc | OpCode::CallProcArgs)
+    }
+
+    // Anzahl der Daten, die der Befehl vom Stack nimmt und danach darauf legt
+    // (Aufrufe lassen den Stack des Aufrufers unverändert, EntryProc beginnt einen neuen,
+    // bei CallProcArgs kommen die Parameter dazu und bei Aufrufen von Prozeduren mit ReturnValue das Ergebnis, siehe PL0VM::stack_effect)
+    pub fn stack_effect(&self) -> (usize, usize) {
+        match self {
+            OpCode::PushValueLocalVar | OpCode::PushValueMainVar | OpCode::PushValueGlobalVar
+                | OpCode::PushAddressLocalVar | OpCode::PushAddressMainVar | OpCode::PushAddressGlobalVar
+                | OpCode::PushConstant | OpCode::PushString | OpCode::GetChar | OpCode::Random | OpCode::Clock | OpCode::ArgCount => (0, 1),
+            OpCode::StoreValue | OpCode::Put | OpCode::StoreHeap | OpCode::FileWrite => (2, 0),
+            OpCode::OutputValue | OpCode::InputToAddr | OpCode::JumpIfFalse | OpCode::Pop | OpCode::OutputFloat | OpCode::Free | OpCode::OutputString
+                | OpCode::PutChar | OpCode::ReturnValue | OpCode::Halt
+                | OpCode::Assert | OpCode::FileClose => (1, 0),
+            OpCode::Minusify | OpCode::IsOdd | OpCode::OpNot | OpCode::Swap | OpCode::Get | OpCode::Alloc | OpCode::LoadHeap
+                | OpCode::FloatMinusify | OpCode::IntToFloat | OpCode::FloatToInt | OpCode::FileRead | OpCode::FileAtEnd
+                | OpCode::GetArg | OpCode::EnvNumber | OpCode::EnvString => (1, 1),
+            OpCode::OpAdd | OpCode::OpSubtract | OpCode::OpMultiply | OpCode::OpDivide | OpCode::OpModulo
+                | OpCode::OpAnd | OpCode::OpOr | OpCode::OpXor
+                | OpCode::ShiftLeft | OpCode::ShiftRight | OpCode::ShiftRightLogical | OpCode::LogicalAnd | OpCode::LogicalOr
+                | OpCode::CompareEq | OpCode::CompareNotEq | OpCode::CompareLT | OpCode::CompareGT
+                | OpCode::CompareLTEq | OpCode::CompareGTEq | OpCode::OpAddAddr => (2, 1),
+            OpCode::FloatAdd | OpCode::FloatSubtract | OpCode::FloatMultiply | OpCode::FloatDivide
+                | OpCode::FloatCompareEq | OpCode::FloatCompareNotEq | OpCode::FloatCompareLT | OpCode::FloatCompareGT
+                | OpCode::FloatCompareLTEq | OpCode::FloatCompareGTEq => (2, 1),
+            OpCode::StringConcat | OpCode::StringCompare | OpCode::FileOpen => (2, 1),
+            OpCode::CallProc | OpCode::CallProcArgs | OpCode::ReturnProc | OpCode::Jump | OpCode::EntryProc
+                | OpCode::PutString | OpCode::PutStringRef | OpCode::EndOfCode | OpCode::Wide => (0, 0),
+        }
+    }
 }
 
 impl Display for OpCode {
@@ -0,0 +1,86 @@
+use crate::decoder::decode_instruction;
+use crate::opcodes::OpCode;
+use crate::pl0_vm::PL0VM;
+use rust_i18n::t;
+
+impl PL0VM {
+    // insert the instructions in code in front of the instruction at addr
+    // jumps targeting addr will afterwards land at the start of the inserted code
+    pub fn insert_instructions(&mut self, addr: usize, code: &[u8]) -> Result<(), String> {
+        self.splice_code(addr, 0, code)
+    }
+
+    // replace the instruction at addr with the instructions in code (which may also be empty)
+    pub fn replace_instruction(&mut self, addr: usize, code: &[u8]) -> Result<(), String> {
+        let len = match self.decode_code()?.iter().find(|instr| instr.addr == addr) {
+            Some(instr) => instr.len,
+            None => return Err(t!("pl0.patch.not_boundary", addr = addr:{:04X}).to_string()),
+        };
+        self.splice_code(addr, len, code)
+    }
+
+    // replace remove_len bytes at addr with code, then fix up the length of the surrounding
+    // procedure and all relative jump offsets that cross the patched location
+    fn splice_code(&mut self, addr: usize, remove_len: usize, code: &[u8]) -> Result<(), String> {
+        let instructions = self.decode_code()?.to_vec();
+        let index = match instructions.iter().position(|instr| instr.addr == addr) {
+            Some(val) => val,
+            None => return Err(t!("pl0.patch.not_boundary", addr = addr:{:04X}).to_string()),
+        };
+        if let OpCode::EntryProc = instructions[index].op {
+            return Err(t!("pl0.patch.entry_proc", addr = addr:{:04X}).to_string());
+        }
+
+        // the new code has to consist of complete instructions, and may not start a new procedure
+        let mut offset = 0;
+        while offset < code.len() {
+            match decode_instruction(code, offset) {
+                Ok(instr) if instr.op != OpCode::EntryProc => offset += instr.len,
+                _ => return Err(t!("pl0.patch.invalid_code", offset = offset).to_string()),
+            }
+        }
+
+        let delta = code.len() as isize - remove_len as isize;
+        // new position of an instruction start (or jump target) after patching
+        let new_pos = |pos: usize| if pos <= addr { pos } else { pos.checked_add_signed(delta).expect("position moved before patch") };
+
+        // position, new value and whether the argument is wide
+        let mut fixes: Vec<(usize, i32, bool)> = vec![];
+        // normal arguments have to stay in the i16 range
+        let fits = |val: isize, wide: bool| if wide { i32::try_from(val).ok() } else { i16::try_from(val).ok().map(|val| val as i32) };
+        // a replaced instruction is gone, an instruction with code inserted in front of it moves behind that code
+        for instr in instructions.iter().filter(|instr| remove_len == 0 || instr.addr != addr) {
+            match instr.op {
+                OpCode::Jump | OpCode::JumpIfFalse => {
+                    let arg = self.read_operand(instr.arg_addr(0), instr.wide).expect("length checked while decoding");
+                    // offsets are relative to the end of the jump instruction
+                    let target = match (instr.addr + instr.len).checked_add_signed(arg as isize) {
+                        Some(val) => val,
+                        None => return Err(t!("pl0.invalid_jump", pc = instr.addr, arg = arg).to_string()),
+                    };
+                    let new_addr = if instr.addr == addr { addr + code.len() } else { new_pos(instr.addr) };
+                    let new_arg = match fits(new_pos(target) as isize - (new_addr + instr.len) as isize, instr.wide) {
+                        Some(val) => val,
+                        None => return Err(t!("pl0.patch.jump_range", addr = instr.addr:{:04X}).to_string()),
+                    };
+                    fixes.push((new_addr + (instr.arg_addr(0) - instr.addr), new_arg, instr.wide));
+                },
+                _ => {},
+            }
+        }
+        // the procedure containing the patched instruction changes its length
+        let entry = instructions[..index].iter().rev().find(|instr| matches!(instr.op, OpCode::EntryProc)).expect("every procedure starts with EntryProc");
+        let proc_len = self.read_operand(entry.arg_addr(0), entry.wide).expect("length checked while decoding");
+        match fits(proc_len as isize + delta, entry.wide) {
+            Some(val) => fixes.push((entry.arg_addr(0), val, entry.wide)),
+            None => return Err(t!("pl0.patch.proc_length", addr = entry.addr:{:04X}).to_string()),
+        }
+
+        self.program.to_mut().splice(addr..(addr + remove_len), code.iter().copied());
+        for (pos, val, wide) in fixes {
+            self.write_operand(pos, val, wide);
+        }
+        self.reparse();
+        Ok(())
+    }
+}
@@ -1,47 +1,424 @@
+use crate::format::{self, Section, CHECKSUM_TAG, EXPECTED_OUTPUT_TAG, FORMAT_VERSION, LINES_TAG, METADATA_TAG, STRINGS_TAG, SYMBOLS_TAG};
+use crate::coredump::CoreDump;
+use crate::decoder::{decode_instruction, Code, Instruction, ProgramImage, Superinstruction};
+use crate::image::Image;
+use crate::json::to_hex;
+use crate::lines::LineTable;
+use crate::metadata::Metadata;
 use crate::opcodes::OpCode;
-use crate::pl0_vm::Data::{B16, B32, B64};
+#[cfg(feature = "bignum")]
+use crate::bignum::Bigs;
+use crate::files::Files;
+use crate::heap::Heap;
+use crate::interrupt;
+use crate::random::Rng;
+use crate::replay::Replay;
+use crate::stack::Stack;
+use crate::stackusage::StackUsage;
+use crate::symbols::Symbols;
+use crate::telemetry::Telemetry;
+use crate::pl0_vm::Data::{B16, B32, B64, B8};
+use crate::diag::{paint, Part};
+use crate::{diag, diagln};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 use std::fmt::Debug;
-use std::io::{stderr, stdin, BufRead, Write};
+use std::io::{stderr, stdin, stdout, BufRead, BufWriter, IsTerminal, Write};
 use rust_i18n::t;
 
-fn error(msg: &str) {
-    stderr().write(msg.as_bytes()).expect("Could not write to stderr");
-    stderr().write("\n".as_bytes()).expect("Could not write to stderr");
+thread_local! {
+    // error messages of this thread go here instead of stderr while it is set, see capture_errors
+    static CAPTURED_ERRORS: RefCell<Option<String>> = const { RefCell::new(None) };
 }
 
-const ARG_SIZE: usize = 2;
+pub(crate) fn error(msg: &str) {
+    let captured = CAPTURED_ERRORS.with_borrow_mut(|captured| match captured {
+        Some(captured) => {
+            captured.push_str(msg);
+            captured.push('\n');
+            true
+        }
+        None => false,
+    });
+    if !captured {
+        stderr().write(msg.as_bytes()).expect("Could not write to stderr");
+        stderr().write("\n".as_bytes()).expect("Could not write to stderr");
+    }
+}
+
+// run f and return the error messages it would have printed instead, for running several programs at once
+pub(crate) fn capture_errors<T>(f: impl FnOnce() -> T) -> (T, String) {
+    let outer = CAPTURED_ERRORS.replace(Some(String::new()));
+    let result = f();
+    let errors = CAPTURED_ERRORS.replace(outer).unwrap_or_default();
+    (result, errors)
+}
+
+pub(crate) const ARG_SIZE: usize = 2;
+// argument size of instructions with the Wide prefix
+pub(crate) const WIDE_ARG_SIZE: usize = 4;
 const HEX_ARG_SIZE: usize = ARG_SIZE * 2;
+// CallProc stores the return address, frame pointer and procedure of the caller on the stack, 8 bytes each
+// (one value each, the rest of the 24 bytes stays 0 for smaller architectures)
+pub(crate) const CALL_FRAME_SIZE: usize = 3 * 8;
+// instructions per millisecond of the virtual clock, see set_virtual_clock
+pub const VIRTUAL_CLOCK_RATE: usize = 1000;
+// bytes of the heap, unless set with set_heap_limit
+pub const DEFAULT_HEAP_LIMIT: usize = 64 * 1024;
+// what InputToAddr asks with, unless set with set_prompt
+pub const DEFAULT_PROMPT: &str = "? ";
+// tries InputToAddr gives after the first invalid line, unless set with set_input_retries
+pub const DEFAULT_INPUT_RETRIES: usize = 3;
+// status of a run stopped by the input timeout, the one of timeout(1)
+pub const INPUT_TIMEOUT_STATUS: i64 = 124;
+// status of a run stopped with Ctrl+C, the one shells use for SIGINT
+pub const INTERRUPT_STATUS: i64 = 130;
+// status of a program that couldn't start, e.g. because it failed verification
+pub const START_FAILED_STATUS: i64 = 1;
+
+pub(crate) fn arg_size(wide: bool) -> usize {
+    if wide { WIDE_ARG_SIZE } else { ARG_SIZE }
+}
+
+// result of a binary operation for superinstructions, None for a division by zero
+fn binary_op(vm: &PL0VM, op: OpCode, left: i64, right: i64) -> Option<i64> {
+    let (uleft, uright) = (vm.as_unsigned(left), vm.as_unsigned(right));
+    Some(match op {
+        OpCode::OpAdd => left + right,
+        OpCode::OpSubtract => left - right,
+        OpCode::OpMultiply => left * right,
+        OpCode::OpDivide | OpCode::OpModulo if right == 0 => return None,
+        OpCode::OpDivide if vm.unsigned => (uleft / uright) as i64,
+        OpCode::OpDivide => left / right,
+        OpCode::OpModulo if vm.unsigned => (uleft % uright) as i64,
+        OpCode::OpModulo => left.wrapping_rem(right),
+        OpCode::OpAnd => left & right,
+        OpCode::OpOr => left | right,
+        OpCode::OpXor => left ^ right,
+        OpCode::LogicalAnd => (left != 0 && right != 0) as i64,
+        OpCode::LogicalOr => (left != 0 || right != 0) as i64,
+        OpCode::CompareEq => (left == right) as i64,
+        OpCode::CompareNotEq => (left != right) as i64,
+        OpCode::CompareLT if vm.unsigned => (uleft < uright) as i64,
+        OpCode::CompareLT => (left < right) as i64,
+        OpCode::CompareGT if vm.unsigned => (uleft > uright) as i64,
+        OpCode::CompareGT => (left > right) as i64,
+        OpCode::CompareLTEq if vm.unsigned => (uleft <= uright) as i64,
+        OpCode::CompareLTEq => (left <= right) as i64,
+        OpCode::CompareGTEq if vm.unsigned => (uleft >= uright) as i64,
+        OpCode::CompareGTEq => (left >= right) as i64,
+        _ => unreachable!("{op} is no binary operation"),
+    })
+}
+
+// result of a binary operation on floats, see PL0VM::float
+fn float_op(vm: &PL0VM, op: OpCode, left: i64, right: i64) -> i64 {
+    let (left, right) = (vm.float(left), vm.float(right));
+    match op {
+        OpCode::FloatAdd => vm.float_value(left + right),
+        OpCode::FloatSubtract => vm.float_value(left - right),
+        OpCode::FloatMultiply => vm.float_value(left * right),
+        OpCode::FloatDivide => vm.float_value(left / right),
+        OpCode::FloatCompareEq => (left == right) as i64,
+        OpCode::FloatCompareNotEq => (left != right) as i64,
+        OpCode::FloatCompareLT => (left < right) as i64,
+        OpCode::FloatCompareGT => (left > right) as i64,
+        OpCode::FloatCompareLTEq => (left <= right) as i64,
+        OpCode::FloatCompareGTEq => (left >= right) as i64,
+        _ => unreachable!("{op} is no binary float operation"),
+    }
+}
+
+// result of running an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    // the next instruction can run
+    Running,
+    // the program ended normally
+    Finished,
+    // the program was stopped by a runtime error, which was already printed
+    Error,
+}
+
+// what happens to strings of the program that aren't UTF-8, see PL0VM::set_string_decoding
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StringDecoding {
+    // a runtime error when printing them, an error in the analysis
+    #[default]
+    Error,
+    // the invalid bytes become U+FFFD
+    Lossy,
+    // every byte is the character with its code, for compilers that wrote umlauts in Latin-1
+    Latin1,
+}
+
+// how OutputValue prints values, see PL0VM::set_output_format
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OutputFormat {
+    // hexadecimal digits of the bits at the data width instead of a decimal number
+    pub hex: bool,
+    // zeros in front, up to as many digits as values of the data width can have
+    pub pad: bool,
+    // no line break after the value
+    pub no_newline: bool,
+}
+
+// an active procedure call, see Execution::backtrace
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub procedure: usize,
+    // start of the variables of the call in the stack
+    pub frame_ptr: usize,
+    // where the caller continues after the call returns, None for the main procedure
+    pub return_addr: Option<usize>,
+}
+
+// counters of a run, see Execution::metrics
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Metrics {
+    // including the one that caused a runtime error
+    pub instructions: usize,
+    pub calls: usize,
+    // numbers read with InputToAddr
+    pub inputs: usize,
+    // values and strings printed
+    pub outputs: usize,
+    // most bytes the stack had at any time
+    pub peak_stack: usize,
+}
+
+// a value written to the stack by an instruction (StoreValue, InputToAddr, Put)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackWrite {
+    // absolute address in the stack
+    pub addr: usize,
+    // 0 if the stack had to grow for the write
+    pub old: i64,
+    pub new: i64,
+}
+
+// A program that is being run, one instruction at a time, see PL0VM::start.
+// Clones continue independently from the same state, the debugger keeps them as snapshots.
+#[derive(Clone)]
+pub struct Execution<'a> {
+    vm: &'a PL0VM,
+    procedures: Vec<Procedure>,
+    // the constant pool, converted once when starting
+    constants: Vec<i64>,
+    // program counter = index of currently executed byte
+    pc: usize,
+    // stack = contains all dynamic runtime data, one value of the architecture per entry,
+    // addresses still count bytes, so the value at address a is stack[a / data_size]
+    stack: Stack,
+    // blocks of Alloc, addressed in bytes like the stack
+    heap: Heap,
+    // string values by their number: the string table of the program, then the results of StringConcat
+    strings: Vec<Vec<u8>>,
+    // numbers of Random, started from seed
+    rng: Rng,
+    seed: u64,
+    // when the execution started, for Clock
+    started: Instant,
+    // the value Halt ended the program with, INPUT_TIMEOUT_STATUS after the input timeout, INTERRUPT_STATUS after
+    // Ctrl+C, 0 otherwise
+    status: i64,
+    // files opened with FileOpen
+    files: Files,
+    // numbers too large for a cell in bignum mode
+    #[cfg(feature = "bignum")]
+    bigs: Bigs,
+    // frame pointer = address of start of current stack frame
+    fp: usize,
+    // current procedure index = index of current procedure in vector procedures
+    cur_proc_i: usize,
+    // number of procedure calls that haven't returned yet, 0 in main
+    depth: usize,
+    // result of the last step, nothing runs anymore once it isn't Running
+    state: Step,
+    // what the last step wrote to the stack, if anything
+    last_write: Option<StackWrite>,
+    // all numbers InputToAddr and character codes GetChar read so far, in order
+    inputs: Vec<i64>,
+    // numbers InputToAddr and GetChar read before asking on stdin, so runs can be repeated
+    pending_inputs: VecDeque<i64>,
+    // once the queued numbers are used up, InputToAddr and GetChar fail instead of reading stdin
+    input_closed: bool,
+    // address of the instruction that caused a runtime error
+    fault: Option<usize>,
+    // spans and events for tracing, see telemetry.rs
+    telemetry: Telemetry,
+    // the code decoded before running, shared with the clones, see PL0VM::predecode
+    code: Arc<Code>,
+    // the verifier found no stack depth problems, run_fast relies on that
+    stack_verified: bool,
+    // index of the instruction at pc in code, None if no instruction starts there
+    ip: Option<usize>,
+    metrics: Metrics,
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Procedure {
     // byte position of procedure in program
     start_pos: usize,
     // starts with space for variables
     frame_ptr: usize,
+    // bytes of variables at the start of its frames, from its EntryProc
+    vars: usize,
+}
+
+impl Procedure {
+    // the offset is one of the variables of the procedure
+    fn holds(&self, offset: i64) -> bool {
+        usize::try_from(offset).is_ok_and(|offset| offset < self.vars)
+    }
+}
+
+// The procedure with an active frame whose variables hold the absolute stack address, going from the current
+// frame down through its callers to main - None if no frame does (call information, temporary values, beyond the stack).
+fn frame_holding(stack: &[i64], size: usize, procedures: &[Procedure], mut proc_id: usize, mut fp: usize, addr: i64) -> Option<usize> {
+    let addr = usize::try_from(addr).ok()?;
+    loop {
+        if addr >= fp && procedures[proc_id].holds((addr - fp) as i64) {
+            return Some(proc_id);
+        }
+        if proc_id == 0 {
+            return None;
+        }
+        let info = fp.checked_sub(CALL_FRAME_SIZE)? / size;
+        let (caller_fp, caller) = (*stack.get(info + 1)? as usize, *stack.get(info + 2)? as usize);
+        // frames of callers are always further down, anything else means the stack was overwritten
+        if caller_fp >= fp || caller >= procedures.len() {
+            return None;
+        }
+        (proc_id, fp) = (caller, caller_fp);
+    }
+}
+
+// why read_number got no number
+pub(crate) enum NoInput {
+    // the runtime error
+    Error(String),
+    // nothing came within the input timeout, the run ends with INPUT_TIMEOUT_STATUS
+    Timeout(Duration),
+}
+
+// a number typed or pasted for InputToAddr: whitespace around it (including the \r of Windows line endings) doesn't
+// matter, it may have a sign, + too, and 0x in front of hexadecimal digits
+pub fn parse_number(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (digits, radix) = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None => (digits, 10),
+    };
+    // from_str_radix would take another sign
+    if !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let magnitude = u64::from_str_radix(digits, radix).ok()?;
+    if negative { 0i64.checked_sub_unsigned(magnitude) } else { i64::try_from(magnitude).ok() }
+}
+
+// wait for a number on stdin for InputToAddr: a line that isn't one is reported and asked for again, at most
+// input_retries times and only if someone is typing
+pub(crate) fn read_number(vm: &PL0VM, out: &mut dyn Write, pc: usize) -> Result<i64, NoInput> {
+    let interactive = stdin().is_terminal();
+    let mut retries = 0;
+    loop {
+        // the output before the prompt has to be visible while waiting
+        out.flush().expect("Could not write program output");
+        diag!("{}", vm.prompt);
+        let line = match read_line(vm.input_timeout) {
+            Some(Ok(line)) if line.is_empty() => return Err(NoInput::Error(t!("pl0.error.input_ended").to_string())),
+            Some(Ok(line)) => line,
+            // no UTF-8, so no number either
+            Some(Err(_)) => String::new(),
+            None => return Err(NoInput::Timeout(vm.input_timeout.unwrap_or_default())),
+        };
+        if let Some(num) = parse_number(&line) {
+            return Ok(num);
+        }
+        if !interactive || retries >= vm.input_retries {
+            return Err(NoInput::Error(t!("pl0.error.invalid_input", text = line.trim()).to_string()));
+        }
+        retries += 1;
+        vm.runtime_error(pc, &t!("pl0.invalid_number_input", text = line.trim()));
+    }
+}
+
+// the next line on stdin, empty at its end - None if it didn't come within the timeout
+fn read_line(timeout: Option<Duration>) -> Option<std::io::Result<String>> {
+    let read = || {
+        let mut line = String::new();
+        stdin().lock().read_line(&mut line).map(|_| line)
+    };
+    let timeout = match timeout {
+        Some(val) => val,
+        None => return Some(read()),
+    };
+    // the thread stays blocked if nothing comes, but the run ends then anyway
+    let (send, receive) = mpsc::channel();
+    std::thread::spawn(move || send.send(read()));
+    receive.recv_timeout(timeout).ok()
+}
+
+// code of the next character on stdin for GetChar, -1 at its end and U+FFFD for bytes that aren't UTF-8
+fn read_char() -> i64 {
+    let mut stdin = stdin().lock();
+    let mut bytes = vec![];
+    loop {
+        let byte = match stdin.fill_buf() {
+            Ok(buf) if !buf.is_empty() => buf[0],
+            _ => break,
+        };
+        // only continuation bytes belong to the character already started
+        if !bytes.is_empty() && byte & 0xC0 != 0x80 {
+            break;
+        }
+        stdin.consume(1);
+        bytes.push(byte);
+        let len = match bytes[0] {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+        if bytes.len() >= len {
+            break;
+        }
+    }
+    if bytes.is_empty() {
+        return -1;
+    }
+    std::str::from_utf8(&bytes).ok().and_then(|str| str.chars().next()).unwrap_or(char::REPLACEMENT_CHARACTER) as i64
 }
 
 // wrapper for differently sized integers
 #[derive(Debug, Clone)]
-enum Data {
+pub(crate) enum Data {
+    B8(i8),
     B16(i16),
     B32(i32),
     B64(i64),
 }
 impl Data {
-    fn i64(&self) -> i64 {
+    pub(crate) fn i64(&self) -> i64 {
         self.clone().into()
     }
-    fn to_bytes(&self) -> Vec<u8> {
-        match self {
-            B16(x) => x.to_le_bytes().to_vec(),
-            B32(x) => x.to_le_bytes().to_vec(),
-            B64(x) => x.to_le_bytes().to_vec(),
-        }
-    }
 }
 impl Into<i64> for Data {
     fn into(self) -> i64 {
         match self {
+            B8(num) => num as i64,
             B16(num) => num as i64,
             B32(num) => num as i64,
             B64(num) => num,
@@ -50,61 +427,546 @@ impl Into<i64> for Data {
 }
 
 pub struct PL0VM {
-    program: Vec<u8>,
+    pub(crate) program: Image,
+    // format version from the file header, None for legacy files without header
+    format_version: Option<u16>,
+    // optional sections following the program in the file
+    sections: Vec<Section>,
+    // debug symbols from the SYMS section
+    symbols: Symbols,
+    // source locations from the LINE section
+    lines: LineTable,
+    // strings from the STRS section, referenced by PutStringRef
+    strings: Vec<Vec<u8>>,
+    // provenance information from the META section
+    metadata: Metadata,
+    // byte order of arguments and constants in the image, detected from the architecture bytes
+    big_endian: bool,
     bits: Data,
-    debug: bool,
+    // structure of the image, or why it is invalid - None until a file with a valid architecture was loaded,
+    // see parse_program
+    pub(crate) layout: Option<Result<ProgramImage, String>>,
+    // CallProc or CallProcArgs right in front of ReturnProc reuses the frame of the caller, see Decoded::tail
+    pub(crate) tail_calls: bool,
+    // most bytes Alloc can hand out at the same time, see Heap
+    heap_limit: usize,
+    // first state of the numbers of Random, None for a different one every run
+    seed: Option<u64>,
+    // Clock counts instructions instead of real time
+    virtual_clock: bool,
+    // the only directory the program may use files in, None for no file access at all
+    file_root: Option<PathBuf>,
+    // numbers for ArgCount and GetArg
+    program_args: Vec<i64>,
+    // environment variables EnvNumber and EnvString may read
+    allowed_env: Vec<String>,
+    // printed before InputToAddr reads a number from stdin
+    pub(crate) prompt: String,
+    // how often InputToAddr asks again for a number after a line that isn't one
+    input_retries: usize,
+    // the whole input of every run instead of stdin
+    pub(crate) stdin_values: Option<Vec<i64>>,
+    // longest InputToAddr waits for a line
+    input_timeout: Option<Duration>,
+    // strings that aren't UTF-8
+    string_decoding: StringDecoding,
+    output_format: OutputFormat,
+    // division, modulo and the comparisons take values as unsigned numbers of the data width
+    pub(crate) unsigned: bool,
+    // numbers never overflow, see bignum.rs
+    #[cfg(feature = "bignum")]
+    bignum: bool,
+}
+
+impl Default for PL0VM {
+    fn default() -> Self {
+        PL0VM::new()
+    }
 }
 
 impl PL0VM {
-    pub fn new(debug: bool) -> PL0VM {
+    pub fn new() -> PL0VM {
         PL0VM {
-            program: vec![],
+            program: Image::default(),
+            format_version: None,
+            sections: vec![],
+            symbols: Symbols::default(),
+            lines: LineTable::default(),
+            strings: vec![],
+            metadata: Metadata::default(),
+            big_endian: false,
             bits: B16(0),
-            debug,
+            layout: None,
+            tail_calls: true,
+            heap_limit: DEFAULT_HEAP_LIMIT,
+            seed: None,
+            virtual_clock: false,
+            file_root: None,
+            program_args: vec![],
+            allowed_env: vec![],
+            prompt: DEFAULT_PROMPT.to_string(),
+            input_retries: DEFAULT_INPUT_RETRIES,
+            stdin_values: None,
+            input_timeout: None,
+            string_decoding: StringDecoding::Error,
+            output_format: OutputFormat::default(),
+            unsigned: false,
+            #[cfg(feature = "bignum")]
+            bignum: false,
         }
     }
-    fn data_size(&self) -> usize { match self.bits { B16(_) => 2, B32(_) => 4, B64(_) => 8 } }
+    pub(crate) fn data_size(&self) -> usize { match self.bits { B8(_) => 1, B16(_) => 2, B32(_) => 4, B64(_) => 8 } }
 
-    fn data_true(&self) -> Data { match self.bits { B16(_) => B16(1), B32(_) => B32(1), B64(_) => B64(1) } }
-    fn data_false(&self) -> Data { match self.bits { B16(_) => B16(0), B32(_) => B32(0), B64(_) => B64(0) } }
-    fn data_bool(&self, val: bool) -> Data { match val { true => self.data_true(), false => self.data_false() } }
+    // cut a value down to the data width, overflowing like the architecture would
+    fn wrap(&self, val: i64) -> i64 {
+        if self.bignum() {
+            return val;
+        }
+        match self.bits { B8(_) => val as i8 as i64, B16(_) => val as i16 as i64, B32(_) => val as i32 as i64, B64(_) => val }
+    }
+    // the bits of a value as an unsigned number of the data width
+    fn as_unsigned(&self, val: i64) -> u64 { match self.bits { B8(_) => val as u8 as u64, B16(_) => val as u16 as u64, B32(_) => val as u32 as u64, B64(_) => val as u64 } }
+    // whether values grow beyond the data width instead of wrapping, always false without the feature bignum
+    pub fn bignum(&self) -> bool {
+        #[cfg(feature = "bignum")]
+        return self.bignum;
+        #[cfg(not(feature = "bignum"))]
+        false
+    }
+
+    // Floats are stored as their bits, f32 on 32 bit and f64 on 64 bit architectures - the verifier doesn't let
+    // float instructions run on smaller ones. f32 values are calculated as f64 and rounded back, which gives the
+    // same results for the basic operations.
+    pub(crate) fn float(&self, val: i64) -> f64 {
+        match self.bits { B32(_) => f32::from_bits(val as u32) as f64, _ => f64::from_bits(val as u64) }
+    }
+    pub(crate) fn float_value(&self, val: f64) -> i64 {
+        match self.bits { B32(_) => (val as f32).to_bits() as i32 as i64, _ => val.to_bits() as i64 }
+    }
+    // a float as the program prints it, in its own precision
+    pub(crate) fn float_string(&self, val: i64) -> String {
+        match self.bits { B32(_) => f32::from_bits(val as u32).to_string(), _ => f64::from_bits(val as u64).to_string() }
+    }
+    // a value as OutputValue prints it
+    pub(crate) fn value_text(&self, val: i64) -> String {
+        let size = self.data_size();
+        match (self.output_format.hex, self.output_format.pad) {
+            (true, true) => format!("{:01$X}", self.as_unsigned(val), size * 2),
+            (true, false) => format!("{:X}", self.as_unsigned(val)),
+            (false, true) => {
+                let digits = [3, 5, 10, 19][size.trailing_zeros() as usize];
+                format!("{}{:02$}", if val < 0 { "-" } else { "" }, val.unsigned_abs(), digits)
+            },
+            (false, false) => val.to_string(),
+        }
+    }
+    // print the value for OutputValue
+    pub(crate) fn write_value(&self, out: &mut dyn Write, val: i64) {
+        let result = match self.output_format {
+            OutputFormat { hex: false, pad: false, no_newline: false } => writeln!(out, "{val}"),
+            OutputFormat { no_newline: false, .. } => writeln!(out, "{}", self.value_text(val)),
+            OutputFormat { no_newline: true, .. } => write!(out, "{}", self.value_text(val)),
+        };
+        result.expect("Could not write program output");
+    }
+    // a string of the program as text, Err is the message if it isn't UTF-8 and the string decoding makes that an error
+    pub(crate) fn decode_string<'a>(&self, bytes: &'a [u8]) -> Result<Cow<'a, str>, String> {
+        match std::str::from_utf8(bytes) {
+            Ok(str) => Ok(Cow::Borrowed(str)),
+            Err(err) => match self.string_decoding {
+                StringDecoding::Error => Err(t!("pl0.invalid_str", err = err).to_string()),
+                StringDecoding::Lossy => Ok(String::from_utf8_lossy(bytes)),
+                StringDecoding::Latin1 => Ok(Cow::Owned(bytes.iter().map(|&byte| byte as char).collect())),
+            },
+        }
+    }
 
-    pub fn from_file(debug: bool, filename: &str) -> Result<PL0VM, std::io::Error> {
-        let mut pl0vm = PL0VM::new(debug);
+    pub fn from_file(filename: &str) -> Result<PL0VM, std::io::Error> {
+        let mut pl0vm = PL0VM::new();
         match pl0vm.load_from_file(filename) {
             Ok(_) => Ok(pl0vm),
             Err(e) => Err(e),
         }
     }
 
+    pub fn from_bytes(bytes: Vec<u8>) -> PL0VM {
+        let mut pl0vm = PL0VM::new();
+        pl0vm.load_from_bytes(bytes);
+        pl0vm
+    }
+
+    // with the mmap feature, uncompressed binary files are mapped into memory instead of read
     pub fn load_from_file(&mut self, filename: &str) -> Result<bool, std::io::Error> {
-        match std::fs::read(filename) {
-            Ok(bytes) => {
-                self.program = bytes;
-                self.bits = match self.read_arg(ARG_SIZE) {
-                    Some(val) => match val {
-                        2 => B16(0),
-                        4 => B32(0),
-                        8 => B64(0),
-                        _ => return Ok(false),
-                    },
-                    None => return Ok(false),
-                };
-                Ok(true)
-            },
+        #[cfg(feature = "mmap")]
+        if let Some(image) = Image::map_file(filename)? {
+            return Ok(self.load_image(image));
+        }
+        match std::fs::read(filename).and_then(format::decompress).and_then(format::from_hex_text) {
+            Ok(bytes) => Ok(self.load_from_bytes(bytes)),
             Err(err) => { Err(err) },
         }
     }
 
-    fn read_arg(&self, offset: usize) -> Option<i16> {
+    pub fn load_from_bytes(&mut self, bytes: Vec<u8>) -> bool {
+        self.load_image(Image::Owned(bytes))
+    }
+
+    fn load_image(&mut self, file: Image) -> bool {
+        self.layout = None;
+        // strip the header, so that all addresses stay relative to the start of the legacy layout
+        let contents = match format::parse(&file) {
+            Some(val) => val,
+            None => {
+                self.program = Image::default();
+                return false;
+            },
+        };
+        self.program = file.narrow(contents.image.clone());
+        self.format_version = contents.version;
+        self.sections = contents.sections;
+        self.symbols = match self.section(SYMBOLS_TAG).map(Symbols::parse) {
+            Some(Some(symbols)) => symbols,
+            Some(None) => {
+                error(&t!("pl0.invalid_symbols"));
+                Symbols::default()
+            },
+            None => Symbols::default(),
+        };
+        self.lines = match self.section(LINES_TAG).map(LineTable::parse) {
+            Some(Some(lines)) => lines,
+            Some(None) => {
+                error(&t!("pl0.invalid_lines"));
+                LineTable::default()
+            },
+            None => LineTable::default(),
+        };
+        self.strings = match self.section(STRINGS_TAG) {
+            Some(data) if data.last().is_none_or(|&b| b == 0) => {
+                data.split(|&b| b == 0).take(data.iter().filter(|&&b| b == 0).count()).map(|str| str.to_vec()).collect()
+            },
+            Some(_) => {
+                error(&t!("pl0.invalid_strings"));
+                vec![]
+            },
+            None => vec![],
+        };
+        self.metadata = match self.section(METADATA_TAG).map(Metadata::parse) {
+            Some(Some(metadata)) => metadata,
+            Some(None) => {
+                error(&t!("pl0.invalid_metadata"));
+                Metadata::default()
+            },
+            None => Metadata::default(),
+        };
+        if !self.format_supported() { return false; }
+        // little endian files have the architecture in byte 3, big endian ones in byte 4
+        self.big_endian = self.program.get(2) == Some(&0) && matches!(self.program.get(3), Some(1 | 2 | 4 | 8));
+        self.bits = match self.read_arg(ARG_SIZE) {
+            Some(val) => match val {
+                1 => B8(0),
+                2 => B16(0),
+                4 => B32(0),
+                8 => B64(0),
+                _ => return false,
+            },
+            None => return false,
+        };
+        self.reparse();
+        matches!(self.layout, Some(Ok(_)))
+    }
+
+    pub fn program(&self) -> &[u8] {
+        &self.program
+    }
+    pub fn is_big_endian(&self) -> bool {
+        self.big_endian
+    }
+    pub fn format_version(&self) -> Option<u16> {
+        self.format_version
+    }
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+    pub fn section(&self, tag: &[u8; 4]) -> Option<&[u8]> {
+        self.sections.iter().find(|section| &section.tag == tag).map(|section| section.data.as_slice())
+    }
+    // add the section or replace its data if it already exists
+    pub fn set_section(&mut self, tag: &[u8; 4], data: Vec<u8>) {
+        match self.sections.iter_mut().find(|section| &section.tag == tag) {
+            Some(section) => section.data = data,
+            None => self.sections.push(Section { tag: *tag, data }),
+        }
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.set_section(SYMBOLS_TAG, symbols.to_bytes());
+        self.symbols = symbols;
+    }
+    pub fn line_table(&self) -> &LineTable {
+        &self.lines
+    }
+    pub fn set_line_table(&mut self, lines: LineTable) {
+        self.set_section(LINES_TAG, lines.to_bytes());
+        self.lines = lines;
+    }
+    pub fn strings(&self) -> &[Vec<u8>] {
+        &self.strings
+    }
+    pub fn set_strings(&mut self, strings: Vec<Vec<u8>>) {
+        self.set_section(STRINGS_TAG, strings.iter().flat_map(|str| str.iter().copied().chain([0])).collect());
+        self.strings = strings;
+    }
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+    pub fn set_metadata(&mut self, metadata: Metadata) {
+        self.set_section(METADATA_TAG, metadata.to_bytes());
+        self.metadata = metadata;
+    }
+    pub fn expected_output(&self) -> Option<&[u8]> {
+        self.section(EXPECTED_OUTPUT_TAG)
+    }
+    pub fn set_expected_output(&mut self, output: Vec<u8>) {
+        self.set_section(EXPECTED_OUTPUT_TAG, output);
+    }
+    // without tail calls every call gets its own frame, so backtraces show all of them
+    pub fn set_tail_calls(&mut self, enabled: bool) {
+        self.tail_calls = enabled;
+    }
+    // the heap can't be larger than the addresses the architecture can hold
+    pub fn set_heap_limit(&mut self, bytes: usize) {
+        self.heap_limit = bytes;
+    }
+    // Random gives the same numbers in every run with the same seed
+    pub fn set_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+    }
+    // the virtual clock advances one millisecond every VIRTUAL_CLOCK_RATE instructions, so Clock gives the same times
+    // in every run, however fast it goes
+    pub fn set_virtual_clock(&mut self, enabled: bool) {
+        self.virtual_clock = enabled;
+    }
+    // let FileOpen open files in the directory and below it, see files.rs
+    pub fn allow_files(&mut self, dir: Option<PathBuf>) {
+        self.file_root = dir;
+    }
+    // the arguments of the program, like the numbers after -- on the command line
+    pub fn set_program_args(&mut self, args: Vec<i64>) {
+        self.program_args = args;
+    }
+    // the environment variables the program may read, it can't see any others
+    pub fn allow_env(&mut self, names: Vec<String>) {
+        self.allowed_env = names;
+    }
+    // text to show before waiting for a number from stdin, like the classic PL/0 interpreters do - empty for none
+    pub fn set_prompt(&mut self, prompt: String) {
+        self.prompt = prompt;
+    }
+    // the user may mistype that many times in a row before it is a runtime error; without a terminal the first line
+    // that isn't a number already is one, nobody could correct it
+    pub fn set_input_retries(&mut self, retries: usize) {
+        self.input_retries = retries;
+    }
+    // InputToAddr and GetChar read these numbers instead of stdin, reading more of them is a runtime error
+    pub fn set_stdin_values(&mut self, values: Option<Vec<i64>>) {
+        self.stdin_values = values;
+    }
+    // stop a run that waits longer for a number, so a program reading stdin that never gets anything doesn't hang
+    // forever in a pipeline - the runtime error is followed by INPUT_TIMEOUT_STATUS as the status
+    pub fn set_input_timeout(&mut self, timeout: Option<Duration>) {
+        self.input_timeout = timeout;
+    }
+    // print values the way the expected output of older reference solutions has them
+    pub fn set_output_format(&mut self, format: OutputFormat) {
+        self.output_format = format;
+    }
+    // how PutString, PutStringRef, OutputString and the analysis show strings that aren't UTF-8
+    pub fn set_string_decoding(&mut self, decoding: StringDecoding) {
+        self.string_decoding = decoding;
+    }
+    // the runtime error for reading after the last queued number
+    fn input_closed_error(&self) -> String {
+        match &self.stdin_values {
+            Some(values) => t!("pl0.error.input_exhausted", count = values.len()).to_string(),
+            None => t!("pl0.error.input_closed").to_string(),
+        }
+    }
+    // for bytecode of compilers whose VM calculates without signs: division, modulo and the comparisons see e.g. -1 as
+    // the largest value, the other operations work the same either way
+    pub fn set_unsigned(&mut self, enabled: bool) {
+        self.unsigned = enabled;
+    }
+    // arithmetic and comparisons with numbers of any size, the superinstructions, the fast path and the JIT are
+    // left out then
+    #[cfg(feature = "bignum")]
+    pub fn set_bignum(&mut self, enabled: bool) {
+        self.bignum = enabled;
+    }
+    // forget the loaded program, settings like tail_calls stay
+    pub fn reset(&mut self) {
+        *self = PL0VM { tail_calls: self.tail_calls, heap_limit: self.heap_limit, seed: self.seed, virtual_clock: self.virtual_clock, file_root: self.file_root.clone(),
+            program_args: self.program_args.clone(), allowed_env: self.allowed_env.clone(), unsigned: self.unsigned,
+            prompt: self.prompt.clone(), input_retries: self.input_retries,
+            stdin_values: self.stdin_values.clone(), input_timeout: self.input_timeout,
+            string_decoding: self.string_decoding, output_format: self.output_format,
+            #[cfg(feature = "bignum")]
+            bignum: self.bignum, ..PL0VM::new() };
+    }
+    // run the program and compare its output with the expected one - None if the file has no expected output
+    pub fn selftest(&self) -> Option<bool> {
+        let expected = self.expected_output()?;
+        Some(self.execute_captured() == expected)
+    }
+    // replace all inline PutString instructions with PutStringRef, storing every distinct string once in the string table
+    pub fn move_strings_to_table(&mut self) -> Result<(), String> {
+        let mut strings = self.strings.clone();
+        // patch from the end, so that the addresses of the remaining instructions stay valid
+        for instr in self.decode_code()?.to_vec().iter().rev().filter(|instr| instr.op == OpCode::PutString) {
+            let str = self.program[(instr.addr + 1)..(instr.addr + instr.len - 1)].to_vec();
+            let index = match strings.iter().position(|other| *other == str) {
+                Some(val) => val,
+                None => {
+                    strings.push(str);
+                    strings.len() - 1
+                },
+            };
+            // indexes beyond the normal argument range need the Wide prefix
+            let mut code = vec![];
+            match (i16::try_from(index), i32::try_from(index)) {
+                (Ok(index), _) => {
+                    code.push(OpCode::PutStringRef.into());
+                    code.extend(if self.big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+                },
+                (_, Ok(index)) => {
+                    code.extend([u8::from(OpCode::Wide), u8::from(OpCode::PutStringRef)]);
+                    code.extend(if self.big_endian { index.to_be_bytes() } else { index.to_le_bytes() });
+                },
+                _ => return Err(t!("pl0.string_table_full").to_string()),
+            }
+            self.replace_instruction(instr.addr, &code)?;
+        }
+        self.set_strings(strings);
+        Ok(())
+    }
+
+    // source file and line of the code at addr, if the file contains a line table
+    pub fn source_location(&self, addr: usize) -> Option<(&str, u32)> {
+        self.lines.lookup(addr)
+    }
+    // print the error, with the source location of the instruction if known - always returns Step::Error
+    pub(crate) fn runtime_error(&self, pc: usize, msg: &str) -> Step {
+        match self.source_location(pc) {
+            Some((file, line)) => error(&t!("pl0.error.at_source", msg = msg, file = file, line = line)),
+            None => error(msg),
+        }
+        Step::Error
+    }
+
+    // name of the procedure from the debug symbols, or its ID if there is none
+    pub(crate) fn proc_label(&self, proc_id: usize) -> String {
+        match self.symbols.procedure(proc_id as u16) {
+            Some(name) => name.to_string(),
+            None => proc_id.to_string(),
+        }
+    }
+    // " (name)" if there is a debug symbol for the procedure
+    pub(crate) fn proc_suffix(&self, proc_id: usize) -> String {
+        match self.symbols.procedure(proc_id as u16) {
+            Some(name) => format!(" ({name})"),
+            None => String::new(),
+        }
+    }
+    // " (name)" if there is a debug symbol for the variable
+    pub(crate) fn var_suffix(&self, proc_id: usize, addr: i32) -> String {
+        match u16::try_from(addr).ok().and_then(|addr| self.symbols.variable(proc_id as u16, addr)) {
+            Some(name) => format!(" ({name})"),
+            None => String::new(),
+        }
+    }
+
+    // the whole file in the current format, including all sections
+    pub fn to_file_bytes(&self) -> Vec<u8> {
+        format::serialize(&self.program, &self.sections)
+    }
+    pub fn save_to_file(&self, filename: &str) -> Result<(), std::io::Error> {
+        std::fs::write(filename, self.to_file_bytes())
+    }
+
+    pub fn save_compressed(&self, filename: &str) -> Result<(), std::io::Error> {
+        std::fs::write(filename, format::compress(&self.to_file_bytes()))
+    }
+
+    pub fn add_checksum(&mut self) {
+        self.set_section(CHECKSUM_TAG, format::crc32(&self.program).to_le_bytes().to_vec());
+    }
+    // None if the file has no checksum
+    pub fn verify_checksum(&self) -> Option<bool> {
+        self.section(CHECKSUM_TAG).map(|sum| sum == format::crc32(&self.program).to_le_bytes())
+    }
+
+    fn format_supported(&self) -> bool {
+        self.format_version.is_none_or(|version| version > 0 && version <= FORMAT_VERSION)
+    }
+
+    // check that the loaded file looks like bytecode this VM can handle, printing an error otherwise
+    fn check_file(&self) -> bool {
+        if let Some(version) = self.format_version {
+            if version > FORMAT_VERSION {
+                error(&t!("pl0.unsupported_version", version = version, supported = FORMAT_VERSION));
+                return false;
+            }
+        }
+        if !self.format_supported() || self.program.len() <= 4 || self.program[if self.big_endian { 2 } else { 3 }] > 0 {
+            error(&t!("pl0.invalid_file"));
+            return false;
+        }
+        if self.verify_checksum() == Some(false) {
+            error(&t!("pl0.checksum_mismatch"));
+            return false;
+        }
+        if let Some(Err(err)) = &self.layout {
+            error(err);
+            return false;
+        }
+        true
+    }
+
+    pub(crate) fn read_arg(&self, offset: usize) -> Option<i16> {
         match self.program.get(offset..(offset + ARG_SIZE)) {
-            Some(val) => Some(i16::from_le_bytes(val.try_into().expect("Invalid byte count?!"))),
+            Some(val) => Some(match self.big_endian {
+                true => i16::from_be_bytes(val.try_into().expect("Invalid byte count?!")),
+                false => i16::from_le_bytes(val.try_into().expect("Invalid byte count?!")),
+            }),
             None => None
         }
     }
+    pub(crate) fn write_arg(&mut self, offset: usize, val: i16) {
+        let bytes = if self.big_endian { val.to_be_bytes() } else { val.to_le_bytes() };
+        self.program[offset..(offset + ARG_SIZE)].copy_from_slice(&bytes);
+    }
+    // read a normal or (with the Wide prefix) 4 byte argument
+    pub(crate) fn read_operand(&self, offset: usize, wide: bool) -> Option<i32> {
+        if !wide {
+            return self.read_arg(offset).map(|val| val as i32);
+        }
+        let bytes = self.program.get(offset..(offset + WIDE_ARG_SIZE))?.try_into().expect("Invalid byte count?!");
+        Some(if self.big_endian { i32::from_be_bytes(bytes) } else { i32::from_le_bytes(bytes) })
+    }
+    // the caller has to make sure that val fits into a normal argument if wide is false
+    pub(crate) fn write_operand(&mut self, offset: usize, val: i32, wide: bool) {
+        if !wide {
+            return self.write_arg(offset, val as i16);
+        }
+        let bytes = if self.big_endian { val.to_be_bytes() } else { val.to_le_bytes() };
+        self.program[offset..(offset + WIDE_ARG_SIZE)].copy_from_slice(&bytes);
+    }
     fn bytes_to_data(&self, bytes: &Option<&[u8]>) -> Option<Data> {
         match bytes {
             Some(bytes) => Some(match self.bits {
+                B8(_) => B8(i8::from_le_bytes(bytes[0..1].try_into().expect("Invalid byte count?!"))),
                 B16(_) => B16(i16::from_le_bytes(bytes[0..2].try_into().expect("Invalid byte count?!"))),
                 B32(_) => B32(i32::from_le_bytes(bytes[0..4].try_into().expect("Invalid byte count?!"))),
                 B64(_) => B64(i64::from_le_bytes(bytes[0..8].try_into().expect("Invalid byte count?!"))),
@@ -112,776 +974,1946 @@ impl PL0VM {
             None => None,
         }
     }
-    fn read_data(&self, offset: usize) -> Option<Data> {
-        self.bytes_to_data(&self.program.get(offset..))
+    // stack address as a value, None if it doesn't fit into the architecture
+    fn address_value(&self, addr: usize) -> Option<i64> {
+        Some(match self.bits {
+            B8(_) => i8::try_from(addr).ok()? as i64,
+            B16(_) => i16::try_from(addr).ok()? as i64,
+            B32(_) => i32::try_from(addr).ok()? as i64,
+            B64(_) => i64::try_from(addr).ok()?,
+        })
+    }
+    // read a constant from the program - the stack always uses little endian, so convert if needed
+    pub(crate) fn read_data(&self, offset: usize) -> Option<Data> {
+        let bytes = self.program.get(offset..(offset + self.data_size()))?;
+        match self.big_endian {
+            true => self.bytes_to_data(&Some(&bytes.iter().rev().copied().collect::<Vec<u8>>())),
+            false => self.bytes_to_data(&Some(bytes)),
+        }
     }
 
     pub fn print_analysis(&self) {
-        if self.program.len() <= 4 || self.program[3] > 0 {
-            error(&t!("pl0.invalid_file"));
-            return;
-        }
+        if !self.check_file() { return; }
 
-        let mut pc = 4;
-        let mut procedure_count = match self.read_arg(0) {
+        if let Some(version) = self.format_version {
+            diagln!("{}: {}", t!("pl0.format_version"), version);
+        }
+        if self.verify_checksum().is_some() {
+            diagln!("{}: {:08X}", t!("pl0.checksum"), format::crc32(&self.program));
+        }
+        for (key, value) in &self.metadata.entries {
+            diagln!("{}: {} = {}", t!("pl0.metadata"), key, value);
+        }
+        let procedure_count = match self.read_arg(0) {
             Some(val) => val,
             None => return error("unreachable code"),
         };
-        print!("0000: {}: {:04X} = {}, ", t!("pl0.procedure_count"), procedure_count, procedure_count);
+        diag!("0000: {}: {:04X} = {}, ", t!("pl0.procedure_count"), procedure_count, procedure_count);
         let arch = match self.read_arg(ARG_SIZE) {
             Some(val) => val,
             None => return error("unreachable code"),
         };
-        print!("{}: {:04X} = ", t!("pl0.arch"), arch);
+        diag!("{}: {:04X} = ", t!("pl0.arch"), arch);
+        let endianness = if self.big_endian { t!("pl0.big_endian") } else { "".into() };
         match arch {
-            2 => println!("16 bit"),
-            4 => println!("32 bit"),
-            8 => println!("64 bit"),
-            _ => println!("{}", t!("pl0.invalid")),
+            1 => diagln!("8 bit{endianness}"),
+            2 => diagln!("16 bit{endianness}"),
+            4 => diagln!("32 bit{endianness}"),
+            8 => diagln!("64 bit{endianness}"),
+            _ => diagln!("{}", t!("pl0.invalid")),
         }
-        if arch != 2 && arch != 4 && arch != 8 {
+        if !matches!(arch, 1 | 2 | 4 | 8) {
             error(&t!("pl0.arch_invalid", arch = arch:{:04X}));
             return;
         }
 
-        let print_arg = |pc: &mut usize, last: bool| {
-            let val = match self.read_arg(*pc) {
-                Some(val) => val,
-                None => return false,
-            };
-            print!("{:0HEX_ARG_SIZE$X}{}", val, if last { "" } else { ", " });
-            *pc += ARG_SIZE;
-            return true;
+        // arguments as hex, negative ones in two's complement of their size
+        let hex_arg = |val: i32, wide: bool| if wide { format!("{:08X}", val) } else { format!("{:0HEX_ARG_SIZE$X}", val as i16) };
+        let arg = |instr: &Instruction, i: usize| self.read_operand(instr.arg_addr(i), instr.wide).expect("length checked while decoding");
+        let image = match self.program_image() {
+            Ok(val) => val,
+            Err(err) => return error(&err),
         };
 
-        let mut rem_bytes = 0;
-        loop {
-            let byte = match self.program.get(pc) {
-                Some(val) => val,
-                None => return error(&t!("pl0.error.invalid_pc", pc = pc:{:04X})),
-            };
-            let opc = pc;
-            let op = match OpCode::try_from(*byte) {
-                Ok(op) => op,
-                Err(_) => {
-                    error(&t!("pl0.unknown_opcode", op = byte:{:02X}));
-                    break;
-                },
-            };
-            print!("{:04X}: {:02X} {:<21} ", pc, byte, op);
-            pc += 1;
+        let unreachable = self.unreachable_code().unwrap_or_default();
+        // jump targets, sorted by address, get the labels L1, L2, ...
+        let mut labels: Vec<usize> = image.instructions.iter().filter_map(|instr| self.jump_target(instr)).collect();
+        labels.sort();
+        labels.dedup();
+        let label = |addr: usize| labels.binary_search(&addr).ok().map(|i| format!("L{}", i + 1));
+        // ID of the procedure being listed, for variable names
+        let mut cur_proc = 0usize;
+        for instr in &image.instructions {
+            let (op, wide) = (instr.op, instr.wide);
+            // the Wide prefix is listed together with the instruction it belongs to
+            let name = if wide { format!("{} {}", OpCode::Wide, op) } else { op.to_string() };
+            if let Some(label) = label(instr.addr) {
+                diagln!("{}", paint(format!("{label}:"), Part::Header));
+            }
+            diag!("{:04X}: {:02X} {} ", instr.addr, u8::from(op), paint(format!("{:<21}", name), Part::Mnemonic));
+            let args: Vec<String> = (0..op.arg_count()).map(|i| paint(hex_arg(arg(instr, i), wide), Part::Operand)).collect();
             match op {
-                OpCode::PushValueLocalVar | OpCode::PushValueMainVar
-                    | OpCode::PushAddressLocalVar | OpCode::PushAddressMainVar
-                    | OpCode::CallProc | OpCode::PushConstant => {
-                    print_arg(&mut pc, true);
-                },
+                OpCode::PushValueLocalVar | OpCode::PushAddressLocalVar => diag!("{}{}", args[0], self.var_suffix(cur_proc, arg(instr, 0))),
+                OpCode::PushValueMainVar | OpCode::PushAddressMainVar => diag!("{}{}", args[0], self.var_suffix(0, arg(instr, 0))),
+                OpCode::CallProc => diag!("{}{}", args[0], self.proc_suffix(arg(instr, 0) as usize)),
+                OpCode::CallProcArgs => diag!("{}{}, {}", args[0], self.proc_suffix(arg(instr, 0) as usize), args[1]),
                 OpCode::Jump | OpCode::JumpIfFalse => {
-                    let arg = match self.read_arg(pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    let target = match (pc + ARG_SIZE).checked_add_signed(arg as isize) {
+                    let offset = arg(instr, 0);
+                    // jumps are relative to the end of the instruction
+                    let target = match (instr.addr + instr.len).checked_add_signed(offset as isize) {
                         Some(target) => target,
                         None => {
-                            error(&t!("pl0.invalid_jump", pc = pc, arg = arg));
+                            error(&t!("pl0.invalid_jump", pc = instr.arg_addr(0), arg = offset));
                             break;
                         },
                     };
-                    print!("{}{:0HEX_ARG_SIZE$X} => {:0HEX_ARG_SIZE$X}", if arg < 0 { "-" } else { "" }, arg.abs(), target);
-                    pc += ARG_SIZE;
+                    let offset = format!("{}{}", if offset < 0 { "-" } else { "" }, hex_arg(offset.abs(), wide));
+                    diag!("{} => {}", paint(offset, Part::Operand), paint(format!("{:0HEX_ARG_SIZE$X}", target), Part::Operand));
+                    if let Some(label) = label(target) {
+                        diag!(" ({})", paint(label, Part::Header));
+                    }
                 },
                 OpCode::PushValueGlobalVar | OpCode::PushAddressGlobalVar => {
-                    print_arg(&mut pc, false);
-                    print_arg(&mut pc, true);
+                    diag!("{}{}", args.join(", "), self.var_suffix(arg(instr, 1) as usize, arg(instr, 0)));
                 },
                 OpCode::EntryProc => {
-                    rem_bytes = match self.read_arg(pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    print!("{:0HEX_ARG_SIZE$X}, ", rem_bytes);
-                    pc += ARG_SIZE;
-                    let pid = match self.read_arg(pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    print!("{:0HEX_ARG_SIZE$X}, ", pid);
-                    pc += ARG_SIZE;
-                    print_arg(&mut pc, true);
-                    print!(" <<< {}", if pid == 0 { t!("pl0.proc_start_main") } else { t!("pl0.proc_start") });
-                    procedure_count -= 1;
-                }
+                    let pid = arg(instr, 1);
+                    let start = format!("<<< {}{}", if pid == 0 { t!("pl0.proc_start_main") } else { t!("pl0.proc_start") }, self.proc_suffix(pid as usize));
+                    diag!("{} {}", args.join(", "), paint(start, Part::Header));
+                    cur_proc = pid as usize;
+                },
                 OpCode::PutString => {
-                    let strb: Vec<_> = self.program.iter().skip(pc).take_while(|&&b| b != 0).map(|b| *b).collect();
-                    pc += strb.len() + 1;
-                    let str = match String::from_utf8(strb) {
+                    let str = match self.decode_string(&self.program[(instr.addr + 1)..(instr.addr + instr.len - 1)]) {
                         Ok(str) => str,
                         Err(err) => {
-                            error(&t!("pl0.invalid_str", err = err));
+                            error(&err);
                             break;
                         }
                     };
-                    print!("\"{str}\"");
-                }
-                _ => {},
+                    diag!("{}", paint(format!("\"{str}\""), Part::String));
+                },
+                OpCode::PutStringRef | OpCode::PushString | OpCode::Assert => {
+                    diag!("{}", args[0]);
+                    if let Some(str) = self.strings.get(arg(instr, 0) as usize) {
+                        let str = self.decode_string(str).unwrap_or_else(|_| String::from_utf8_lossy(str));
+                        diag!(" {}", paint(format!("\"{str}\""), Part::String));
+                    }
+                },
+                _ => diag!("{}", args.join(", ")),
+            }
+            if unreachable.contains(&instr.addr) {
+                diag!(" {}", paint(format!("<<< {}", t!("pl0.unreachable")), Part::Note));
             }
-            rem_bytes -= (pc - opc) as i16;
-
-            println!();
 
-            if rem_bytes <= 0 && procedure_count == 0 { break; }
+            diagln!();
         }
-        (0..((self.program.len() - pc) / self.data_size())).map(|i| self.read_data(pc + self.data_size() * i)).enumerate().for_each(|(i, constant)| {
-            let ds2 = self.data_size() * 2;
-            let c = match constant {
-                Some(val) => val,
-                None => return error(&t!("pl0.error.invalid_constant_read", i = i)),
-            }.i64();
+        let ds2 = self.data_size() * 2;
+        for (i, c) in image.constants.iter().enumerate() {
             let cstr = format!("{:0ds2$X}", c);
-            println!("{} {:04}: 0x{} = {}", t!("pl0.constant"), i, &cstr[cstr.len() - ds2..], c);
-        });
+            diagln!("{} {:04}: 0x{} = {}", t!("pl0.constant"), i, &cstr[cstr.len() - ds2..], c);
+        }
+        self.print_stack_usage();
+        let rest = (self.program.len() - image.code_end) % self.data_size();
+        if rest != 0 {
+            error(&t!("pl0.layout.trailing_bytes", count = rest, offset = (self.program.len() - rest):{:04X}));
+        }
+        for (jump, target) in self.state_invariant_loops().unwrap_or_default() {
+            error(&t!("pl0.infinite_loop", start = target:{:04X}, end = jump:{:04X}));
+        }
     }
 
-    fn load_data(&self) -> Option<(Vec<Procedure>, Vec<Data>)> {
-        let mut procedure_count = self.read_arg(0).expect("failed to read procedure count - should be unreachable");
-        let mut procedures = Vec::with_capacity(procedure_count as usize);
-        procedures.resize_with(procedures.capacity(), || None);
-        let mut pc = 4;
-
-        let mut rem_bytes = 0;
-        loop {
-            let byte = match self.program.get(pc) {
-                Some(val) => *val,
-                None => { error(&t!("pl0.error.preload_error")); return None },
-            };
-            let opc = pc;
-            pc += 1;
-            if rem_bytes == 0 && byte == <OpCode as Into<u8>>::into(OpCode::EntryProc) {
-                rem_bytes = match self.read_arg(pc) {
-                    Some(val) => val,
-                    None => { error(&t!("pl0.error.preload_error")); return None },
-                };
-                pc += ARG_SIZE;
-                let proc_id = match self.read_arg(pc) {
-                    Some(val) => val,
-                    None => { error(&t!("pl0.error.preload_error")); return None },
-                } as usize;
-                pc += ARG_SIZE * 2;
-                if proc_id >= procedures.len() {
-                    error(&t!("pl0.error.invalid_preload_procedure"));
-                    return None;
-                }
-                procedures[proc_id] = Some(Procedure {
-                    start_pos: pc - 1 - ARG_SIZE * 3,
-                    frame_ptr: 0,
-                });
-                procedure_count -= 1;
-            }
-            rem_bytes -= (pc - opc) as i16;
-
-            if rem_bytes <= 0 && procedure_count == 0 { break; }
+    fn load_data(&self) -> Option<(Vec<Procedure>, Vec<i64>)> {
+        // the layout was validated while loading
+        let image = match self.program_image() {
+            Ok(val) => val,
+            Err(_) => { error(&t!("pl0.error.preload_error")); return None },
+        };
+        let mut procedures = Vec::with_capacity(image.procedures.len());
+        procedures.resize_with(image.procedures.len(), || None);
+        for (proc_id, range) in &image.procedures {
+            let entry = decode_instruction(&self.program, range.start).expect("layout checked by parse_program");
+            procedures[*proc_id] = Some(Procedure {
+                start_pos: range.start,
+                frame_ptr: 0,
+                vars: self.read_operand(entry.arg_addr(2), entry.wide).expect("length checked while decoding").max(0) as usize,
+            });
         }
         Some((
-            procedures.into_iter().map(|procedure| procedure.unwrap()).collect(),
-            (0..((self.program.len() - pc) / self.data_size())).map(|i| self.read_data(pc + self.data_size() * i).expect(&t!("pl0.error.invalid_constant_read", i = i))).collect(),
+            procedures.into_iter().map(|procedure| procedure.expect("IDs checked by parse_program")).collect(),
+            image.constants.clone(),
         ))
     }
 
-    //noinspection RsConstantConditionIf
-    pub fn execute(&self) {
-        if self.program.len() <= 4 || self.program[3] > 0 {
-            error(&t!("pl0.invalid_file"));
-            return;
+    // returns the status of Halt, START_FAILED_STATUS if the program couldn't start, 0 if it ended otherwise; the output
+    // is buffered, it comes out before the program reads input, at runtime errors and at the end
+    pub fn execute(&self) -> i64 {
+        self.run(&mut BufWriter::new(stdout()))
+    }
+    // run and return everything the program printed
+    pub fn execute_captured(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.run(&mut out);
+        out
+    }
+    // run like execute and return the seed and the numbers the program read, for replay files, with the status like
+    // execute
+    pub fn execute_recording(&self) -> (Replay, i64) {
+        let mut execution = match self.start() {
+            Some(val) => val,
+            None => return (Replay::default(), START_FAILED_STATUS),
+        };
+        execution.run(&mut BufWriter::new(stdout()));
+        (Replay { seed: Some(execution.seed()), inputs: execution.inputs().to_vec() }, execution.status())
+    }
+    // run like execute, but with the recorded seed and the recorded numbers instead of stdin (reading more of them is
+    // a runtime error), returns how many of them weren't read and the status like execute
+    pub fn execute_replay(&mut self, replay: &Replay) -> (usize, i64) {
+        if replay.seed.is_some() {
+            self.set_seed(replay.seed);
+        }
+        let inputs = &replay.inputs;
+        let mut execution = match self.start() {
+            Some(val) => val,
+            None => return (inputs.len(), START_FAILED_STATUS),
+        };
+        execution.feed_inputs(inputs.iter().copied());
+        execution.close_input();
+        execution.run(&mut BufWriter::new(stdout()));
+        (execution.pending_inputs(), execution.status())
+    }
+    // run like execute, but with the checks the verifier did left out, see Execution::run_fast - logging every
+    // instruction needs the normal steps
+    pub fn execute_fast(&self) -> i64 {
+        if log::log_enabled!(log::Level::Debug) || self.bignum() {
+            return self.execute();
+        }
+        match self.start() {
+            Some(mut execution) => {
+                execution.run_fast(&mut BufWriter::new(stdout()));
+                execution.status()
+            },
+            None => START_FAILED_STATUS,
+        }
+    }
+    // run like execute and return the counters of the run, None if it couldn't start, with the status like execute
+    pub fn execute_metrics(&self) -> (Option<Metrics>, i64) {
+        let mut execution = match self.start() {
+            Some(val) => val,
+            None => return (None, START_FAILED_STATUS),
+        };
+        execution.run(&mut BufWriter::new(stdout()));
+        (Some(execution.metrics()), execution.status())
+    }
+    // run like execute, returns the execution if a runtime error stopped it, for core dumps, with the status like execute
+    pub fn execute_until_error(&self) -> (Option<Execution<'_>>, i64) {
+        let mut execution = match self.start() {
+            Some(val) => val,
+            None => return (None, START_FAILED_STATUS),
+        };
+        execution.run(&mut BufWriter::new(stdout()));
+        let status = execution.status();
+        (Some(execution).filter(|execution| execution.state() == Step::Error), status)
+    }
+
+    // program output goes to out, debug information is logged
+    fn run(&self, out: &mut dyn Write) -> i64 {
+        match self.start() {
+            Some(mut execution) => {
+                execution.run(out);
+                execution.status()
+            },
+            None => START_FAILED_STATUS,
         }
+    }
+
+    // check the program and prepare running it from the start of main, None if it can't run (the reason is printed)
+    pub fn start(&self) -> Option<Execution<'_>> {
+        if !self.check_file() { return None; }
 
         // --- architecture check ---
         let arch_bytes = match self.read_arg(ARG_SIZE) {
             Some(val) => val,
-            None => return error(&t!("pl0.error.failed_arch_read")),
+            None => { error(&t!("pl0.error.failed_arch_read")); return None },
         };
-        if self.debug {
+        if log::log_enabled!(log::Level::Info) {
             let invalid = t!("pl0.invalid");
-            println!("\t@0000: {:<21}{arch_bytes:04X} = {}", t!("pl0.set_arch"), match arch_bytes {
+            log::info!("@0000: {:<21}{arch_bytes:04X} = {}", t!("pl0.set_arch"), match arch_bytes {
+                1 => "8 bit",
                 2 => "16 bit",
                 4 => "32 bit",
                 8 => "64 bit",
                 _ => &invalid,
             });
         }
-        if arch_bytes != 2 && arch_bytes != 4 && arch_bytes != 8 {
+        if !matches!(arch_bytes, 1 | 2 | 4 | 8) {
             error(&t!("pl0.arch_invalid", arch = arch_bytes:{:04X}));
-            return;
+            return None;
+        }
+        // refuse to run code that would jump or reference something outside of the program
+        let (problems, stack_problems) = self.verify_parts();
+        if !problems.is_empty() {
+            error(&t!("verifier.failed"));
+            problems.iter().for_each(|problem| error(problem));
+            return None;
+        }
+        if !stack_problems.is_empty() {
+            error(&t!("verifier.stack_warning"));
+            stack_problems.iter().for_each(|problem| error(problem));
         }
 
-        let (mut procedures, constants) = match self.load_data() {
-            Some(val) => val,
-            None => return,
+        let (procedures, constants) = self.load_data()?;
+        let code = match self.predecode() {
+            Ok(val) => Arc::new(val),
+            Err(err) => { error(&err); return None },
         };
+        // without recursion the stack never needs to grow while running, each frame can round up its variables by one value
+        let stack = match self.stack_usage() {
+            Ok(StackUsage { total: Some(bytes), .. }) => Stack::with_capacity(bytes / self.data_size() + procedures.len()),
+            _ => Stack::default(),
+        };
+        // heap addresses have to fit into a value like stack addresses
+        let max_addr = (i64::MAX >> (64 - self.data_size() * 8)) as usize;
+        let heap = Heap::new(self.heap_limit.min(max_addr) / self.data_size());
+        let seed = self.seed.unwrap_or_else(Rng::time_seed);
+        let mut telemetry = Telemetry::default();
+        telemetry.call(self, 0);
+        Some(Execution {
+            vm: self,
+            pc: procedures[0].start_pos,
+            ip: code.at(procedures[0].start_pos),
+            stack,
+            heap,
+            strings: self.strings.clone(),
+            rng: Rng::new(seed),
+            seed,
+            started: Instant::now(),
+            status: 0,
+            files: Files::default(),
+            #[cfg(feature = "bignum")]
+            bigs: Bigs::default(),
+            fp: 0,
+            cur_proc_i: 0,
+            depth: 0,
+            procedures,
+            constants,
+            state: Step::Running,
+            last_write: None,
+            inputs: Vec::with_capacity(self.stdin_values.as_ref().map_or(0, Vec::len)),
+            pending_inputs: self.stdin_values.iter().flatten().copied().collect(),
+            input_closed: self.stdin_values.is_some(),
+            fault: None,
+            telemetry,
+            metrics: Metrics::default(),
+            code,
+            stack_verified: stack_problems.is_empty(),
+        })
+    }
 
-        // --- execution state ---
-        // program counter = index of currently executed byte
-        let mut pc = procedures[0].start_pos;
-        // stack = contains all dynamic runtime data
-        let mut stack: Vec<u8> = vec![];
-        // frame pointer = index of start of current stack frame in vector stack
-        let mut fp = 0usize;
-        // current procedure index = index of current procedure in vector procedures
-        let mut cur_proc_i = 0usize;
+}
 
-        // --- collection of functions used for execution ---
-        // pop one Data from the stack
-        let pop_data = |stack: &mut Vec<u8>| -> Option<Data> {
-            let size = self.data_size();
-            let len = stack.len();
+impl Execution<'_> {
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+    pub fn state(&self) -> Step {
+        self.state
+    }
+    pub fn frame_pointer(&self) -> usize {
+        self.fp
+    }
+    pub fn current_procedure(&self) -> usize {
+        self.cur_proc_i
+    }
+    pub fn call_depth(&self) -> usize {
+        self.depth
+    }
+    pub fn procedure_count(&self) -> usize {
+        self.procedures.len()
+    }
+    // counters of the run so far
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+    // address of the instruction that stopped the program with a runtime error
+    pub fn fault_addr(&self) -> Option<usize> {
+        self.fault
+    }
+    // size of the stack in bytes
+    pub fn stack_len(&self) -> usize {
+        self.stack.len() * self.vm.data_size()
+    }
+    // the stack in values of the architecture, starting at address 0 (call information is read as values too)
+    pub fn stack_values(&self) -> &[i64] {
+        &self.stack
+    }
+    // value at the stack address, None if it is beyond the stack or not the start of a value
+    fn value_at(&self, addr: usize) -> Option<i64> {
+        let size = self.vm.data_size();
+        if !addr.is_multiple_of(size) {
+            return None;
+        }
+        self.stack.get(addr / size).copied()
+    }
+
+    pub fn last_write(&self) -> Option<StackWrite> {
+        self.last_write
+    }
+
+    pub fn inputs(&self) -> &[i64] {
+        &self.inputs
+    }
+    // queue numbers for InputToAddr and character codes for GetChar, they are read before stdin
+    pub fn feed_inputs(&mut self, inputs: impl IntoIterator<Item = i64>) {
+        self.pending_inputs.extend(inputs);
+        // they are recorded once read, without allocating while running
+        self.inputs.reserve(self.pending_inputs.len());
+    }
+    // only read the queued numbers, reading more is a runtime error
+    pub fn close_input(&mut self) {
+        self.input_closed = true;
+    }
+    // what Halt passed, INPUT_TIMEOUT_STATUS or INTERRUPT_STATUS, 0 if the program didn't end with any of them (yet)
+    pub fn status(&self) -> i64 {
+        self.status
+    }
+    // the seed Random started from, to repeat the run with set_seed
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    // queued numbers that weren't read (yet)
+    pub fn pending_inputs(&self) -> usize {
+        self.pending_inputs.len()
+    }
 
-            if len < size {
-                return None;
+    // the active procedure calls, innermost first, read from the call information CallProc stores
+    // in front of every frame (return address, frame pointer and procedure of the caller)
+    pub fn backtrace(&self) -> Vec<Frame> {
+        let read = |addr: usize| self.value_at(addr).map(|val| val as usize);
+        let size = self.vm.data_size();
+        let mut frames = vec![];
+        let mut procedure = self.cur_proc_i;
+        let mut frame_ptr = match self.procedures.get(procedure) {
+            Some(val) => val.frame_ptr,
+            None => return frames,
+        };
+        while procedure != 0 {
+            let info = match frame_ptr.checked_sub(CALL_FRAME_SIZE) {
+                Some(val) => val,
+                None => break,
+            };
+            let (return_addr, caller_fp, caller) = match (read(info), read(info + size), read(info + 2 * size)) {
+                (Some(pc), Some(fp), Some(proc_i)) => (pc, fp, proc_i),
+                _ => break,
+            };
+            frames.push(Frame { procedure, frame_ptr, return_addr: Some(return_addr) });
+            // frames of callers are always further down, anything else means the stack was overwritten
+            if caller_fp >= frame_ptr {
+                return frames;
             }
+            (procedure, frame_ptr) = (caller, caller_fp);
+        }
+        frames.push(Frame { procedure, frame_ptr, return_addr: None });
+        frames
+    }
 
-            let start = len - size;
-            
-            let data_bytes = &stack[start..];
-            let data = self.bytes_to_data(&Some(data_bytes));
+    // absolute stack address of the variable at addr in the current frame of the procedure
+    pub fn variable_addr(&self, proc_id: usize, addr: i64) -> Option<usize> {
+        self.procedures.get(proc_id)?.frame_ptr.checked_add_signed(addr as isize)
+    }
+    // value of the variable at addr in the frame of the procedure, None if it isn't on the stack
+    pub fn variable(&self, proc_id: usize, addr: i64) -> Option<i64> {
+        self.value_at(self.variable_addr(proc_id, addr)?)
+    }
+    pub fn constant(&self, index: usize) -> Option<i64> {
+        self.constants.get(index).copied()
+    }
+    #[cfg(feature = "jit")]
+    pub(crate) fn code(&self) -> &Code {
+        &self.code
+    }
 
-            stack.truncate(start);
+    // run the instruction at the program counter, program output goes to out, what it did is logged at the debug level
+    pub fn step(&mut self, out: &mut dyn Write) -> Step {
+        self.run_steps(out, 1)
+    }
+    // run until the program ends, without the overhead of starting every instruction on its own
+    pub fn run(&mut self, out: &mut dyn Write) -> Step {
+        self.run_steps(out, usize::MAX)
+    }
+    // run at most limit instructions, Running if the program didn't end within them
+    pub fn run_for(&mut self, out: &mut dyn Write, limit: usize) -> Step {
+        self.run_steps(out, limit)
+    }
+
+    // run up to limit instructions, the state is only moved out of self and stored again once
+    fn run_steps(&mut self, out: &mut dyn Write, limit: usize) -> Step {
+        if self.state != Step::Running { return self.state; }
+        let vm = self.vm;
+        // what the instruction did, logged as one line at its end
+        let debug = log::log_enabled!(log::Level::Debug);
+        let mut trace = String::new();
+        macro_rules! note {
+            ($($arg:tt)*) => { if debug { trace += &format!($($arg)*); } };
+        }
+        // the error goes to stderr, so the buffered output in front of it has to come out first
+        macro_rules! fail {
+            ($($arg:tt)*) => {{ out.flush().expect("Could not write program output"); vm.runtime_error($($arg)*) }};
+        }
+        let constants = &self.constants;
+        // the state is moved out while the instruction runs and stored again afterwards
+        let mut pc = self.pc;
+        let mut ip = self.ip;
+        let mut stack = std::mem::take(&mut self.stack);
+        let mut fp = self.fp;
+        let mut cur_proc_i = self.cur_proc_i;
+        let mut procedures = std::mem::take(&mut self.procedures);
+        let mut last_write = None;
 
-            data
+        // --- collection of functions used for execution ---
+        let size = vm.data_size();
+        // pop one value from the stack
+        let pop_data = |stack: &mut Stack| -> Option<i64> {
+            stack.pop()
         };
-        // push a Data onto the stack
-        let push_data = |stack: &mut Vec<u8>, data: Data| {
-            stack.append(&mut data.to_bytes());
+        // push a value onto the stack, cut to the data width
+        let push_data = |stack: &mut Stack, val: i64| {
+            stack.push(vm.wrap(val));
         };
-        // pop one argument from the bytecode, by increasing the program counter by ARG_SIZE
-        let pop_argument = |pc: &mut usize| -> Option<i16> {
-            *pc += ARG_SIZE;
-            self.read_arg(*pc - ARG_SIZE)
+        // read the value at the address in the stack, None if there is none starting there
+        let read = |stack: &Stack, addr: usize| -> Option<i64> {
+            if !addr.is_multiple_of(size) { return None; }
+            stack.get(addr / size).copied()
         };
-        // set the bytes at the specified position (fp) in the stack to the value in data
-        let set_addr = |stack: &mut Vec<u8>, fp: &usize, data: &Data| {
-            if stack.len() < (fp + self.data_size()) { stack.resize(fp + self.data_size(), 0); }
-            let bytes = match data {
-                B16(v) => v.to_le_bytes().to_vec(), B32(v) => v.to_le_bytes().to_vec(), B64(v) => v.to_le_bytes().to_vec(),
+        // set the value at the specified address (fp) in the stack, returning what was overwritten, None if the address doesn't start a value
+        // frames are reserved by EntryProc, so writes only ever go to values already on the stack
+        let set_addr = |stack: &mut Stack, addr: i64, val: i64| -> Result<StackWrite, String> {
+            let i = match usize::try_from(addr) {
+                Ok(addr) if !addr.is_multiple_of(size) => return Err(t!("pl0.error.unaligned_address", addr = addr).to_string()),
+                Ok(addr) if addr / size < stack.len() => addr / size,
+                _ => return Err(t!("pl0.error.beyond_stack", addr = addr, bytes = stack.len() * size).to_string()),
             };
-            stack.splice(fp..&(fp + self.data_size()), bytes);
+            let new = vm.wrap(val);
+            let old = std::mem::replace(&mut stack[i], new);
+            Ok(StackWrite { addr: i * size, old, new })
+        };
+        // message for a variable offset beyond the variables of the procedure
+        let outside_frame = |procedures: &[Procedure], proc_id: usize, offset: i64| {
+            t!("pl0.error.outside_frame", offset = offset, id = vm.proc_label(proc_id), bytes = procedures[proc_id].vars).to_string()
+        };
+        let out_of_bounds = |procedures: &[Procedure], proc_id: usize, fp: usize, addr: i64| {
+            t!("pl0.error.out_of_bounds", addr = addr, id = vm.proc_label(proc_id), bytes = procedures[proc_id].vars, start = fp).to_string()
         };
         // calculate the address start + offset, with respect to types
         let offsetted = |start: &usize, offset: isize| start.checked_add_signed(offset).expect("invalid variable offset");
 
-        // --- main execution loop ---
-        loop {
-            let byte = self.program[pc];
-
-            // try to get op code from current byte
-            let op = match OpCode::try_from(byte) {
-                Ok(op) => op,
-                Err(_) => {
-                    error(&t!("pl0.unknown_opcode", op = byte:{:02X}));
-                    break;
-                },
-            };
-            if self.debug { print!("\t@{pc:04X}: {:<21}", op); }
-            // increase program counter already, so that next pop_argument call returns valid data
-            pc += 1;
-            match op {
-                OpCode::EntryProc => {
-                    pc += ARG_SIZE;
-                    let proc_i = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    if proc_i < 0 {
-                        error(&t!("pl0.enter_invalid_proc", id = proc_i));
-                        return;
-                    }
-                    let varlen = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    } as usize;
-                    fp = procedures[proc_i as usize].frame_ptr;
-                    stack.resize(fp + varlen, 0);
-                    if self.debug { print!("{}", t!("pl0.reserved_varspace", bytes = varlen)); }
+        let mut state = Step::Running;
+        // superinstructions run several instructions at once, so they are left out while logging every instruction, and
+        // they calculate with plain cells
+        let fuse = !debug && !vm.bignum();
+        let mut executed = 0;
+        let mut interrupted = false;
+        while executed < limit {
+            executed += 1;
+            // start of the current instruction, for error locations
+            let instr_pc = pc;
+            last_write = None;
+            state = 'step: {
+                if interrupt::take() {
+                    interrupted = true;
+                    self.status = INTERRUPT_STATUS;
+                    break 'step fail!(instr_pc, &t!("pl0.error.interrupted", pc = instr_pc:{:04X}));
                 }
-                OpCode::ReturnProc => {
-                    if cur_proc_i == 0 {
-                        if self.debug { println!("{}", t!("pl0.exiting")); }
-                        break;
-                    } else {
-                        stack.truncate(procedures[cur_proc_i].frame_ptr);
-                        let new_proc_i = u64::from_le_bytes(stack.drain(stack.len() - 8..).collect::<Vec<u8>>().try_into().expect("jumping back failed - stack invalid"));
-                        let new_fp = u64::from_le_bytes(stack.drain(stack.len() - 8..).collect::<Vec<u8>>().try_into().expect("jumping back failed - stack invalid"));
-                        let new_pc = u64::from_le_bytes(stack.drain(stack.len() - 8..).collect::<Vec<u8>>().try_into().expect("jumping back failed - stack invalid"));
-                        if self.debug { print!("pc: {pc} => {new_pc}, fp: {fp} => {new_fp}, cpi: {cur_proc_i} => {new_proc_i}"); }
-                        pc = new_pc as usize;
-                        fp = new_fp as usize;
-                        cur_proc_i = new_proc_i as usize;
+                let (i, instr) = match ip.and_then(|ip| Some((ip, self.code.instrs.get(ip)?))) {
+                    Some(val) => val,
+                    // only addresses the verifier didn't check get here, e.g. after the stack was corrupted
+                    _ => break 'step fail!(instr_pc, &match decode_instruction(&vm.program, pc) {
+                        Err(err) => err,
+                        Ok(_) => t!("pl0.error.invalid_pc", pc = pc:{:04X}).to_string(),
+                    }),
+                };
+                if let Some(fused) = instr.fused.filter(|fused| fuse && limit - executed >= fused.count() - 1) {
+                    // the stack holds one or two values more in between, nothing is changed if an instruction
+                    // would fail, it runs on its own then to report the error
+                    let done = match fused {
+                        Superinstruction::StoreConstant { c } => {
+                            self.metrics.peak_stack = self.metrics.peak_stack.max((stack.len() + 1) * size);
+                            match stack.pop() {
+                                Some(addr) => match set_addr(&mut stack, addr, constants[c]) {
+                                    Ok(write) => {
+                                        last_write = Some(write);
+                                        true
+                                    }
+                                    Err(_) => {
+                                        stack.push(addr);
+                                        false
+                                    }
+                                },
+                                None => false,
+                            }
+                        }
+                        Superinstruction::ConstantOp { c, op } => {
+                            self.metrics.peak_stack = self.metrics.peak_stack.max((stack.len() + 1) * size);
+                            match stack.last_mut().and_then(|top| Some((binary_op(vm, op, *top, constants[c])?, top))) {
+                                Some((val, top)) => {
+                                    *top = vm.wrap(val);
+                                    true
+                                }
+                                None => false,
+                            }
+                        }
+                        Superinstruction::VariableConstantOp { main, addr, c, op } => {
+                            self.metrics.peak_stack = self.metrics.peak_stack.max((stack.len() + 2) * size);
+                            let (proc, base) = if main { (&procedures[0], procedures[0].frame_ptr) } else { (&procedures[cur_proc_i], fp) };
+                            match read(&stack, base + addr).filter(|_| proc.holds(addr as i64)).and_then(|val| binary_op(vm, op, val, constants[c])) {
+                                Some(val) => {
+                                    push_data(&mut stack, val);
+                                    true
+                                }
+                                None => false,
+                            }
+                        }
+                    };
+                    if done {
+                        let last = &self.code.instrs[i + fused.count() - 1];
+                        pc = last.addr + last.len;
+                        ip = Some(i + fused.count());
+                        executed += fused.count() - 1;
+                        self.metrics.instructions += fused.count() - 1;
+                        break 'step Step::Running;
                     }
                 }
-                OpCode::CallProc => {
-                    let proc_id = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    if proc_id < 0 {
-                        error(&t!("pl0.call_invalid_proc", id = proc_id));
-                        return;
-                    }
-                    stack.extend((pc as u64).to_le_bytes());
-                    stack.extend((fp as u64).to_le_bytes());
-                    stack.extend((cur_proc_i as u64).to_le_bytes());
-                    let proc = &mut procedures[proc_id as usize];
-                    if self.debug { print!("pc: {pc} => {}, fp: {fp} => {}, cpi: {cur_proc_i} => {}", proc.start_pos, stack.len(), proc_id); }
-                    cur_proc_i = proc_id as usize;
-                    pc = proc.start_pos;
-                    proc.frame_ptr = stack.len();
+                let op = instr.op;
+                if debug {
+                    let name = if instr.wide { format!("{} {}", OpCode::Wide, op) } else { op.to_string() };
+                    note!("@{instr_pc:04X}: {:<21}", name);
                 }
+                // jumps are relative to the end of the instruction, so move there already
+                pc += instr.len;
+                ip = ip.map(|ip| ip + 1);
+                match op {
+                    #[cfg(feature = "bignum")]
+                    op if vm.bignum && Bigs::handles(op) => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let result = match op {
+                            OpCode::OutputValue => match self.bigs.get(right) {
+                                Ok(val) => {
+                                    note!("{}", val);
+                                    writeln!(out, "{}", val).expect("Could not write program output");
+                                    self.telemetry.output(&val);
+                                    self.metrics.outputs += 1;
+                                    None
+                                },
+                                Err(err) => Some(Err(err)),
+                            },
+                            OpCode::Minusify | OpCode::IsOdd => Some(self.bigs.unary(op, right)),
+                            _ => match pop_data(&mut stack) {
+                                Some(left) => Some(self.bigs.binary(op, left, right)),
+                                None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                            },
+                        };
+                        match result {
+                            Some(Ok(val)) => {
+                                note!("{}", t!("pl0.big_result", val = self.bigs.get(val).map(|big| big.to_string()).unwrap_or_default()));
+                                push_data(&mut stack, val);
+                            },
+                            Some(Err(err)) => break 'step fail!(instr_pc, &err),
+                            None => {},
+                        }
+                    }
+                    // the bits of a reference aren't the ones of the number
+                    #[cfg(feature = "bignum")]
+                    op if vm.bignum && matches!(op, OpCode::OpAnd | OpCode::OpOr | OpCode::OpXor | OpCode::OpNot
+                            | OpCode::ShiftLeft | OpCode::ShiftRight | OpCode::ShiftRightLogical)
+                        && stack.iter().rev().take(if op == OpCode::OpNot { 1 } else { 2 }).any(|val| Bigs::is_reference(*val)) => {
+                        break 'step fail!(instr_pc, &t!("pl0.error.big_bits", op = op));
+                    }
+                    OpCode::EntryProc => {
+                        let proc_i = instr.args[1];
+                        if proc_i < 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.enter_invalid_proc", id = proc_i));
+                        }
+                        let varlen = instr.args[2] as usize;
+                        fp = procedures[proc_i as usize].frame_ptr;
+                        // frames start at a value, so the variables take whole values too
+                        stack.resize((fp + varlen).div_ceil(size), 0);
+                        note!("{}", t!("pl0.reserved_varspace", bytes = varlen));
+                    }
+                    op @ (OpCode::ReturnProc | OpCode::ReturnValue) => {
+                        let result = match op {
+                            OpCode::ReturnValue => match pop_data(&mut stack) {
+                                Some(val) => Some(val),
+                                None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                            },
+                            _ => None,
+                        };
+                        if cur_proc_i == 0 {
+                            note!("{}", t!("pl0.exiting"));
+                            break 'step Step::Finished;
+                        } else {
+                            // fp and not the frame_ptr of the procedure, a recursive call may have moved that to a frame that's gone now
+                            let info = (fp / size).checked_sub(CALL_FRAME_SIZE / size).expect("jumping back failed - stack invalid");
+                            let (new_pc, new_fp, new_proc_i) = match stack.get(info..(info + 3)) {
+                                Some(&[pc, fp, proc_i]) => (pc, fp, proc_i),
+                                _ => panic!("jumping back failed - stack invalid"),
+                            };
+                            stack.truncate(info);
+                            note!("pc: {pc} => {new_pc}, fp: {fp} => {new_fp}, cpi: {cur_proc_i} => {new_proc_i}");
+                            pc = new_pc as usize;
+                            ip = self.code.at(pc);
+                            fp = new_fp as usize;
+                            if new_proc_i as usize == cur_proc_i {
+                                // back in the frame of the same procedure, its variables are the ones of that frame again
+                                procedures[cur_proc_i].frame_ptr = fp;
+                            }
+                            cur_proc_i = new_proc_i as usize;
+                            self.depth = self.depth.saturating_sub(1);
+                            self.telemetry.ret();
+                            if let Some(val) = result {
+                                note!("{}", t!("pl0.returned_value", val = val));
+                                push_data(&mut stack, val);
+                            }
+                        }
+                    }
+                    op @ (OpCode::CallProc | OpCode::CallProcArgs) => {
+                        // the ID is known to exist, predecode resolved the target
+                        let proc_id = instr.args[0] as usize;
+                        let target = instr.target.expect("resolved by predecode");
+                        // the parameters of CallProcArgs stay on top of the stack and end up at the start of the new frame
+                        let count = if op == OpCode::CallProcArgs { instr.args[1] as usize } else { 0 };
+                        let params = match stack.len().checked_sub(count) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        if instr.tail {
+                            // the callee returns to where the caller would have, so it takes over the frame of the caller
+                            stack.copy_within(params.., fp / size);
+                            stack.truncate(fp / size + count);
+                            note!("{} pc: {pc} => {}, cpi: {cur_proc_i} => {}{}", t!("pl0.tail_call"), procedures[proc_id].start_pos, proc_id, vm.proc_suffix(proc_id));
+                            self.telemetry.ret();
+                            cur_proc_i = proc_id;
+                            pc = self.code.instrs[target].addr;
+                            ip = Some(target);
+                            procedures[proc_id].frame_ptr = fp;
+                            self.telemetry.call(vm, cur_proc_i);
+                            self.metrics.calls += 1;
+                            break 'step Step::Running;
+                        }
+                        // the call information goes below the parameters
+                        let info = CALL_FRAME_SIZE / size;
+                        stack.resize(stack.len() + info, 0);
+                        stack.copy_within(params..(params + count), params + info);
+                        stack[params..(params + info)].fill(0);
+                        stack[params..(params + 3)].copy_from_slice(&[pc as i64, fp as i64, cur_proc_i as i64]);
+                        let proc = &mut procedures[proc_id];
+                        proc.frame_ptr = params * size + CALL_FRAME_SIZE;
+                        note!("pc: {pc} => {}, fp: {fp} => {}, cpi: {cur_proc_i} => {}{}", proc.start_pos, proc.frame_ptr, proc_id, vm.proc_suffix(proc_id));
+                        cur_proc_i = proc_id;
+                        pc = self.code.instrs[target].addr;
+                        ip = Some(target);
+                        self.depth += 1;
+                        self.telemetry.call(vm, cur_proc_i);
+                        self.metrics.calls += 1;
+                    }
 
-                OpCode::PushValueLocalVar => {
-                    let addr = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    if addr < 0 {
-                        error(&t!("pl0.invalid_local_var_val", addr = addr));
-                        return;
+                    OpCode::PushValueLocalVar => {
+                        let addr = instr.args[0];
+                        if addr < 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.invalid_local_var_val", addr = addr));
+                        }
+                        if !procedures[cur_proc_i].holds(addr as i64) {
+                            break 'step fail!(instr_pc, &outside_frame(&procedures, cur_proc_i, addr as i64));
+                        }
+                        let data = match read(&stack, offsetted(&fp, addr as isize)) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}{}", t!("pl0.took_from_addr", val = data, addr = offsetted(&fp, addr as isize)), vm.var_suffix(cur_proc_i, addr));
+                        push_data(&mut stack, data);
                     }
-                    let data = match self.bytes_to_data(&stack.get(offsetted(&fp, addr as isize)..)) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    };
-                    if self.debug { print!("{}", t!("pl0.took_from_addr", val = data.i64(), addr = offsetted(&fp, addr as isize))); }
-                    push_data(&mut stack, data);
-                }
-                OpCode::PushValueMainVar => {
-                    let addr = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    if addr < 0 {
-                        error(&t!("pl0.invalid_main_var_val", addr = addr));
-                        return;
+                    OpCode::PushValueMainVar => {
+                        let addr = instr.args[0];
+                        if addr < 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.invalid_main_var_val", addr = addr));
+                        }
+                        if !procedures[0].holds(addr as i64) {
+                            break 'step fail!(instr_pc, &outside_frame(&procedures, 0, addr as i64));
+                        }
+                        let data = match read(&stack, offsetted(&procedures[0].frame_ptr, addr as isize)) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}{}", t!("pl0.took_from_addr", val = data, addr = offsetted(&procedures[0].frame_ptr, addr as isize)), vm.var_suffix(0, addr));
+                        push_data(&mut stack, data);
                     }
-                    let data = match self.bytes_to_data(&stack.get(offsetted(&procedures[0].frame_ptr, addr as isize)..)) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    };
-                    if self.debug { print!("{}", t!("pl0.took_from_addr", val = data.i64(), addr = offsetted(&procedures[0].frame_ptr, addr as isize))); }
-                    push_data(&mut stack, data);
-                }
-                OpCode::PushValueGlobalVar => {
-                    let addr = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    let proc_index = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    } as usize;
-                    if addr < 0 {
-                        error(&t!("pl0.invalid_global_var_val", addr = addr, proc_index = proc_index));
-                        return;
-                    }
-                    let data = match self.bytes_to_data(&stack.get(offsetted(&procedures[proc_index].frame_ptr, addr as isize)..)) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    };
-                    if self.debug { print!("{}", t!("pl0.took_from_addr", val = data.i64(), addr = offsetted(&procedures[proc_index].frame_ptr, addr as isize))); }
-                    push_data(&mut stack, data);
-                }
-                OpCode::PushAddressLocalVar => {
-                    let addr = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    if addr < 0 {
-                        error(&t!("pl0.invalid_local_var_addr", addr = addr));
-                        return;
+                    OpCode::PushValueGlobalVar => {
+                        let addr = instr.args[0];
+                        let proc_index = instr.args[1] as usize;
+                        if addr < 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.invalid_global_var_val", addr = addr, proc_index = vm.proc_label(proc_index)));
+                        }
+                        if !procedures[proc_index].holds(addr as i64) {
+                            break 'step fail!(instr_pc, &outside_frame(&procedures, proc_index, addr as i64));
+                        }
+                        let data = match read(&stack, offsetted(&procedures[proc_index].frame_ptr, addr as isize)) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}{}", t!("pl0.took_from_addr", val = data, addr = offsetted(&procedures[proc_index].frame_ptr, addr as isize)), vm.var_suffix(proc_index, addr));
+                        push_data(&mut stack, data);
                     }
-                    let data = self.bytes_to_data(&Some(&offsetted(&fp, addr as isize).to_le_bytes())).expect("failed to convert offset to Data");
-                    if self.debug { print!("{}", t!("pl0.pushed_addr", addr = offsetted(&fp, addr as isize))); }
-                    push_data(&mut stack, data);
-                }
-                OpCode::PushAddressMainVar => {
-                    let addr = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    if addr < 0 {
-                        error(&t!("pl0.invalid_main_var_addr", addr = addr));
-                        return;
+                    OpCode::PushAddressLocalVar => {
+                        let addr = instr.args[0];
+                        if addr < 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.invalid_local_var_addr", addr = addr));
+                        }
+                        if !procedures[cur_proc_i].holds(addr as i64) {
+                            break 'step fail!(instr_pc, &outside_frame(&procedures, cur_proc_i, addr as i64));
+                        }
+                        let data = match vm.address_value(offsetted(&fp, addr as isize)) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.address_range", addr = offsetted(&fp, addr as isize))),
+                        };
+                        note!("{}{}", t!("pl0.pushed_addr", addr = offsetted(&fp, addr as isize)), vm.var_suffix(cur_proc_i, addr));
+                        push_data(&mut stack, data);
                     }
-                    let data = self.bytes_to_data(&Some(&offsetted(&procedures[0].frame_ptr, addr as isize).to_le_bytes())).expect("failed to convert offset to Data");
-                    if self.debug { print!("{}", t!("pl0.pushed_addr", addr = offsetted(&procedures[0].frame_ptr, addr as isize))); }
-                    push_data(&mut stack, data);
-                }
-                OpCode::PushAddressGlobalVar => {
-                    let addr = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    let proc_index = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    } as usize;
-                    if addr < 0 {
-                        error(&t!("pl0.invalid_global_var_addr", addr = addr, proc_index = proc_index));
-                        return;
-                    }
-                    if self.debug {
-                        print!("{}", t!("pl0.pushed_global_addr", proc_index = proc_index, addr = addr, push_addr = offsetted(&procedures[proc_index].frame_ptr, addr as isize)));
-                    }
-                    let data = self.bytes_to_data(&Some(&offsetted(&procedures[proc_index].frame_ptr, addr as isize).to_le_bytes())).expect("failed to convert offset to Data");
-                    push_data(&mut stack, data);
-                }
-                OpCode::PushConstant => {
-                    let c = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    if c < 0 {
-                        error(&t!("pl0.invalid_constant", c = c));
-                        return;
+                    OpCode::PushAddressMainVar => {
+                        let addr = instr.args[0];
+                        if addr < 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.invalid_main_var_addr", addr = addr));
+                        }
+                        if !procedures[0].holds(addr as i64) {
+                            break 'step fail!(instr_pc, &outside_frame(&procedures, 0, addr as i64));
+                        }
+                        let data = match vm.address_value(offsetted(&procedures[0].frame_ptr, addr as isize)) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.address_range", addr = offsetted(&procedures[0].frame_ptr, addr as isize))),
+                        };
+                        note!("{}{}", t!("pl0.pushed_addr", addr = offsetted(&procedures[0].frame_ptr, addr as isize)), vm.var_suffix(0, addr));
+                        push_data(&mut stack, data);
+                    }
+                    OpCode::PushAddressGlobalVar => {
+                        let addr = instr.args[0];
+                        let proc_index = instr.args[1] as usize;
+                        if addr < 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.invalid_global_var_addr", addr = addr, proc_index = vm.proc_label(proc_index)));
+                        }
+                        if !procedures[proc_index].holds(addr as i64) {
+                            break 'step fail!(instr_pc, &outside_frame(&procedures, proc_index, addr as i64));
+                        }
+                        note!("{}{}", t!("pl0.pushed_global_addr", proc_index = vm.proc_label(proc_index), addr = addr, push_addr = offsetted(&procedures[proc_index].frame_ptr, addr as isize)), vm.var_suffix(proc_index, addr));
+                        let data = match vm.address_value(offsetted(&procedures[proc_index].frame_ptr, addr as isize)) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.address_range", addr = offsetted(&procedures[proc_index].frame_ptr, addr as isize))),
+                        };
+                        push_data(&mut stack, data);
+                    }
+                    OpCode::PushConstant => {
+                        let c = instr.args[0];
+                        if c < 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.invalid_constant", c = c));
+                        }
+                        let cd = constants[c as usize];
+                        note!("{}", t!("pl0.pushed_constant", c = c, val = cd));
+                        push_data(&mut stack, cd);
+                    }
+                    OpCode::StoreValue => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let addr = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}", t!("pl0.stored_value", val = data, addr = addr));
+                        last_write = match set_addr(&mut stack, addr, data) {
+                            Ok(write) => Some(write),
+                            Err(err) => break 'step fail!(instr_pc, &err),
+                        };
                     }
-                    let cd = constants[c as usize].clone();
-                    if self.debug { print!("{}", t!("pl0.pushed_constant", c = c, val = cd.i64())); }
-                    push_data(&mut stack, cd);
-                }
-                OpCode::StoreValue => {
-                    let data = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    };
-                    let addr = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    if self.debug { print!("{}", t!("pl0.stored_value", val = data.i64(), addr = addr)) }
-                    set_addr(&mut stack, &(addr as usize), &data);
-                }
 
-                OpCode::OutputValue => {
-                    let data = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    };
-                    if self.debug {
-                        print!("{}\n{}", data.i64(), data.i64());
-                    } else {
-                        println!("{}", data.i64());
+                    OpCode::OutputValue => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}", data);
+                        vm.write_value(out, data);
+                        self.telemetry.output(data);
+                        self.metrics.outputs += 1;
                     }
-                }
-                OpCode::InputToAddr => {
-                    let addr = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    };
-                    if self.debug { println!("{}", t!("pl0.to_address", addr = addr.i64())); }
-                    // wait for user to input a valid number
-                    'input_loop: loop {
-                        let mut line = String::new();
-                        stdin().lock().read_line(&mut line).expect("Input failed");
-                        let input: Result<i64, _> = line.trim().parse();
-                        match input {
-                            Ok(num) => {
-                                set_addr(&mut stack, &offsetted(&fp, addr.i64() as isize), &self.bytes_to_data(&Some(&num.to_le_bytes())).expect("failed to convert number to Data - unreachable error"));
-                                break 'input_loop;
+                    OpCode::InputToAddr => {
+                        let addr = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}", t!("pl0.to_address", addr = addr));
+                        // queued numbers come first, otherwise wait for user to input a valid number
+                        let num = match self.pending_inputs.pop_front() {
+                            Some(num) => num,
+                            None if self.input_closed => break 'step fail!(instr_pc, &vm.input_closed_error()),
+                            None => match read_number(vm, out, instr_pc) {
+                                Ok(num) => num,
+                                Err(NoInput::Error(err)) => break 'step fail!(instr_pc, &err),
+                                Err(NoInput::Timeout(limit)) => {
+                                    self.status = INPUT_TIMEOUT_STATUS;
+                                    break 'step fail!(instr_pc, &t!("pl0.error.input_timeout", secs = limit.as_secs_f64()));
+                                },
+                            },
+                        };
+                        self.inputs.push(num);
+                        self.telemetry.input(num);
+                        self.metrics.inputs += 1;
+                        last_write = match set_addr(&mut stack, (fp as i64).saturating_add(addr), num) {
+                            Ok(write) => Some(write),
+                            Err(err) => break 'step fail!(instr_pc, &err),
+                        };
+                    }
+
+                    OpCode::Random => {
+                        // the upper bits are the better ones, one less than the data width keeps the value positive
+                        let data = (self.rng.next_u64() >> (65 - size * 8)) as i64;
+                        note!("{}", data);
+                        push_data(&mut stack, data);
+                    }
+                    OpCode::Assert => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}", data != 0);
+                        if data == 0 {
+                            let message = match usize::try_from(instr.args[0]).ok().and_then(|index| vm.strings.get(index)) {
+                                Some(str) => t!("pl0.error.assertion_message", pc = instr_pc:{:04X}, msg = String::from_utf8_lossy(str)),
+                                None => t!("pl0.error.assertion", pc = instr_pc:{:04X}),
+                            };
+                            break 'step fail!(instr_pc, &message);
+                        }
+                    }
+                    OpCode::FileOpen => {
+                        let mode = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let name = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let name = match usize::try_from(name).ok().and_then(|index| self.strings.get(index)) {
+                            Some(val) => String::from_utf8_lossy(val).into_owned(),
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_string", val = name)),
+                        };
+                        let handle = match self.files.open(vm.file_root.as_deref(), &name, mode) {
+                            Ok(val) => val,
+                            Err(err) => break 'step fail!(instr_pc, &err),
+                        };
+                        note!("\"{name}\" => {handle}");
+                        push_data(&mut stack, handle);
+                    }
+                    op @ (OpCode::FileRead | OpCode::FileAtEnd | OpCode::FileClose) => {
+                        let handle = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let result = match op {
+                            OpCode::FileRead => self.files.read(handle).map(Some),
+                            OpCode::FileAtEnd => self.files.at_end(handle).map(|end| Some(end as i64)),
+                            _ => self.files.close(handle).map(|_| None),
+                        };
+                        match result {
+                            Ok(Some(val)) => {
+                                note!("{handle} => {val}");
+                                push_data(&mut stack, val);
                             },
-                            Err(_) => {
-                                error(&t!("pl0.invalid_number_input"));
+                            Ok(None) => note!("{handle}"),
+                            Err(err) => break 'step fail!(instr_pc, &err),
+                        }
+                    }
+                    OpCode::FileWrite => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let handle = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{data} => {handle}");
+                        if let Err(err) = self.files.write(handle, data) {
+                            break 'step fail!(instr_pc, &err);
+                        }
+                    }
+                    OpCode::ArgCount => {
+                        note!("{}", vm.program_args.len());
+                        push_data(&mut stack, vm.program_args.len() as i64);
+                    }
+                    OpCode::GetArg => {
+                        let index = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let data = match usize::try_from(index).ok().and_then(|index| vm.program_args.get(index)) {
+                            Some(val) => *val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_arg", index = index, count = vm.program_args.len())),
+                        };
+                        note!("{index} => {data}");
+                        push_data(&mut stack, data);
+                    }
+                    op @ (OpCode::EnvNumber | OpCode::EnvString) => {
+                        let name = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let name = match usize::try_from(name).ok().and_then(|index| self.strings.get(index)) {
+                            Some(val) => String::from_utf8_lossy(val).into_owned(),
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_string", val = name)),
+                        };
+                        if !vm.allowed_env.contains(&name) {
+                            break 'step fail!(instr_pc, &t!("pl0.error.env_not_allowed", name = name));
+                        }
+                        let value = std::env::var(&name).unwrap_or_default();
+                        let data = if op == OpCode::EnvNumber {
+                            match value.trim() {
+                                "" => 0,
+                                text => match text.parse() {
+                                    Ok(val) => vm.wrap(val),
+                                    Err(_) => break 'step fail!(instr_pc, &t!("pl0.error.env_number", name = name, value = value)),
+                                },
                             }
+                        } else {
+                            self.strings.push(value.clone().into_bytes());
+                            self.strings.len() as i64 - 1
+                        };
+                        note!("{name} = \"{value}\" => {data}");
+                        push_data(&mut stack, data);
+                    }
+                    OpCode::Halt => {
+                        let status = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}", t!("pl0.halted", status = status));
+                        self.status = status;
+                        break 'step Step::Finished;
+                    }
+                    OpCode::Clock => {
+                        let millis = if vm.virtual_clock {
+                            self.metrics.instructions / VIRTUAL_CLOCK_RATE
+                        } else {
+                            self.started.elapsed().as_millis() as usize
+                        };
+                        note!("{}", t!("pl0.clock", millis = millis));
+                        push_data(&mut stack, millis as i64);
+                    }
+                    OpCode::GetChar => {
+                        let code = match self.pending_inputs.pop_front() {
+                            Some(code) => code,
+                            None if self.input_closed => break 'step fail!(instr_pc, &vm.input_closed_error()),
+                            None => {
+                                out.flush().expect("Could not write program output");
+                                read_char()
+                            },
+                        };
+                        note!("{}", t!("pl0.read_char", code = code));
+                        self.inputs.push(code);
+                        self.telemetry.input(code);
+                        self.metrics.inputs += 1;
+                        push_data(&mut stack, code);
+                    }
+                    OpCode::PutChar => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let char = match u32::try_from(data).ok().and_then(char::from_u32) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_char", code = data)),
+                        };
+                        note!("'{}'", char.escape_debug());
+                        write!(out, "{char}").expect("Could not write program output");
+                        self.telemetry.output(char);
+                        self.metrics.outputs += 1;
+                    }
+                    OpCode::Minusify => {
+                        let int = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let data = vm.wrap(-int);
+                        note!("{} => {}", int, data);
+                        push_data(&mut stack, data);
+                    }
+                    OpCode::IsOdd => {
+                        let int = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        // Done this way to handle negative numbers correctly
+                        // because -1 % 2 == -1 and 1 % 2 == 1
+                        let not_odd = int % 2 == 0;
+                        let val = !not_odd;
+                        note!("{} => {}", int, val);
+                        push_data(&mut stack, val as i64);
+                    }
+                    OpCode::LogicalAnd => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = left != 0 && right != 0;
+                        note!("{left} and {right} = {val}");
+                        push_data(&mut stack, val as i64);
+                    }
+                    OpCode::LogicalOr => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = left != 0 || right != 0;
+                        note!("{left} or {right} = {val}");
+                        push_data(&mut stack, val as i64);
+                    }
+                    op @ (OpCode::ShiftLeft | OpCode::ShiftRight | OpCode::ShiftRightLogical) => {
+                        let amount = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let int = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let bits = size as i64 * 8;
+                        if !(0..bits).contains(&amount) {
+                            break 'step fail!(instr_pc, &t!("pl0.error.shift_range", amount = amount, bits = bits));
                         }
+                        let val = match op {
+                            OpCode::ShiftLeft => int << amount,
+                            OpCode::ShiftRight => int >> amount,
+                            // only the bits of the data width, without the sign extension
+                            _ => ((int as u64 & (u64::MAX >> (64 - bits))) >> amount) as i64,
+                        };
+                        note!("{op} {int} {amount} = {}", vm.wrap(val));
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::OpNot => {
+                        let int = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = !int;
+                        note!("{} => {}", int, val);
+                        push_data(&mut stack, val);
                     }
-                }
 
-                OpCode::Minusify => {
-                    let int = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    };
-                    let data = match int {
-                        B16(x) => B16(-x), B32(x) => B32(-x), B64(x) => B64(-x),
-                    };
-                    if self.debug { print!("{} => {}", int.i64(), data.i64()); }
-                    push_data(&mut stack, data);
-                }
-                OpCode::IsOdd => {
-                    let int = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    // Done this way to handle negative numbers correctly
-                    // because -1 % 2 == -1 and 1 % 2 == 1
-                    let not_odd = int % 2 == 0;
-                    let val = !not_odd;
-                    if self.debug { print!("{} => {}", int, val); }
-                    push_data(&mut stack, self.data_bool(val));
+                    OpCode::OpAdd => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = left + right;
+                        note!("{left} + {right} = {val}");
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::OpSubtract => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = left - right;
+                        note!("{left} - {right} = {val}");
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::OpMultiply => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = left * right;
+                        note!("{left} * {right} = {val}");
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::OpDivide => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        if right == 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.error.division_by_zero"));
+                        }
+                        let val = if vm.unsigned { (vm.as_unsigned(left) / vm.as_unsigned(right)) as i64 } else { left / right };
+                        note!("{left} / {right} = {val}");
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::OpModulo => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        if right == 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.error.division_by_zero"));
+                        }
+                        // the smallest value modulo -1 overflows while dividing, but the remainder is 0 anyway
+                        let val = if vm.unsigned { (vm.as_unsigned(left) % vm.as_unsigned(right)) as i64 } else { left.wrapping_rem(right) };
+                        note!("{left} % {right} = {val}");
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::OpAnd => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = left & right;
+                        note!("{left} & {right} = {val}");
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::OpOr => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = left | right;
+                        note!("{left} | {right} = {val}");
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::OpXor => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = left ^ right;
+                        note!("{left} ^ {right} = {val}");
+                        push_data(&mut stack, val);
+                    }
+
+                    OpCode::CompareEq => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = left == right;
+                        note!("{left} == {right} = {val}");
+                        push_data(&mut stack, val as i64);
+                    }
+                    OpCode::CompareNotEq => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = left != right;
+                        note!("{left} != {right} = {val}");
+                        push_data(&mut stack, val as i64);
+                    }
+                    OpCode::CompareLT => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = if vm.unsigned { vm.as_unsigned(left) < vm.as_unsigned(right) } else { left < right };
+                        note!("{left} < {right} = {val}");
+                        push_data(&mut stack, val as i64);
+                    }
+                    OpCode::CompareGT => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = if vm.unsigned { vm.as_unsigned(left) > vm.as_unsigned(right) } else { left > right };
+                        note!("{left} > {right} = {val}");
+                        push_data(&mut stack, val as i64);
+                    }
+                    OpCode::CompareLTEq => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = if vm.unsigned { vm.as_unsigned(left) <= vm.as_unsigned(right) } else { left <= right };
+                        note!("{left} <= {right} = {val}");
+                        push_data(&mut stack, val as i64);
+                    }
+                    OpCode::CompareGTEq => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = if vm.unsigned { vm.as_unsigned(left) >= vm.as_unsigned(right) } else { left >= right };
+                        note!("{left} >= {right} = {val}");
+                        push_data(&mut stack, val as i64);
+                    }
+
+                    OpCode::Jump => {
+                        let target = instr.target.expect("resolved by predecode");
+                        pc = self.code.instrs[target].addr;
+                        ip = Some(target);
+                        note!("{}", t!("pl0.jumping_to", pc = pc:{:04X}));
+                    }
+                    OpCode::JumpIfFalse => {
+                        let dat = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}", t!("pl0.jumping_if_bool", bool = dat == 0));
+                        if dat == 0 {
+                            let target = instr.target.expect("resolved by predecode");
+                            pc = self.code.instrs[target].addr;
+                            ip = Some(target);
+                            note!("{}", t!("pl0.jumping_if_where", pc = pc:{:04X}));
+                        }
+                    }
+
+                    OpCode::PutString => {
+                        // without the op code and the terminating 0
+                        let bytes = &vm.program[(instr_pc + 1)..(pc - 1)];
+                        let str = match vm.decode_string(bytes) {
+                            Ok(str) => str,
+                            Err(err) => break 'step fail!(instr_pc, &format!("\n{err}")),
+                        };
+                        note!("\"{str}\"");
+                        writeln!(out, "{str}").expect("Could not write program output");
+                        self.telemetry.output(&str);
+                        self.metrics.outputs += 1;
+                    }
+                    OpCode::PutStringRef => {
+                        let index = instr.args[0];
+                        let bytes = match vm.strings.get(index as usize) {
+                            Some(val) if index >= 0 => val,
+                            _ => break 'step fail!(instr_pc, &format!("\n{}", t!("pl0.invalid_string_index", index = index))),
+                        };
+                        let str = match vm.decode_string(bytes) {
+                            Ok(str) => str,
+                            Err(err) => break 'step fail!(instr_pc, &format!("\n{err}")),
+                        };
+                        note!("\"{str}\"");
+                        writeln!(out, "{str}").expect("Could not write program output");
+                        self.telemetry.output(&str);
+                        self.metrics.outputs += 1;
+                    }
+
+                    OpCode::Pop => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}", t!("pl0.popped", data = data));
+                    }
+                    OpCode::Swap => {
+                        let offset = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let data = match read(&stack, offset as usize) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}", t!("pl0.swapped", addr = offset as usize, val = data));
+                        push_data(&mut stack, data);
+                    }
+
+                    OpCode::EndOfCode => {
+                        break 'step Step::Finished;
+                    }
+                    // a second Wide prefix, rejected above since Wide has no arguments
+                    OpCode::Wide => unreachable!("double Wide prefix"),
+
+                    /*
+                    ---- Arrays ----
+                    The elements of an array are variables next to each other, the address of element i is
+                    the absolute stack address of the array (as PushAddressLocalVar/MainVar/GlobalVar push it)
+                    + i * data size - calculated with OpAddAddr, or with OpMultiply and OpAdd.
+                    Put and Get only access the variables of the frames of the current procedure and the
+                    procedures it was called from, anything else is out of bounds.
+
+                    Put: Stack before (bottom -> top): ... | Address | Value, after: ... with Stack[Address] = Value
+                    Get: Stack before (bottom -> top): ... | Address, after: ... | Stack[Address]
+                    OpAddAddr: Stack before (bottom -> top): ... | Address | Index, after: ... | Address + Index * data size
+                    */
+                    OpCode::Put => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let addr = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        if frame_holding(&stack, size, &procedures, cur_proc_i, fp, addr).is_none() {
+                            break 'step fail!(instr_pc, &out_of_bounds(&procedures, cur_proc_i, fp, addr));
+                        }
+                        note!("{}", t!("pl0.stored_value", val = data, addr = addr));
+                        last_write = match set_addr(&mut stack, addr, data) {
+                            Ok(write) => Some(write),
+                            Err(err) => break 'step fail!(instr_pc, &err),
+                        };
+                    }
+                    OpCode::Get => {
+                        let addr = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        if frame_holding(&stack, size, &procedures, cur_proc_i, fp, addr).is_none() {
+                            break 'step fail!(instr_pc, &out_of_bounds(&procedures, cur_proc_i, fp, addr));
+                        }
+                        let data = match read(&stack, addr as usize) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.unaligned_address", addr = addr)),
+                        };
+                        note!("{}", t!("pl0.took_from_addr", val = data, addr = addr));
+                        push_data(&mut stack, data);
+                    }
+                    OpCode::OpAddAddr => {
+                        let index = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let addr = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = addr.wrapping_add(index.wrapping_mul(size as i64));
+                        note!("{addr} + {index} * {size} = {val}");
+                        push_data(&mut stack, val);
+                    }
+
+                    OpCode::Alloc => {
+                        let bytes = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        if bytes < 0 {
+                            break 'step fail!(instr_pc, &t!("pl0.error.heap_size", bytes = bytes));
+                        }
+                        let cell = match self.heap.alloc((bytes as usize).div_ceil(size)) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.heap_full", bytes = bytes, limit = vm.heap_limit)),
+                        };
+                        note!("{}", t!("pl0.allocated", bytes = bytes, addr = cell * size));
+                        push_data(&mut stack, (cell * size) as i64);
+                    }
+                    OpCode::Free => {
+                        let addr = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}", t!("pl0.freed", addr = addr));
+                        if addr != 0 && (addr < 0 || !(addr as usize).is_multiple_of(size) || !self.heap.free(addr as usize / size)) {
+                            break 'step fail!(instr_pc, &t!("pl0.error.heap_free", addr = addr));
+                        }
+                    }
+                    OpCode::LoadHeap => {
+                        let addr = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let data = match usize::try_from(addr).ok().filter(|addr| addr.is_multiple_of(size)).and_then(|addr| self.heap.get(addr / size)) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.heap_address", addr = addr)),
+                        };
+                        note!("{}", t!("pl0.took_from_heap", val = data, addr = addr));
+                        push_data(&mut stack, data);
+                    }
+                    OpCode::StoreHeap => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let addr = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        note!("{}", t!("pl0.stored_in_heap", val = data, addr = addr));
+                        if !usize::try_from(addr).ok().filter(|addr| addr.is_multiple_of(size)).is_some_and(|addr| self.heap.set(addr / size, data)) {
+                            break 'step fail!(instr_pc, &t!("pl0.error.heap_address", addr = addr));
+                        }
+                    }
+
+                    OpCode::PushString => {
+                        let index = instr.args[0];
+                        let str = match usize::try_from(index).ok().and_then(|index| self.strings.get(index)) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_string", val = index)),
+                        };
+                        note!("{}", t!("pl0.pushed_string", str = String::from_utf8_lossy(str), val = index));
+                        push_data(&mut stack, index as i64);
+                    }
+                    OpCode::OutputString => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let bytes = match usize::try_from(data).ok().and_then(|index| self.strings.get(index)) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_string", val = data)),
+                        };
+                        let str = match vm.decode_string(bytes) {
+                            Ok(str) => str,
+                            Err(err) => break 'step fail!(instr_pc, &format!("\n{err}")),
+                        };
+                        note!("\"{str}\"");
+                        writeln!(out, "{str}").expect("Could not write program output");
+                        self.telemetry.output(&str);
+                        self.metrics.outputs += 1;
+                    }
+                    op @ (OpCode::StringConcat | OpCode::StringCompare) => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let (left_str, right_str) = match [left, right].map(|val| usize::try_from(val).ok().and_then(|index| self.strings.get(index))) {
+                            [Some(left), Some(right)] => (left, right),
+                            [None, _] => break 'step fail!(instr_pc, &t!("pl0.error.invalid_string", val = left)),
+                            [_, None] => break 'step fail!(instr_pc, &t!("pl0.error.invalid_string", val = right)),
+                        };
+                        let (left_text, right_text) = (String::from_utf8_lossy(left_str), String::from_utf8_lossy(right_str));
+                        let result = if op == OpCode::StringCompare {
+                            let result = left_str.cmp(right_str) as i64;
+                            note!("{}", t!("pl0.compared_strings", left = left_text, right = right_text, val = result));
+                            result
+                        } else {
+                            let joined = [left_str.as_slice(), right_str].concat();
+                            let index = self.strings.len() as i64;
+                            note!("{}", t!("pl0.concatenated", left = left_text, right = right_text, val = index));
+                            self.strings.push(joined);
+                            index
+                        };
+                        push_data(&mut stack, result);
+                    }
+
+                    op @ (OpCode::FloatAdd | OpCode::FloatSubtract | OpCode::FloatMultiply | OpCode::FloatDivide
+                        | OpCode::FloatCompareEq | OpCode::FloatCompareNotEq | OpCode::FloatCompareLT | OpCode::FloatCompareGT
+                        | OpCode::FloatCompareLTEq | OpCode::FloatCompareGTEq) => {
+                        let right = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let left = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = float_op(vm, op, left, right);
+                        // comparisons give 1 or 0 as integers
+                        let result = match op {
+                            OpCode::FloatAdd | OpCode::FloatSubtract | OpCode::FloatMultiply | OpCode::FloatDivide => vm.float_string(val),
+                            _ => val.to_string(),
+                        };
+                        note!("{op} {} {} = {result}", vm.float_string(left), vm.float_string(right));
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::FloatMinusify => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = vm.float_value(-vm.float(data));
+                        note!("{} => {}", vm.float_string(data), vm.float_string(val));
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::OutputFloat => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let str = vm.float_string(data);
+                        note!("{}", str);
+                        writeln!(out, "{}", str).expect("Could not write program output");
+                        self.telemetry.output(str);
+                        self.metrics.outputs += 1;
+                    }
+                    OpCode::IntToFloat => {
+                        let int = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        let val = vm.float_value(int as f64);
+                        note!("{} => {}", int, vm.float_string(val));
+                        push_data(&mut stack, val);
+                    }
+                    OpCode::FloatToInt => {
+                        let data = match pop_data(&mut stack) {
+                            Some(val) => val,
+                            None => break 'step fail!(instr_pc, &t!("pl0.error.invalid_stack_read")),
+                        };
+                        // saturating at the limits of the architecture instead of wrapping around
+                        let val = match vm.bits { B32(_) => vm.float(data) as i32 as i64, _ => vm.float(data) as i64 };
+                        note!("{} => {}", vm.float_string(data), val);
+                        push_data(&mut stack, val);
+                    }
                 }
 
-                OpCode::OpAdd => {
-                    let right = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let left = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let val = left + right;
-                    if self.debug { print!("{left} + {right} = {val}") }
-                    push_data(&mut stack, match self.bits {
-                        B16(_) => B16(val as i16), B32(_) => B32(val as i32), B64(_) => B64(val),
-                    });
+                Step::Running
+            };
+            self.metrics.instructions += 1;
+            if debug && !trace.is_empty() {
+                // keep the output where it happened between the logged instructions
+                out.flush().expect("Could not write program output");
+                log::debug!("{}", trace);
+                trace.clear();
+            }
+            if state != Step::Running {
+                if state == Step::Error {
+                    self.fault = Some(instr_pc);
                 }
-                OpCode::OpSubtract => {
-                    let right = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let left = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let val = left - right;
-                    if self.debug { print!("{left} - {right} = {val}") }
-                    push_data(&mut stack, match self.bits {
-                        B16(_) => B16(val as i16), B32(_) => B32(val as i32), B64(_) => B64(val),
-                    });
+                break;
+            }
+        }
+        // the most the stack ever held, the intermediate values of superinstructions are added above
+        self.metrics.peak_stack = self.metrics.peak_stack.max(stack.high_water() * size);
+        self.pc = pc;
+        self.ip = ip;
+        self.stack = stack;
+        self.fp = fp;
+        self.cur_proc_i = cur_proc_i;
+        self.procedures = procedures;
+        self.state = state;
+        self.last_write = last_write;
+        if interrupted {
+            self.report_interrupt();
+        }
+        state
+    }
+
+    // how far the run got when Ctrl+C stopped it: the active calls, innermost first, and the counters
+    fn report_interrupt(&self) {
+        for (i, frame) in self.backtrace().iter().enumerate() {
+            let returns = match frame.return_addr {
+                Some(addr) => format!(", {}", t!("debugger.returns_to", addr = addr:{:04X})),
+                None => String::new(),
+            };
+            diagln!("#{} {}{}", i, t!("debugger.frame", id = self.vm.proc_label(frame.procedure), fp = frame.frame_ptr:{:04X}), returns);
+        }
+        diagln!("{}", t!("stats.instructions", count = self.metrics.instructions));
+        diagln!("{}", t!("stats.calls", count = self.metrics.calls));
+        diagln!("{}", t!("stats.io", inputs = self.metrics.inputs, outputs = self.metrics.outputs));
+        diagln!("{}", t!("stats.peak_stack", bytes = self.metrics.peak_stack));
+    }
+
+    // Run until the program ends like run, for --fast: the verifier already proved that jumps, calls and constants
+    // are valid and that no instruction pops from an empty stack, so the common instructions run here without
+    // checking that again, without logging and step limits. Whatever could still fail at runtime (division
+    // by zero, stack addresses, variables outside of their frame) and the rarer instructions are left to a normal
+    // step, which reports the error if there is one. Only instructions, calls, outputs and the peak stack are counted.
+    // Programs with stack depth problems only got a warning when starting, they run with the normal steps.
+    pub fn run_fast(&mut self, out: &mut dyn Write) -> Step {
+        if !self.stack_verified {
+            return self.run(out);
+        }
+        let vm = self.vm;
+        let size = vm.data_size();
+        let code = Arc::clone(&self.code);
+        while self.state == Step::Running {
+            let (i, instr) = match self.ip.and_then(|ip| Some((ip, code.instrs.get(ip)?))).filter(|_| !interrupt::pending()) {
+                Some(val) => val,
+                // a normal step handles the rest, including Ctrl+C
+                None => { self.run_steps(out, 1); continue; }
+            };
+            let stack = &mut self.stack;
+            // where to go on, None if the instruction has to run as a normal step
+            let next = match instr.op {
+                OpCode::PushValueLocalVar | OpCode::PushValueMainVar | OpCode::PushValueGlobalVar => {
+                    let proc_id = match instr.op {
+                        OpCode::PushValueLocalVar => self.cur_proc_i,
+                        OpCode::PushValueMainVar => 0,
+                        _ => instr.args[1] as usize,
+                    };
+                    let base = if instr.op == OpCode::PushValueLocalVar { self.fp } else { self.procedures[proc_id].frame_ptr };
+                    // offsets outside of the frame are left to the normal step to report, the verifier doesn't check them
+                    let addr = base.checked_add_signed(instr.args[0] as isize).filter(|_| self.procedures[proc_id].holds(instr.args[0] as i64));
+                    match addr.and_then(|addr| stack.get(addr / size).filter(|_| addr.is_multiple_of(size))) {
+                        Some(&val) => {
+                            stack.push(val);
+                            Some(i + 1)
+                        }
+                        None => None,
+                    }
                 }
-                OpCode::OpMultiply => {
-                    let right = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let left = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let val = left * right;
-                    if self.debug { print!("{left} * {right} = {val}") }
-                    push_data(&mut stack, match self.bits {
-                        B16(_) => B16(val as i16), B32(_) => B32(val as i32), B64(_) => B64(val),
-                    });
+                OpCode::PushAddressLocalVar | OpCode::PushAddressMainVar | OpCode::PushAddressGlobalVar => {
+                    let proc_id = match instr.op {
+                        OpCode::PushAddressLocalVar => self.cur_proc_i,
+                        OpCode::PushAddressMainVar => 0,
+                        _ => instr.args[1] as usize,
+                    };
+                    let base = if instr.op == OpCode::PushAddressLocalVar { self.fp } else { self.procedures[proc_id].frame_ptr };
+                    let addr = base.checked_add_signed(instr.args[0] as isize).filter(|_| self.procedures[proc_id].holds(instr.args[0] as i64));
+                    match addr.and_then(|addr| vm.address_value(addr)) {
+                        Some(val) => {
+                            stack.push(val);
+                            Some(i + 1)
+                        }
+                        None => None,
+                    }
                 }
-                OpCode::OpDivide => {
-                    let right = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let left = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let val = left / right;
-                    if self.debug { print!("{left} / {right} = {val}") }
-                    push_data(&mut stack, match self.bits {
-                        B16(_) => B16(val as i16), B32(_) => B32(val as i32), B64(_) => B64(val),
-                    });
+                OpCode::PushConstant => {
+                    stack.push(self.constants[instr.args[0] as usize]);
+                    Some(i + 1)
                 }
-
-                OpCode::CompareEq => {
-                    let right = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let left = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let val = left == right;
-                    if self.debug { print!("{left} == {right} = {val}") }
-                    push_data(&mut stack, self.data_bool(val));
+                OpCode::StoreValue => {
+                    let (val, addr) = (stack[stack.len() - 1], stack[stack.len() - 2] as usize);
+                    // addresses beyond the stack are left to the normal step to report
+                    if addr.is_multiple_of(size) && addr / size < stack.len() - 2 {
+                        stack.truncate(stack.len() - 2);
+                        stack[addr / size] = vm.wrap(val);
+                        Some(i + 1)
+                    } else {
+                        None
+                    }
                 }
-                OpCode::CompareNotEq => {
-                    let right = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let left = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let val = left != right;
-                    if self.debug { print!("{left} != {right} = {val}") }
-                    push_data(&mut stack, self.data_bool(val));
+                OpCode::OutputValue => {
+                    let val = stack.pop().expect("stack depth verified");
+                    vm.write_value(out, val);
+                    self.telemetry.output(val);
+                    self.metrics.outputs += 1;
+                    Some(i + 1)
                 }
-                OpCode::CompareLT => {
-                    let right = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let left = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let val = left < right;
-                    if self.debug { print!("{left} < {right} = {val}") }
-                    push_data(&mut stack, self.data_bool(val));
+                OpCode::Minusify => {
+                    let top = stack.last_mut().expect("stack depth verified");
+                    *top = vm.wrap(-*top);
+                    Some(i + 1)
                 }
-                OpCode::CompareGT => {
-                    let right = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let left = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let val = left > right;
-                    if self.debug { print!("{left} > {right} = {val}") }
-                    push_data(&mut stack, self.data_bool(val));
+                OpCode::IsOdd => {
+                    let top = stack.last_mut().expect("stack depth verified");
+                    *top = (*top % 2 != 0) as i64;
+                    Some(i + 1)
                 }
-                OpCode::CompareLTEq => {
-                    let right = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let left = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let val = left <= right;
-                    if self.debug { print!("{left} <= {right} = {val}") }
-                    push_data(&mut stack, self.data_bool(val));
+                op if op.is_binary() => {
+                    let len = stack.len();
+                    let right = stack[len - 1];
+                    let left = &mut stack[len - 2];
+                    match binary_op(vm, op, *left, right) {
+                        Some(val) => {
+                            *left = vm.wrap(val);
+                            stack.pop();
+                            Some(i + 1)
+                        }
+                        None => None,
+                    }
                 }
-                OpCode::CompareGTEq => {
-                    let right = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let left = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let val = left >= right;
-                    if self.debug { print!("{left} >= {right} = {val}") }
-                    push_data(&mut stack, self.data_bool(val));
+                OpCode::Jump => instr.target,
+                OpCode::JumpIfFalse => {
+                    if stack.pop().expect("stack depth verified") == 0 { instr.target } else { Some(i + 1) }
                 }
-
-                OpCode::Jump => {
-                    let offset = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    pc = offsetted(&pc, offset as isize);
-                    if self.debug { print!("{}", t!("pl0.jumping_to", pc = pc:{:04X})); }
+                OpCode::Pop => {
+                    stack.pop();
+                    Some(i + 1)
                 }
-                OpCode::JumpIfFalse => {
-                    let dat = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let offset = match pop_argument(&mut pc) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_arg_read", addr = pc:{:04X})),
-                    };
-                    if self.debug { print!("{}", t!("pl0.jumping_if_bool", bool = dat == 0)); }
-                    if dat == 0 {
-                        pc = offsetted(&pc, offset as isize);
-                        if self.debug { print!("{}", t!("pl0.jumping_if_where", pc = pc:{:04X})); }
+                OpCode::Swap | OpCode::Get => {
+                    let top = stack.len() - 1;
+                    let addr = stack[top] as usize;
+                    // arrays outside of the current frame are left to the normal step, which looks through the callers
+                    let in_frame = instr.op == OpCode::Swap || addr.checked_sub(self.fp).is_some_and(|offset| self.procedures[self.cur_proc_i].holds(offset as i64));
+                    match stack[..top].get(addr / size) {
+                        Some(&val) if in_frame && addr.is_multiple_of(size) => {
+                            stack[top] = val;
+                            Some(i + 1)
+                        }
+                        _ => None,
                     }
                 }
-
-                OpCode::PutString => {
-                    let bytes: Vec<u8> = self.program[pc..].iter().take_while(|&&b| b != 0).map(|&b| b).collect();
-                    pc += bytes.len() + 1;
-                    let str = match String::from_utf8(bytes) {
-                        Ok(str) => str,
-                        Err(err) => {
-                            error(&format!("\n{}", t!("pl0.invalid_str", err = err)));
-                            break;
-                        }
-                    };
-                    if self.debug {
-                        print!("\"{str}\"\n{str}");
+                OpCode::Put => {
+                    let (val, addr) = (stack[stack.len() - 1], stack[stack.len() - 2] as usize);
+                    let in_frame = addr.checked_sub(self.fp).is_some_and(|offset| self.procedures[self.cur_proc_i].holds(offset as i64));
+                    if in_frame && addr.is_multiple_of(size) && addr / size < stack.len() - 2 {
+                        stack.truncate(stack.len() - 2);
+                        stack[addr / size] = vm.wrap(val);
+                        Some(i + 1)
                     } else {
-                        println!("{str}");
+                        None
                     }
                 }
-
-                OpCode::Pop => {
-                    if self.debug {
-                        println!("{}", t!("pl0.popped", data = match pop_data(&mut stack) {
-                            Some(val) => val,
-                            None => return error(&t!("pl0.error.invalid_stack_read")),
-                        }.i64()));
-                    } else {
-                        pop_data(&mut stack);
+                OpCode::PutString => match vm.decode_string(&vm.program[(instr.addr + 1)..(instr.addr + instr.len - 1)]) {
+                    Ok(str) => {
+                        writeln!(out, "{str}").expect("Could not write program output");
+                        self.telemetry.output(&str);
+                        self.metrics.outputs += 1;
+                        Some(i + 1)
                     }
+                    Err(_) => None,
+                },
+                OpCode::EntryProc => {
+                    self.fp = self.procedures[instr.args[1] as usize].frame_ptr;
+                    stack.resize((self.fp + instr.args[2] as usize).div_ceil(size), 0);
+                    Some(i + 1)
                 }
-                OpCode::Swap => {
-                    let offset = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let data = match self.bytes_to_data(&stack.get((offset as usize)..)) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    };
-                    if self.debug { print!("{}", t!("pl0.swapped", addr = offset as usize, val = data.i64())) }
-                    push_data(&mut stack, data);
+                OpCode::CallProc if !instr.tail => {
+                    let proc_id = instr.args[0] as usize;
+                    let info = stack.len();
+                    stack.extend([(instr.addr + instr.len) as i64, self.fp as i64, self.cur_proc_i as i64]);
+                    stack.resize(info + CALL_FRAME_SIZE / size, 0);
+                    self.procedures[proc_id].frame_ptr = stack.len() * size;
+                    self.cur_proc_i = proc_id;
+                    self.depth += 1;
+                    self.telemetry.call(vm, proc_id);
+                    self.metrics.calls += 1;
+                    instr.target
                 }
-
-                OpCode::EndOfCode => {
-                    if self.debug { println!(); }
-                    break;
+                OpCode::ReturnProc if self.cur_proc_i != 0 => {
+                    let info = (self.fp / size).saturating_sub(CALL_FRAME_SIZE / size);
+                    match stack.get(info..(info + 3)) {
+                        Some(&[pc, fp, proc_i]) if code.at(pc as usize).is_some() => {
+                            stack.truncate(info);
+                            if proc_i as usize == self.cur_proc_i {
+                                self.procedures[self.cur_proc_i].frame_ptr = fp as usize;
+                            }
+                            self.fp = fp as usize;
+                            self.cur_proc_i = proc_i as usize;
+                            self.depth = self.depth.saturating_sub(1);
+                            self.telemetry.ret();
+                            code.at(pc as usize)
+                        }
+                        _ => None,
+                    }
                 }
-
-                /*
-                ---- Store variable value at dynamic calculated address ----
-                Stack before (bottom -> top): ... | AddressOffset | Value
-                Stack after: ... Stack[abs_addr] = Value
-            
-                ---- Sequence of operations: ----
-                1. Pop Value from stack
-                2. Pop AddressOffset from stack
-                3. Calculate AbsoluteAddress = fp + AddressOffset
-                4. Write Value to stack at AbsoluteAddress
-                */
-                OpCode::Put => {
-                    let data = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    };
-                    let addr = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let abs_addr = offsetted(&fp, addr as isize);
-                    if self.debug { print!("addr (rel) = {addr}, abs_addr = {abs_addr}, value = {:?}", data.i64()); }
-                    set_addr(&mut stack, &abs_addr, &data);
+                // returning from main, tail calls and the rest run as normal steps
+                _ => None,
+            };
+            match next {
+                Some(next) => {
+                    self.ip = Some(next);
+                    self.pc = code.instrs.get(next).map(|next| next.addr).unwrap_or(instr.addr + instr.len);
+                    self.metrics.instructions += 1;
                 }
+                None => { self.run_steps(out, 1); }
+            }
+        }
+        self.metrics.peak_stack = self.metrics.peak_stack.max(self.stack.high_water() * size);
+        self.state
+    }
 
-                /*
-                ---- Get variable value from dynamic calculated address ----
-                Stack before (bottom -> top): ... | addressOffset
-                Stack after: ... | Value
-            
-                ---- Sequence of operations: ----
-                0. ADD-Instruction:  addressOffset = baseAddress + indexOffset
-                1. Pop addressOffset from stack
-                2. Read Value at stack[addressOffset]
-                3. push value onto the stack
-                */
-                OpCode::Get => {
-                    let addr = match pop_data(&mut stack) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    }.i64();
-                    let data = match self.bytes_to_data(&stack.get((addr as usize)..)) {
-                        Some(val) => val,
-                        None => return error(&t!("pl0.error.invalid_stack_read")),
-                    };
-                    if self.debug { print!("addr = {addr}, value = {:?}", data.i64()); }
-                    push_data(&mut stack, data);
-                }
+    // the state for a core dump, see coredump.rs
+    pub(crate) fn core_dump(&self) -> CoreDump {
+        CoreDump {
+            program: to_hex(&self.vm.to_file_bytes()),
+            fault: self.fault,
+            pc: self.pc,
+            fp: self.fp,
+            procedure: self.cur_proc_i,
+            depth: self.depth,
+            frame_ptrs: self.procedures.iter().map(|proc| proc.frame_ptr).collect(),
+            stack: self.stack.to_vec(),
+            inputs: self.inputs.clone(),
+        }
+    }
+    // continue from the state of a core dump of the same program, false if it doesn't fit the program
+    pub(crate) fn restore(&mut self, core: &CoreDump) -> bool {
+        if core.frame_ptrs.len() != self.procedures.len() || core.procedure >= self.procedures.len() {
+            return false;
+        }
+        for (proc, frame_ptr) in self.procedures.iter_mut().zip(&core.frame_ptrs) {
+            proc.frame_ptr = *frame_ptr;
+        }
+        self.pc = core.pc;
+        self.ip = self.code.at(core.pc);
+        self.fp = core.fp;
+        self.cur_proc_i = core.procedure;
+        self.depth = core.depth;
+        self.stack = Stack::from(core.stack.clone());
+        self.inputs = core.inputs.clone();
+        self.fault = core.fault;
+        self.state = if core.fault.is_some() { Step::Error } else { Step::Running };
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                OpCode::OpAddAddr => { todo!() }
+    // main calls procedure 1, which pushes its variable at offset with op and outputs it
+    fn variable_access(op: OpCode, offset: i16) -> PL0VM {
+        let mut bytes = vec![0x02, 0x00, 0x04, 0x00, 0x1A, 0x0C, 0x00, 0x01, 0x00, 0x04, 0x00, u8::from(op)];
+        bytes.extend_from_slice(&offset.to_le_bytes());
+        bytes.extend_from_slice(&[0x08, 0x17, 0x1A, 0x0B, 0x00, 0x00, 0x00, 0x04, 0x00, 0x16, 0x01, 0x00, 0x17]);
+        PL0VM::from_bytes(bytes)
+    }
+
+    #[test]
+    fn fast_refuses_offsets_outside_of_frame() {
+        for op in [OpCode::PushValueLocalVar, OpCode::PushAddressLocalVar, OpCode::PushValueMainVar, OpCode::PushAddressMainVar] {
+            for offset in [-2, -4, -8, 4, i16::MAX] {
+                let vm = variable_access(op, offset);
+                assert_eq!(vm.start().unwrap().run(&mut vec![]), Step::Error, "{op} {offset}");
+                assert_eq!(vm.start().unwrap().run_fast(&mut vec![]), Step::Error, "{op} {offset}");
             }
+        }
+    }
 
-            match op {
-                OpCode::InputToAddr => (),
-                _ => if self.debug { println!(); }
-            };
+    #[test]
+    fn fast_runs_like_normal() {
+        for op in [OpCode::PushValueLocalVar, OpCode::PushValueMainVar] {
+            let vm = variable_access(op, 0);
+            let (mut normal, mut fast) = (vec![], vec![]);
+            assert_eq!(vm.start().unwrap().run(&mut normal), Step::Finished);
+            assert_eq!(vm.start().unwrap().run_fast(&mut fast), Step::Finished);
+            assert_eq!(normal, b"0\n");
+            assert_eq!(fast, normal);
         }
     }
 }
@@ -0,0 +1,165 @@
+use crate::pl0_vm::{capture_errors, error, Metrics, Step, PL0VM};
+use rust_i18n::t;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+// VMs for services that run one program after the other, e.g. per request: a pool keeps idle VMs around, hands one
+// out for a run and takes it back afterwards, with the program forgotten. Every run is held to the limits of the
+// pool, so a program looping forever or printing without end can't take the service down with it.
+
+// limits of a single run, None for no limit
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_instructions: Option<usize>,
+    // bytes printed by the program
+    pub max_output: Option<usize>,
+    // bytes on the stack at any time
+    pub max_stack: Option<usize>,
+}
+
+// how a run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Finished,
+    // stopped by a runtime error
+    Error,
+    // the program couldn't be loaded or didn't pass the checks before running
+    NotStarted,
+    InstructionLimit,
+    OutputLimit,
+    StackLimit,
+}
+
+// what a run did
+#[derive(Debug, Clone)]
+pub struct RunResult {
+    pub outcome: Outcome,
+    // what Halt ended the program with, 0 otherwise
+    pub status: i64,
+    // everything the program printed, cut off at the output limit
+    pub output: Vec<u8>,
+    // error messages, one per line, as they would have gone to stderr
+    pub errors: String,
+    pub metrics: Metrics,
+}
+
+// instructions run between checking the limits
+const CHUNK: usize = 4096;
+
+pub struct VmPool {
+    idle: Mutex<Vec<PL0VM>>,
+    limits: Limits,
+}
+
+impl VmPool {
+    // a pool with size VMs ready, more are created when all of them are in use
+    pub fn new(size: usize, limits: Limits) -> VmPool {
+        VmPool { idle: Mutex::new((0..size).map(|_| PL0VM::new()).collect()), limits }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    // VMs waiting to be handed out
+    pub fn idle(&self) -> usize {
+        self.idle.lock().expect("a user of the pool panicked").len()
+    }
+
+    // take a VM out of the pool, it goes back when the returned guard is dropped
+    pub fn get(&self) -> PooledVm<'_> {
+        let vm = self.idle.lock().expect("a user of the pool panicked").pop().unwrap_or_default();
+        PooledVm { vm: Some(vm), pool: self }
+    }
+
+    // load the program from bytes (any format from_bytes accepts) into a VM of the pool and run it
+    pub fn run(&self, bytes: Vec<u8>, inputs: &[i64]) -> RunResult {
+        self.get().run(bytes, inputs)
+    }
+}
+
+// a VM handed out by VmPool, settings like tail calls can be changed through it and stay for later runs
+pub struct PooledVm<'a> {
+    // only None while dropping
+    vm: Option<PL0VM>,
+    pool: &'a VmPool,
+}
+
+impl PooledVm<'_> {
+    // load the program and run it within the limits of the pool, reading only the given numbers
+    pub fn run(&mut self, bytes: Vec<u8>, inputs: &[i64]) -> RunResult {
+        let limits = self.pool.limits;
+        let vm: &mut PL0VM = self;
+        let mut output = vec![];
+        let mut metrics = Metrics::default();
+        let mut status = 0;
+        let (outcome, errors) = capture_errors(|| {
+            if !vm.load_from_bytes(bytes) {
+                return Outcome::NotStarted;
+            }
+            let mut execution = match vm.start() {
+                Some(val) => val,
+                None => return Outcome::NotStarted,
+            };
+            execution.feed_inputs(inputs.iter().copied());
+            execution.close_input();
+            let outcome = loop {
+                let ran = execution.metrics().instructions;
+                let chunk = match limits.max_instructions {
+                    Some(max) if ran >= max => {
+                        error(&t!("pool.instruction_limit", max = max));
+                        break Outcome::InstructionLimit;
+                    },
+                    Some(max) => CHUNK.min(max - ran),
+                    None => CHUNK,
+                };
+                let state = execution.run_for(&mut output, chunk);
+                if limits.max_output.is_some_and(|max| output.len() > max) {
+                    error(&t!("pool.output_limit", max = limits.max_output.unwrap_or_default()));
+                    break Outcome::OutputLimit;
+                }
+                if limits.max_stack.is_some_and(|max| execution.metrics().peak_stack > max) {
+                    error(&t!("pool.stack_limit", max = limits.max_stack.unwrap_or_default()));
+                    break Outcome::StackLimit;
+                }
+                match state {
+                    Step::Running => {},
+                    Step::Finished => break Outcome::Finished,
+                    Step::Error => break Outcome::Error,
+                }
+            };
+            metrics = execution.metrics();
+            status = execution.status();
+            outcome
+        });
+        if let Some(max) = limits.max_output {
+            output.truncate(max);
+        }
+        RunResult { outcome, status, output, errors, metrics }
+    }
+}
+
+impl Deref for PooledVm<'_> {
+    type Target = PL0VM;
+    fn deref(&self) -> &PL0VM {
+        self.vm.as_ref().expect("only taken when dropped")
+    }
+}
+
+impl DerefMut for PooledVm<'_> {
+    fn deref_mut(&mut self) -> &mut PL0VM {
+        self.vm.as_mut().expect("only taken when dropped")
+    }
+}
+
+impl Drop for PooledVm<'_> {
+    fn drop(&mut self) {
+        if let Some(mut vm) = self.vm.take() {
+            vm.reset();
+            // a poisoned pool only loses the VM
+            if let Ok(mut idle) = self.pool.idle.lock() {
+                idle.push(vm);
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::decoder::decode_instruction;
+use crate::opcodes::OpCode;
+use crate::pl0_vm::{Step, PL0VM, START_FAILED_STATUS};
+use crate::diagln;
+use rust_i18n::t;
+use std::collections::BTreeMap;
+use std::io::stdout;
+use std::time::{Duration, Instant};
+
+// Counts and times gathered while running a program, see PL0VM::execute_profiled.
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    // executed instructions, a Wide prefix and its instruction count as one
+    pub instructions: usize,
+    // how often every op code was executed
+    pub op_counts: BTreeMap<OpCode, usize>,
+    // procedure ID => what was run in it
+    pub procedures: Vec<ProcedureProfile>,
+    // loops, found by their jumps back: (start, end) => how often the jump back was taken
+    pub loops: BTreeMap<(usize, usize), Loop>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Loop {
+    pub procedure: usize,
+    // how often the jump back to the start was taken
+    pub iterations: usize,
+}
+
+// "self" is what was run in the procedure itself, "total" includes the procedures it called.
+// Totals of recursive procedures only count the outermost call, so nothing is counted twice.
+#[derive(Debug, Default, Clone)]
+pub struct ProcedureProfile {
+    pub calls: usize,
+    pub self_instructions: usize,
+    pub total_instructions: usize,
+    pub self_time: Duration,
+    pub total_time: Duration,
+}
+
+// a running call while profiling
+struct ActiveCall {
+    procedure: usize,
+    instructions: usize,
+    start: Instant,
+}
+
+impl Profile {
+    // op codes with their counts, the most frequent first
+    pub fn ops_by_count(&self) -> Vec<(OpCode, usize)> {
+        let mut ops: Vec<(OpCode, usize)> = self.op_counts.iter().map(|(op, count)| (*op, *count)).collect();
+        ops.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        ops
+    }
+    // part of all executed instructions, in percent
+    pub fn share(&self, count: usize) -> f64 {
+        if self.instructions == 0 { 0.0 } else { count as f64 * 100.0 / self.instructions as f64 }
+    }
+    // procedure IDs of the procedures that were called, the most expensive (by total instructions) first
+    pub fn procedures_by_total(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = (0..self.procedures.len()).filter(|&id| self.procedures[id].calls > 0).collect();
+        ids.sort_by(|&a, &b| self.procedures[b].total_instructions.cmp(&self.procedures[a].total_instructions).then(a.cmp(&b)));
+        ids
+    }
+
+    // loops with at least min_iterations, the most iterations first, as (start, end (exclusive), loop)
+    pub fn hot_loops(&self, min_iterations: usize) -> Vec<(usize, usize, &Loop)> {
+        let mut loops: Vec<(usize, usize, &Loop)> = self.loops.iter()
+            .filter(|(_, hot)| hot.iterations >= min_iterations)
+            .map(|((start, end), hot)| (*start, *end, hot))
+            .collect();
+        loops.sort_by(|a, b| b.2.iterations.cmp(&a.2.iterations).then(a.0.cmp(&b.0)));
+        loops
+    }
+
+    fn end_call(&mut self, calls: &mut Vec<ActiveCall>) {
+        let call = match calls.pop() {
+            Some(val) => val,
+            None => return,
+        };
+        if calls.iter().all(|outer| outer.procedure != call.procedure) {
+            let procedure = &mut self.procedures[call.procedure];
+            procedure.total_instructions += self.instructions - call.instructions;
+            procedure.total_time += call.start.elapsed();
+        }
+    }
+}
+
+impl PL0VM {
+    // run like execute and count what was executed, with the status like execute
+    pub fn execute_profiled(&self) -> (Profile, i64) {
+        let mut profile = Profile::default();
+        let mut execution = match self.start() {
+            Some(val) => val,
+            None => return (profile, START_FAILED_STATUS),
+        };
+        profile.procedures = vec![ProcedureProfile::default(); execution.procedure_count()];
+        profile.procedures[execution.current_procedure()].calls = 1;
+        let mut calls = vec![ActiveCall { procedure: execution.current_procedure(), instructions: 0, start: Instant::now() }];
+        loop {
+            let instr = decode_instruction(self.program(), execution.pc()).ok();
+            if let Some(instr) = &instr {
+                *profile.op_counts.entry(instr.op).or_insert(0) += 1;
+            }
+            profile.instructions += 1;
+            let depth = execution.call_depth();
+            let start = Instant::now();
+            let state = execution.step(&mut stdout());
+            // the call or return itself counts for the caller
+            if let Some(call) = calls.last() {
+                let procedure = &mut profile.procedures[call.procedure];
+                procedure.self_instructions += 1;
+                procedure.self_time += start.elapsed();
+            }
+            // a jump that didn't go forward closes a loop
+            if let Some(instr) = instr.filter(|instr| matches!(instr.op, OpCode::Jump | OpCode::JumpIfFalse)) {
+                if state == Step::Running && execution.pc() <= instr.addr {
+                    let hot = profile.loops.entry((execution.pc(), instr.addr + instr.len)).or_default();
+                    hot.procedure = execution.current_procedure();
+                    hot.iterations += 1;
+                }
+            }
+            if execution.call_depth() > depth {
+                profile.procedures[execution.current_procedure()].calls += 1;
+                calls.push(ActiveCall { procedure: execution.current_procedure(), instructions: profile.instructions, start: Instant::now() });
+            } else if execution.call_depth() < depth {
+                profile.end_call(&mut calls);
+            }
+            if state != Step::Running {
+                break;
+            }
+        }
+        // main and calls that were running when the program ended
+        while !calls.is_empty() {
+            profile.end_call(&mut calls);
+        }
+        (profile, execution.status())
+    }
+
+    // for --profile, after the output of the program
+    pub fn print_profile(&self, profile: &Profile) {
+        diagln!();
+        diagln!("{}", t!("profile.instructions", count = profile.instructions));
+        diagln!("{:<20} {:>10} {:>8}", t!("profile.op"), t!("profile.count"), t!("profile.share"));
+        for (op, count) in profile.ops_by_count() {
+            diagln!("{:<20} {:>10} {:>7.2}%", op, count, profile.share(count));
+        }
+        diagln!();
+        diagln!("{:<20} {:>8} {:>10} {:>10} {:>12} {:>12}", t!("profile.procedure"), t!("profile.calls"),
+            t!("profile.self"), t!("profile.total"), t!("profile.self_time"), t!("profile.total_time"));
+        let millis = |time: Duration| format!("{:.3} ms", time.as_secs_f64() * 1000.0);
+        for proc_id in profile.procedures_by_total() {
+            let procedure = &profile.procedures[proc_id];
+            diagln!("{:<20} {:>8} {:>10} {:>10} {:>12} {:>12}", self.proc_label(proc_id), procedure.calls, procedure.self_instructions,
+                procedure.total_instructions, millis(procedure.self_time), millis(procedure.total_time));
+        }
+    }
+
+    // for --hot-loops, after the output of the program
+    pub fn print_hot_loops(&self, profile: &Profile, min_iterations: usize) {
+        diagln!();
+        let loops = profile.hot_loops(min_iterations);
+        if loops.is_empty() {
+            return diagln!("{}", t!("profile.no_hot_loops", min = min_iterations));
+        }
+        for (start, end, hot) in loops {
+            diagln!("{}", t!("profile.hot_loop", start = start:{:04X}, end = end:{:04X}, id = self.proc_label(hot.procedure), iterations = hot.iterations));
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Pseudo-random numbers for Random: xorshift64*, with the seed spread out by splitmix64 first so that every seed,
+// even 0, gives a usable state. The numbers only depend on the seed, so runs with the same --seed are the same.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng {
+    // never 0, xorshift would stay there
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        let mut mixed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        mixed ^= mixed >> 31;
+        Rng { state: mixed.max(1) }
+    }
+
+    // a seed that differs from run to run, for when none was set
+    pub fn time_seed() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_nanos() as u64).unwrap_or_default()
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
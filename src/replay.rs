@@ -0,0 +1,34 @@
+use rust_i18n::t;
+
+// Replay files hold the seed Random started from and the numbers a run read with InputToAddr, one per line, which is
+// everything that can differ between two runs of the same program - recording and replaying run Clock on the virtual
+// clock. Lines starting with # are comments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Replay {
+    // None if the file has no seed line, Random then starts from --seed or the time like in other runs
+    pub seed: Option<u64>,
+    pub inputs: Vec<i64>,
+}
+
+pub fn parse_replay(text: &str) -> Result<Replay, String> {
+    let mut replay = Replay::default();
+    for (i, line) in text.lines().map(str::trim).enumerate().filter(|(_, line)| !line.is_empty() && !line.starts_with('#')) {
+        let invalid = || t!("replay.invalid_line", line = i + 1, text = line).to_string();
+        match line.strip_prefix("seed ") {
+            Some(seed) => replay.seed = Some(seed.trim().parse().map_err(|_| invalid())?),
+            None => replay.inputs.push(line.parse().map_err(|_| invalid())?),
+        }
+    }
+    Ok(replay)
+}
+
+pub fn format_replay(program: &str, replay: &Replay) -> String {
+    let mut text = format!("# {}\n", t!("replay.header", file = program));
+    if let Some(seed) = replay.seed {
+        text += &format!("seed {}\n", seed);
+    }
+    for input in &replay.inputs {
+        text += &format!("{}\n", input);
+    }
+    text
+}
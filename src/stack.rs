@@ -0,0 +1,81 @@
+use std::ops::{Deref, DerefMut};
+
+// The runtime stack of an execution: the frames of the procedures and the values above them, one value of the
+// architecture per cell. Cells of frames that were left stay allocated and are handed out again to the next frame,
+// so call-heavy programs don't grow and shrink the vector on every call. Only the cells in use can be accessed.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Stack {
+    // every cell used so far, the ones from len on belong to frames that are gone
+    cells: Vec<i64>,
+    // cells in use
+    len: usize,
+    // most cells in use at the same time
+    high_water: usize,
+}
+
+impl Stack {
+    pub fn with_capacity(cells: usize) -> Stack {
+        Stack { cells: Vec::with_capacity(cells), len: 0, high_water: 0 }
+    }
+
+    pub fn high_water(&self) -> usize {
+        self.high_water
+    }
+
+    pub fn push(&mut self, val: i64) {
+        if self.len < self.cells.len() {
+            self.cells[self.len] = val;
+        } else {
+            self.cells.push(val);
+        }
+        self.len += 1;
+        self.high_water = self.high_water.max(self.len);
+    }
+
+    pub fn pop(&mut self) -> Option<i64> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.cells[self.len])
+    }
+
+    pub fn extend(&mut self, vals: impl IntoIterator<Item = i64>) {
+        for val in vals {
+            self.push(val);
+        }
+    }
+
+    // leave the cells from len on, they are reused later
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    // grow or shrink to len cells, new ones are set to val
+    pub fn resize(&mut self, len: usize, val: i64) {
+        if len > self.len {
+            let reused = len.min(self.cells.len());
+            self.cells[self.len..reused].fill(val);
+            self.cells.resize(self.cells.len().max(len), val);
+            self.high_water = self.high_water.max(len);
+        }
+        self.len = len;
+    }
+}
+
+impl Deref for Stack {
+    type Target = [i64];
+    fn deref(&self) -> &[i64] {
+        &self.cells[..self.len]
+    }
+}
+
+impl DerefMut for Stack {
+    fn deref_mut(&mut self) -> &mut [i64] {
+        &mut self.cells[..self.len]
+    }
+}
+
+impl From<Vec<i64>> for Stack {
+    fn from(cells: Vec<i64>) -> Stack {
+        let len = cells.len();
+        Stack { cells, len, high_water: len }
+    }
+}
@@ -0,0 +1,70 @@
+use crate::opcodes::OpCode;
+use crate::pl0_vm::{CALL_FRAME_SIZE, PL0VM};
+use crate::diagln;
+use rust_i18n::t;
+
+// Static upper bounds of the stack space a program needs, from the stack depths the verifier calculates.
+#[derive(Debug, Default, Clone)]
+pub struct StackUsage {
+    // procedure ID => most values on the operand stack of the procedure at the same time
+    pub max_depth: Vec<usize>,
+    // procedure ID => bytes of its frame: variables and operand stack
+    pub frame_size: Vec<usize>,
+    // bytes of the whole stack while running the program, None if procedures are recursive
+    pub total: Option<usize>,
+}
+
+impl PL0VM {
+    pub fn stack_usage(&self) -> Result<StackUsage, String> {
+        let layout = self.procedure_layout()?;
+        let instructions = self.decode_code()?;
+        let graph = self.call_graph()?;
+        let mut usage = StackUsage { max_depth: vec![0; layout.len()], frame_size: vec![0; layout.len()], total: None };
+        for ((proc_id, _), procedure) in layout.iter().zip(instructions.chunk_by(|_, instr| instr.op != OpCode::EntryProc)) {
+            let (depths, _) = self.stack_depths(procedure);
+            // the deepest point is reached right after an instruction pushed its result
+            usage.max_depth[*proc_id] = procedure.iter().zip(depths)
+                .filter_map(|(instr, depth)| {
+                    let (pops, pushes) = self.stack_effect(instr);
+                    depth.map(|depth| depth.max(depth.saturating_sub(pops) + pushes))
+                })
+                .max()
+                .unwrap_or_default();
+            let variables = self.read_operand(procedure[0].arg_addr(2), procedure[0].wide).expect("length checked while decoding");
+            usage.frame_size[*proc_id] = variables.max(0) as usize + usage.max_depth[*proc_id] * self.data_size();
+        }
+
+        if graph.cycles().is_empty() {
+            // stack usage of every procedure including everything it calls, filled callees first
+            let mut totals: Vec<Option<usize>> = vec![None; layout.len()];
+            let mut pending = vec![0];
+            while let Some(&proc_id) = pending.last() {
+                let missing: Vec<usize> = graph.callees(proc_id).iter().copied().filter(|&callee| totals[callee].is_none()).collect();
+                if !missing.is_empty() {
+                    pending.extend(missing);
+                    continue;
+                }
+                pending.pop();
+                let calls = graph.callees(proc_id).iter().map(|&callee| CALL_FRAME_SIZE + totals[callee].expect("filled before the caller")).max();
+                totals[proc_id] = Some(usage.frame_size[proc_id] + calls.unwrap_or_default());
+            }
+            usage.total = totals[0];
+        }
+        Ok(usage)
+    }
+
+    // part of the analysis: stack bounds of every procedure and of the whole program
+    pub(crate) fn print_stack_usage(&self) {
+        let usage = match self.stack_usage() {
+            Ok(val) => val,
+            Err(_) => return,
+        };
+        for (proc_id, (depth, frame)) in usage.max_depth.iter().zip(&usage.frame_size).enumerate() {
+            diagln!("{}", t!("pl0.stack_usage.procedure", id = self.proc_label(proc_id), depth = depth, bytes = frame));
+        }
+        match usage.total {
+            Some(total) => diagln!("{}", t!("pl0.stack_usage.total", bytes = total)),
+            None => diagln!("{}", t!("pl0.stack_usage.recursive")),
+        }
+    }
+}
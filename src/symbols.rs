@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+// Debug symbols, stored in the SYMS section as a list of entries:
+//   procedure: 0x00 <procedure ID: 2 bytes> <name> 0x00
+//   variable:  0x01 <procedure ID: 2 bytes> <variable address: 2 bytes> <name> 0x00
+// Variable addresses are the same offsets the variable access op codes use.
+
+const KIND_PROCEDURE: u8 = 0;
+const KIND_VARIABLE: u8 = 1;
+
+#[derive(Debug, Default, Clone)]
+pub struct Symbols {
+    pub procedures: HashMap<u16, String>,
+    pub variables: HashMap<(u16, u16), String>,
+}
+
+impl Symbols {
+    pub fn procedure(&self, id: u16) -> Option<&str> {
+        self.procedures.get(&id).map(|name| name.as_str())
+    }
+    pub fn variable(&self, proc_id: u16, addr: u16) -> Option<&str> {
+        self.variables.get(&(proc_id, addr)).map(|name| name.as_str())
+    }
+
+    // returns None if the section data is malformed
+    pub fn parse(data: &[u8]) -> Option<Symbols> {
+        let read_u16 = |pos: usize| Some(u16::from_le_bytes(data.get(pos..(pos + 2))?.try_into().ok()?));
+        let read_name = |pos: usize| {
+            let len = data.get(pos..)?.iter().position(|&b| b == 0)?;
+            Some((String::from_utf8(data[pos..(pos + len)].to_vec()).ok()?, pos + len + 1))
+        };
+
+        let mut symbols = Symbols::default();
+        let mut pos = 0;
+        while pos < data.len() {
+            let proc_id = read_u16(pos + 1)?;
+            match data[pos] {
+                KIND_PROCEDURE => {
+                    let (name, next) = read_name(pos + 3)?;
+                    symbols.procedures.insert(proc_id, name);
+                    pos = next;
+                },
+                KIND_VARIABLE => {
+                    let addr = read_u16(pos + 3)?;
+                    let (name, next) = read_name(pos + 5)?;
+                    symbols.variables.insert((proc_id, addr), name);
+                    pos = next;
+                },
+                _ => return None,
+            }
+        }
+        Some(symbols)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut procedures: Vec<_> = self.procedures.iter().collect();
+        procedures.sort();
+        let mut variables: Vec<_> = self.variables.iter().collect();
+        variables.sort();
+
+        let mut bytes = vec![];
+        for (proc_id, name) in procedures {
+            bytes.push(KIND_PROCEDURE);
+            bytes.extend(proc_id.to_le_bytes());
+            bytes.extend(name.as_bytes());
+            bytes.push(0);
+        }
+        for ((proc_id, addr), name) in variables {
+            bytes.push(KIND_VARIABLE);
+            bytes.extend(proc_id.to_le_bytes());
+            bytes.extend(addr.to_le_bytes());
+            bytes.extend(name.as_bytes());
+            bytes.push(0);
+        }
+        bytes
+    }
+}
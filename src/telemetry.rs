@@ -0,0 +1,40 @@
+use crate::pl0_vm::PL0VM;
+
+// Structured telemetry for embedders using tracing (feature tracing): a span for every procedure call, which is
+// the parent of the calls it makes, and events for the numbers the program reads and everything it prints.
+// Without the feature, all of this does nothing.
+#[derive(Clone, Default)]
+pub(crate) struct Telemetry {
+    // spans of the active calls, the innermost last
+    #[cfg(feature = "tracing")]
+    spans: Vec<tracing::Span>,
+}
+
+impl Telemetry {
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn call(&mut self, vm: &PL0VM, proc_id: usize) {
+        #[cfg(feature = "tracing")]
+        {
+            let parent = self.spans.last().and_then(|span| span.id());
+            self.spans.push(tracing::info_span!(parent: parent, "procedure", id = proc_id, procedure = %vm.proc_label(proc_id)));
+        }
+    }
+
+    // the span ends once the execution and all snapshots of it are dropped
+    pub(crate) fn ret(&mut self) {
+        #[cfg(feature = "tracing")]
+        self.spans.pop();
+    }
+
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn output(&self, text: impl std::fmt::Display) {
+        #[cfg(feature = "tracing")]
+        tracing::info!(parent: self.spans.last().and_then(|span| span.id()), text = %text, "output");
+    }
+
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn input(&self, value: i64) {
+        #[cfg(feature = "tracing")]
+        tracing::info!(parent: self.spans.last().and_then(|span| span.id()), value, "input");
+    }
+}
@@ -0,0 +1,113 @@
+use crate::decoder::decode_instruction;
+use crate::pl0_vm::{Step, PL0VM, START_FAILED_STATUS};
+use rust_i18n::t;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::io::{stdout, Write};
+
+// One line of the --trace output: an executed instruction and what it changed.
+// The lines are written while the program runs, so a pipe can be read while it is still running.
+#[derive(Serialize)]
+struct TraceEvent {
+    // number of the instruction since the start, from 1
+    step: usize,
+    pc: usize,
+    op: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    args: Vec<i32>,
+    // change of the stack size, in values
+    stack_delta: i64,
+    // value written to the stack (StoreValue, InputToAddr, Put)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    write: Option<TraceWrite>,
+    // everything the instruction printed
+    #[serde(skip_serializing_if = "String::is_empty")]
+    output: String,
+}
+
+#[derive(Serialize)]
+struct TraceWrite {
+    addr: usize,
+    old: i64,
+    new: i64,
+}
+
+impl PL0VM {
+    // run like execute and write a JSON object for every executed instruction to trace, one per line, returns the
+    // status like execute
+    pub fn execute_traced(&self, trace: &mut dyn Write) -> std::io::Result<i64> {
+        let mut execution = match self.start() {
+            Some(val) => val,
+            None => return Ok(START_FAILED_STATUS),
+        };
+        let mut step = 0;
+        loop {
+            let pc = execution.pc();
+            let instr = decode_instruction(self.program(), pc).ok();
+            let stack_len = execution.stack_values().len() as i64;
+            let mut output = vec![];
+            let state = execution.step(&mut output);
+            stdout().write_all(&output)?;
+            step += 1;
+            if let Some(instr) = instr {
+                let args = match instr.op.arg_count() {
+                    0 => vec![],
+                    count => (0..count).filter_map(|i| self.read_operand(instr.arg_addr(i), instr.wide)).collect(),
+                };
+                let event = TraceEvent {
+                    step, pc, op: format!("{:?}", instr.op), args,
+                    stack_delta: execution.stack_values().len() as i64 - stack_len,
+                    write: execution.last_write().map(|write| TraceWrite { addr: write.addr, old: write.old, new: write.new }),
+                    output: String::from_utf8_lossy(&output).to_string(),
+                };
+                writeln!(trace, "{}", serde_json::to_string(&event).expect("serializing can't fail"))?;
+            }
+            if state != Step::Running {
+                break;
+            }
+        }
+        trace.flush()?;
+        Ok(execution.status())
+    }
+
+    // run like execute and write the procedure calls in the Chrome trace event format to trace,
+    // for a timeline in chrome://tracing or Perfetto: one microsecond of the timeline is one executed instruction,
+    // returns the status like execute
+    pub fn execute_chrome_trace(&self, trace: &mut dyn Write) -> std::io::Result<i64> {
+        let mut execution = match self.start() {
+            Some(val) => val,
+            None => return Ok(START_FAILED_STATUS),
+        };
+        let event = |ph: &str, proc_id: usize, ts: usize| -> Value {
+            json!({ "name": t!("pl0.layout.procedure", id = self.proc_label(proc_id)), "cat": "call", "ph": ph, "ts": ts, "pid": 1, "tid": 1 })
+        };
+        // main starts with the program, every call begins and every return ends a slice
+        let mut calls = vec![execution.current_procedure()];
+        let mut events = vec![event("B", calls[0], 0)];
+        let mut step = 0;
+        loop {
+            let depth = execution.call_depth();
+            let state = execution.step(&mut stdout());
+            step += 1;
+            if execution.call_depth() > depth {
+                calls.push(execution.current_procedure());
+                events.push(event("B", execution.current_procedure(), step));
+            } else if execution.call_depth() < depth {
+                if let Some(proc_id) = calls.pop() {
+                    events.push(event("E", proc_id, step));
+                }
+            }
+            if state != Step::Running {
+                break;
+            }
+        }
+        // calls that didn't return because the program ended (with an error or a return from main)
+        while let Some(proc_id) = calls.pop() {
+            events.push(event("E", proc_id, step));
+        }
+        let json = json!({ "traceEvents": events, "displayTimeUnit": "ns", "otherData": { "instructions": step } });
+        writeln!(trace, "{}", serde_json::to_string(&json).expect("serializing can't fail"))?;
+        trace.flush()?;
+        Ok(execution.status())
+    }
+}
@@ -0,0 +1,212 @@
+use crate::debugger::Debugger;
+use crate::decoder::{decode_instruction, Instruction};
+use crate::opcodes::OpCode;
+use crate::pl0_vm::{Step, PL0VM};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use rust_i18n::t;
+
+// instructions run by one continue before the screen is updated, so endless loops can't freeze it
+const RUN_LIMIT: usize = 100_000;
+
+// Full-screen debugger for --debugger --tui, with panes for the code (the next instruction highlighted), the stack,
+// the active calls and the program output. The cursor in the code pane selects where b toggles a breakpoint.
+// Numbers the program reads are asked for in the status line, as the terminal belongs to the interface.
+struct Tui<'a> {
+    vm: &'a PL0VM,
+    debugger: Debugger<'a>,
+    code: &'a [Instruction],
+    cursor: ListState,
+    output: Vec<u8>,
+    // text of the status line
+    status: String,
+    // digits typed while the program waits for a number, None if it doesn't
+    input: Option<String>,
+}
+
+impl Tui<'_> {
+    // the program is in front of InputToAddr and no number is queued, so it has to be asked for
+    fn needs_input(&self) -> bool {
+        self.debugger.execution().is_some_and(|execution| {
+            execution.state() == Step::Running && execution.pending_inputs() == 0
+                && decode_instruction(self.vm.program(), execution.pc()).is_ok_and(|instr| instr.op == OpCode::InputToAddr)
+        })
+    }
+
+    // run at most limit instructions, stopping early at breakpoints, watchpoints and inputs
+    fn run(&mut self, limit: usize) {
+        for _ in 0..limit {
+            if self.needs_input() {
+                self.input = Some(String::new());
+                self.status = t!("tui.input").to_string();
+                return;
+            }
+            if self.debugger.step(&mut self.output) != Step::Running || self.debugger.at_stop() {
+                break;
+            }
+        }
+        self.update_status();
+    }
+
+    fn update_status(&mut self) {
+        self.status = match self.debugger.execution().map(|execution| execution.state()) {
+            Some(Step::Running) => t!("tui.stopped", steps = self.debugger.steps()).to_string(),
+            Some(Step::Finished) => t!("debugger.finished").to_string(),
+            Some(Step::Error) => t!("debugger.error").to_string(),
+            None => t!("debugger.not_running").to_string(),
+        };
+        // the code pane follows the next instruction
+        let pc = self.debugger.execution().map(|execution| execution.pc());
+        if let Some(i) = self.code.iter().position(|instr| Some(instr.addr) == pc) {
+            self.cursor.select(Some(i));
+        }
+    }
+
+    fn toggle_breakpoint(&mut self) {
+        let addr = match self.cursor.selected().and_then(|i| self.code.get(i)) {
+            Some(instr) => instr.addr,
+            None => return,
+        };
+        match self.debugger.breakpoints().iter().position(|breakpoint| breakpoint.addr == addr) {
+            Some(i) => {
+                let enabled = self.debugger.breakpoints()[i].enabled;
+                self.debugger.set_breakpoint_enabled(i + 1, !enabled);
+            },
+            None => {
+                self.debugger.add_breakpoint(addr);
+            },
+        }
+    }
+
+    // handle a key press, false to quit
+    fn key(&mut self, code: KeyCode) -> bool {
+        if let Some(input) = self.input.as_mut() {
+            match code {
+                KeyCode::Char(c) if c.is_ascii_digit() || (c == '-' && input.is_empty()) => input.push(c),
+                KeyCode::Backspace => { input.pop(); },
+                KeyCode::Enter => {
+                    if let Ok(num) = input.parse() {
+                        self.input = None;
+                        self.debugger.feed_input(num);
+                        self.update_status();
+                    }
+                },
+                KeyCode::Esc => return false,
+                _ => {},
+            }
+            return true;
+        }
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('s') => self.run(1),
+            KeyCode::Char('c') => self.run(RUN_LIMIT),
+            KeyCode::Char('u') => {
+                self.debugger.reverse_step();
+                self.update_status();
+            },
+            KeyCode::Char('r') => {
+                self.output.clear();
+                self.debugger.restart();
+                self.update_status();
+            },
+            KeyCode::Char('b') => self.toggle_breakpoint(),
+            KeyCode::Up => self.cursor.select_previous(),
+            KeyCode::Down => self.cursor.select_next(),
+            _ => {},
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(main);
+        let [code, output] = Layout::vertical([Constraint::Percentage(70), Constraint::Percentage(30)]).areas(left);
+        let [stack, calls] = Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(right);
+
+        let execution = self.debugger.execution();
+        let pc = execution.map(|execution| execution.pc());
+        let items: Vec<ListItem> = self.code.iter().map(|instr| {
+            let breakpoint = match self.debugger.breakpoints().iter().find(|breakpoint| breakpoint.addr == instr.addr) {
+                Some(breakpoint) if breakpoint.enabled => "●",
+                Some(_) => "○",
+                None => " ",
+            };
+            let marker = if Some(instr.addr) == pc { ">" } else { " " };
+            let item = ListItem::new(format!("{}{} {:04X} {}", breakpoint, marker, instr.addr, self.vm.instruction_text(instr)));
+            if Some(instr.addr) == pc { item.style(Style::new().yellow().bold()) } else { item }
+        }).collect();
+        let list = List::new(items).block(Block::bordered().title(t!("tui.code").to_string())).highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(list, code, &mut self.cursor);
+
+        // the end of the output is shown, it scrolls up as the program prints more
+        let text = String::from_utf8_lossy(&self.output).to_string();
+        let lines = text.lines().count() as u16;
+        let scroll = lines.saturating_sub(output.height.saturating_sub(2));
+        frame.render_widget(Paragraph::new(text).scroll((scroll, 0)).block(Block::bordered().title(t!("tui.output").to_string())), output);
+
+        self.draw_stack(frame, stack);
+
+        let call_lines: Vec<Line> = execution.map(|execution| execution.backtrace()).unwrap_or_default().iter().map(|call| {
+            let returns = match call.return_addr {
+                Some(addr) => format!(" -> {:04X}", addr),
+                None => String::new(),
+            };
+            Line::from(format!("{} fp {:04X}{}", self.vm.proc_label(call.procedure), call.frame_ptr, returns))
+        }).collect();
+        frame.render_widget(Paragraph::new(call_lines).block(Block::bordered().title(t!("tui.calls").to_string())), calls);
+
+        let status_text = match &self.input {
+            Some(input) => format!("{} {}", self.status, input),
+            None => format!("{} | {}", self.status, t!("tui.keys")),
+        };
+        frame.render_widget(Paragraph::new(status_text).reversed(), status);
+    }
+
+    // the top of the stack, newest value first, with the frame pointer marked
+    fn draw_stack(&self, frame: &mut Frame, area: Rect) {
+        let size = self.vm.data_size();
+        let lines: Vec<Line> = match self.debugger.execution() {
+            Some(execution) => execution.stack_values().iter().enumerate().rev().map(|(i, val)| {
+                let marker = if i * size == execution.frame_pointer() { " <<< fp" } else { "" };
+                Line::from(format!("{:04X}: {}{}", i * size, val, marker))
+            }).collect(),
+            None => vec![],
+        };
+        frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(t!("tui.stack").to_string())), area);
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// for --debugger --tui
+pub fn run(vm: &PL0VM) -> std::io::Result<()> {
+    let code = match vm.decode_code() {
+        Ok(val) => val,
+        Err(err) => {
+            println!("{}", err);
+            return Ok(());
+        },
+    };
+    let mut tui = Tui { vm, debugger: Debugger::new(vm), code, cursor: ListState::default(), output: vec![], status: String::new(), input: None };
+    if !tui.debugger.restart() {
+        return Ok(());
+    }
+    tui.update_status();
+    let mut terminal = ratatui::init();
+    let result = tui.event_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
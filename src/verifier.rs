@@ -0,0 +1,216 @@
+use crate::decoder::Instruction;
+use crate::opcodes::OpCode;
+use crate::pl0_vm::PL0VM;
+use rust_i18n::t;
+
+impl PL0VM {
+    // Static checks of the code, run before execution: every jump has to land on an instruction of its
+    // own procedure, every procedure, variable, constant and string referenced has to exist, and the stack depth
+    // has to be consistent (see stack_depths).
+    // Returns all problems found, with the address of the instruction as prefix.
+    pub fn verify(&self) -> Result<(), Vec<String>> {
+        let (mut problems, stack_problems) = self.verify_parts();
+        problems.extend(stack_problems);
+        if problems.is_empty() { Ok(()) } else { Err(problems) }
+    }
+
+    // The problems of verify, split into the ones that keep the program from running and the stack depth problems.
+    // Compilers produced programs popping from an empty stack on some path before there was a verifier, they still
+    // run and fail at runtime if they get there.
+    pub(crate) fn verify_parts(&self) -> (Vec<String>, Vec<String>) {
+        // also fails for unterminated strings and instructions cut off by the end of the file
+        let image = match self.program_image() {
+            Ok(val) => val,
+            Err(err) => return (vec![err], vec![]),
+        };
+        let (instructions, constant_count) = (&image.instructions, image.constants.len());
+        // ID and bytes of variables of every procedure
+        let procedure_vars: Vec<(i32, i32)> = instructions.iter()
+            .filter(|instr| instr.op == OpCode::EntryProc)
+            .map(|instr| [1, 2].map(|i| self.read_operand(instr.arg_addr(i), instr.wide).expect("length checked while decoding")))
+            .map(|[id, vars]| (id, vars))
+            .collect();
+        let vars_of = |id: i32| procedure_vars.iter().find(|(other, _)| *other == id).map(|(_, vars)| *vars);
+
+        let (mut problems, mut stack_problems) = (vec![], vec![]);
+        // instructions of one procedure, starting with its EntryProc
+        for procedure in instructions.chunk_by(|_, instr| instr.op != OpCode::EntryProc) {
+            for instr in procedure {
+                let arg = |i: usize| self.read_operand(instr.arg_addr(i), instr.wide).expect("length checked while decoding");
+                let problem = match instr.op {
+                    OpCode::Jump | OpCode::JumpIfFalse => {
+                        match self.jump_target(instr) {
+                            Some(target) if is_boundary(procedure, target) => None,
+                            _ => Some(t!("verifier.jump_target", offset = arg(0))),
+                        }
+                    },
+                    // variables of other procedures are reached through the procedure ID and the offset in its frame
+                    OpCode::PushValueGlobalVar | OpCode::PushAddressGlobalVar => match vars_of(arg(1)) {
+                        None => Some(t!("verifier.global_procedure", id = arg(1))),
+                        Some(vars) if arg(0) < 0 || arg(0) >= vars => {
+                            Some(t!("verifier.global_variable", offset = arg(0), id = self.proc_label(arg(1) as usize), bytes = vars))
+                        },
+                        Some(_) => None,
+                    },
+                    op if op.is_call() && vars_of(arg(0)).is_none() => Some(t!("verifier.procedure", id = arg(0))),
+                    // the parameters become the first variables of the callee
+                    OpCode::CallProcArgs if arg(1) < 0 || arg(1) as i64 * self.data_size() as i64 > vars_of(arg(0)).unwrap_or_default() as i64 => {
+                        Some(t!("verifier.parameters", count = arg(1), id = self.proc_label(arg(0) as usize), bytes = vars_of(arg(0)).unwrap_or_default()))
+                    },
+                    OpCode::PushConstant if arg(0) < 0 || arg(0) as usize >= constant_count => Some(t!("verifier.constant", i = arg(0), count = constant_count)),
+                    op if op.is_float() && self.data_size() < 4 => Some(t!("verifier.float_arch", bits = self.data_size() * 8)),
+                    OpCode::Assert if arg(0) == -1 => None,
+                    OpCode::PutStringRef | OpCode::PushString | OpCode::Assert if arg(0) < 0 || arg(0) as usize >= self.strings().len() => Some(t!("verifier.string", i = arg(0), count = self.strings().len())),
+                    _ => None,
+                };
+                if let Some(problem) = problem {
+                    problems.push(format!("{:04X}: {} - {}", instr.addr, instr.op, problem));
+                }
+            }
+            // callers only know whether a result follows if all returns agree
+            if procedure.iter().any(|instr| instr.op == OpCode::ReturnValue) {
+                for instr in procedure.iter().filter(|instr| instr.op == OpCode::ReturnProc) {
+                    problems.push(format!("{:04X}: {} - {}", instr.addr, instr.op, t!("verifier.mixed_returns")));
+                }
+            }
+            stack_problems.extend(self.stack_depths(procedure).1);
+        }
+        (problems, stack_problems)
+    }
+}
+
+impl PL0VM {
+    // Follow all paths through the procedure with the number of values on the stack: no instruction may
+    // pop more values than there are, and all paths leading to an instruction have to agree on the depth.
+    // Returns the depth in front of every instruction (None if it is unreachable) and the problems found.
+    pub(crate) fn stack_depths(&self, procedure: &[Instruction]) -> (Vec<Option<usize>>, Vec<String>) {
+        let mut problems = vec![];
+        // stack depth when reaching the instruction with the same index, None if not reached yet
+        let mut depths: Vec<Option<usize>> = vec![None; procedure.len()];
+        let mut pending = vec![(0, 0)];
+        while let Some((index, depth)) = pending.pop() {
+            let instr = &procedure[index];
+            match depths[index] {
+                Some(known) if known == depth => continue,
+                Some(known) => {
+                    problems.push(format!("{:04X}: {} - {}", instr.addr, instr.op, t!("verifier.stack_mismatch", depth = depth, other = known)));
+                    continue;
+                },
+                None => depths[index] = Some(depth),
+            }
+
+            let (pops, pushes) = self.stack_effect(instr);
+            if depth < pops {
+                problems.push(format!("{:04X}: {} - {}", instr.addr, instr.op, t!("verifier.stack_underflow", pops = pops, depth = depth)));
+                continue;
+            }
+            let depth = depth - pops + pushes;
+            pending.extend(self.successors(procedure, index).into_iter().map(|next| (next, depth)));
+        }
+        (depths, problems)
+    }
+
+    // values the instruction takes from the stack and puts on it, CallProcArgs takes its parameters and calls of
+    // procedures returning with ReturnValue put the result there
+    pub(crate) fn stack_effect(&self, instr: &Instruction) -> (usize, usize) {
+        let arg = |i: usize| self.read_operand(instr.arg_addr(i), instr.wide).expect("length checked while decoding");
+        match instr.op {
+            OpCode::CallProc => (0, self.returns_value(arg(0)) as usize),
+            OpCode::CallProcArgs => (arg(1).max(0) as usize, self.returns_value(arg(0)) as usize),
+            op => op.stack_effect(),
+        }
+    }
+
+    // the procedure returns with ReturnValue
+    pub(crate) fn returns_value(&self, proc_id: i32) -> bool {
+        let image = match self.program_image() {
+            Ok(val) => val,
+            Err(_) => return false,
+        };
+        image.procedures.iter().find(|(id, _)| *id as i64 == proc_id as i64).is_some_and(|(_, range)| {
+            let first = image.instructions.partition_point(|instr| instr.addr < range.start);
+            image.instructions[first..].iter().take_while(|instr| instr.addr < range.end).any(|instr| instr.op == OpCode::ReturnValue)
+        })
+    }
+}
+
+fn is_boundary(procedure: &[Instruction], addr: usize) -> bool {
+    procedure.iter().any(|instr| instr.addr == addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pl0_vm::PL0VM;
+
+    // main with 4 bytes of variables, the code and the constants
+    fn program(code: &[u8], constants: &[i32]) -> PL0VM {
+        let mut bytes = vec![0x01, 0x00, 0x04, 0x00, 0x1A];
+        bytes.extend_from_slice(&(7 + code.len() as i16).to_le_bytes());
+        bytes.extend_from_slice(&[0x00, 0x00, 0x04, 0x00]);
+        bytes.extend_from_slice(code);
+        for c in constants {
+            bytes.extend_from_slice(&c.to_le_bytes());
+        }
+        PL0VM::from_bytes(bytes)
+    }
+
+    // the problems keeping the program from starting, which it only does without any
+    fn refused(code: &[u8], constants: &[i32]) -> usize {
+        let vm = program(code, constants);
+        let (problems, _) = vm.verify_parts();
+        assert_eq!(vm.start().is_none(), !problems.is_empty());
+        problems.len()
+    }
+
+    #[test]
+    fn accepts_valid_code() {
+        // PushConstant 0, OutputValue, ReturnProc
+        let vm = program(&[0x06, 0x00, 0x00, 0x08, 0x17], &[1]);
+        assert_eq!(vm.verify(), Ok(()));
+        assert!(vm.start().is_some());
+    }
+
+    #[test]
+    fn rejects_missing_constant() {
+        assert_eq!(refused(&[0x06, 0x01, 0x00, 0x08, 0x17], &[1]), 1);
+        assert_eq!(refused(&[0x06, 0xFF, 0xFF, 0x08, 0x17], &[1]), 1);
+    }
+
+    #[test]
+    fn rejects_jump_outside_of_procedure() {
+        // Jump +100, ReturnProc
+        assert_eq!(refused(&[0x18, 0x64, 0x00, 0x17], &[]), 1);
+        // Jump -1 into the jump itself
+        assert_eq!(refused(&[0x18, 0xFF, 0xFF, 0x17], &[]), 1);
+    }
+
+    #[test]
+    fn rejects_missing_procedure() {
+        // CallProc 5, ReturnProc
+        assert_eq!(refused(&[0x16, 0x05, 0x00, 0x17], &[]), 1);
+    }
+
+    #[test]
+    fn rejects_global_variables_outside_of_procedures() {
+        // PushValueGlobalVar of procedure 3, of offset 4 and -4 of main, each followed by Pop
+        let code = [0x02, 0x00, 0x00, 0x03, 0x00, 0x1C, 0x02, 0x04, 0x00, 0x00, 0x00, 0x1C, 0x02, 0xFC, 0xFF, 0x00, 0x00, 0x1C, 0x17];
+        assert_eq!(refused(&code, &[]), 3);
+        // offset 0 of main is its variable
+        assert_eq!(refused(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x1C, 0x17], &[]), 0);
+    }
+
+    #[test]
+    fn stack_problems_only_warn() {
+        // Pop on the empty stack, like the stray instructions of older compilers
+        let vm = program(&[0x1C, 0x17], &[]);
+        let (problems, stack_problems) = vm.verify_parts();
+        assert!(problems.is_empty());
+        assert_eq!(stack_problems.len(), 1);
+        assert!(vm.verify().is_err());
+        // run_fast can't rely on the stack depth, it has to run like run
+        let (mut normal, mut fast) = (vec![], vec![]);
+        let state = vm.start().unwrap().run(&mut normal);
+        assert_eq!(vm.start().unwrap().run_fast(&mut fast), state);
+        assert_eq!(fast, normal);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::opcodes::OpCode;
+use crate::pl0_vm::PL0VM;
+use crate::diagln;
+use rust_i18n::t;
+use std::collections::BTreeMap;
+
+// Where procedures, constants and global variables are referenced, as addresses of the referencing instructions.
+// Global variables are the variables of the main procedure and variables accessed from other procedures
+// than their own (PushValueGlobalVar/PushAddressGlobalVar).
+#[derive(Debug, Default, Clone)]
+pub struct CrossReferences {
+    // procedure ID => call sites
+    pub calls: BTreeMap<usize, Vec<usize>>,
+    // constant index => instructions pushing it
+    pub constants: BTreeMap<usize, Vec<usize>>,
+    // (procedure ID, variable address) => instructions accessing the variable
+    pub variables: BTreeMap<(usize, i32), Vec<usize>>,
+}
+
+impl PL0VM {
+    pub fn cross_references(&self) -> Result<CrossReferences, String> {
+        let layout = self.procedure_layout()?;
+        let instructions = self.decode_code()?;
+        let mut xref = CrossReferences::default();
+        // every procedure is listed, even if it is never called
+        for (proc_id, _) in layout {
+            xref.calls.insert(*proc_id, vec![]);
+        }
+        for ((proc_id, _), procedure) in layout.iter().zip(instructions.chunk_by(|_, instr| instr.op != OpCode::EntryProc)) {
+            for instr in procedure {
+                let arg = |i: usize| self.read_operand(instr.arg_addr(i), instr.wide).expect("length checked while decoding");
+                match instr.op {
+                    OpCode::CallProc | OpCode::CallProcArgs => xref.calls.entry(arg(0) as usize).or_default().push(instr.addr),
+                    OpCode::PushConstant => xref.constants.entry(arg(0) as usize).or_default().push(instr.addr),
+                    OpCode::PushValueMainVar | OpCode::PushAddressMainVar => xref.variables.entry((0, arg(0))).or_default().push(instr.addr),
+                    OpCode::PushValueLocalVar | OpCode::PushAddressLocalVar if *proc_id == 0 => xref.variables.entry((0, arg(0))).or_default().push(instr.addr),
+                    OpCode::PushValueGlobalVar | OpCode::PushAddressGlobalVar => xref.variables.entry((arg(1) as usize, arg(0))).or_default().push(instr.addr),
+                    _ => {},
+                }
+            }
+        }
+        Ok(xref)
+    }
+
+    // for --xref: all references, sorted by what they reference
+    pub fn print_cross_references(&self) {
+        let xref = match self.cross_references() {
+            Ok(val) => val,
+            Err(err) => return diagln!("{}", err),
+        };
+        let addrs = |addrs: &[usize]| match addrs.is_empty() {
+            true => "-".to_string(),
+            false => addrs.iter().map(|addr| format!("{:04X}", addr)).collect::<Vec<_>>().join(", "),
+        };
+        for (proc_id, sites) in &xref.calls {
+            diagln!("{}", t!("xref.procedure", id = format!("{}{}", proc_id, self.proc_suffix(*proc_id)), addrs = addrs(sites)));
+        }
+        for (i, sites) in &xref.constants {
+            diagln!("{}", t!("xref.constant", i = i, addrs = addrs(sites)));
+        }
+        for ((proc_id, addr), sites) in &xref.variables {
+            diagln!("{}", t!("xref.variable", addr = format!("{:04X}{}", addr, self.var_suffix(*proc_id, *addr)), proc_id = self.proc_label(*proc_id), addrs = addrs(sites)));
+        }
+    }
+}
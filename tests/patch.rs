@@ -0,0 +1,128 @@
+// Inserting and replacing instructions with PL0VM::insert_instructions and PL0VM::replace_instruction, checked by
+// verifying and running the patched program.
+use pl0_vm_rs::pl0_vm::PL0VM;
+
+// var i; i := 0; while i < 3 do begin ! i; i := i + 1 end
+const LOOP: &[u8] = &[
+    0x03, 0x00, 0x00, // 000B: PushAddressLocalVar 0
+    0x06, 0x00, 0x00, // 000E: PushConstant 0 (0)
+    0x07, // 0011: StoreValue
+    0x00, 0x00, 0x00, // 0012: PushValueLocalVar 0
+    0x06, 0x01, 0x00, // 0015: PushConstant 1 (3)
+    0x12, // 0018: CompareLT
+    0x19, 0x12, 0x00, // 0019: JumpIfFalse => 002E
+    0x00, 0x00, 0x00, // 001C: PushValueLocalVar 0
+    0x08, // 001F: OutputValue
+    0x03, 0x00, 0x00, // 0020: PushAddressLocalVar 0
+    0x00, 0x00, 0x00, // 0023: PushValueLocalVar 0
+    0x06, 0x02, 0x00, // 0026: PushConstant 2 (1)
+    0x0C, // 0029: OpAdd
+    0x07, // 002A: StoreValue
+    0x18, 0xE4, 0xFF, // 002B: Jump => 0012
+    0x17, // 002E: ReturnProc
+];
+
+const BACKWARD_JUMP: usize = 0x2B;
+const BACKWARD_TARGET: usize = 0x12;
+const FORWARD_JUMP: usize = 0x19;
+const FORWARD_TARGET: usize = 0x2E;
+const STORE: usize = 0x11;
+const OUTPUT: usize = 0x1F;
+
+const PUT_X: &[u8] = &[0x1B, b'x', 0x00];
+
+// one procedure for the 32 bit architecture with one variable and the constants 0, 3 and 1
+fn program(body: &[u8]) -> PL0VM {
+    let mut bytes = vec![0x01, 0x00, 0x04, 0x00, 0x1A];
+    bytes.extend_from_slice(&(7 + body.len() as i16).to_le_bytes());
+    bytes.extend_from_slice(&[0x00, 0x00, 0x04, 0x00]);
+    bytes.extend_from_slice(body);
+    for c in [0i32, 3, 1] {
+        bytes.extend_from_slice(&c.to_le_bytes());
+    }
+    PL0VM::from_bytes(bytes)
+}
+
+fn output(vm: &PL0VM) -> String {
+    assert_eq!(vm.verify(), Ok(()));
+    String::from_utf8(vm.execute_captured()).unwrap()
+}
+
+fn inserted(addr: usize, code: &[u8]) -> String {
+    let mut vm = program(LOOP);
+    vm.insert_instructions(addr, code).unwrap();
+    output(&vm)
+}
+
+fn replaced(addr: usize, code: &[u8]) -> String {
+    let mut vm = program(LOOP);
+    vm.replace_instruction(addr, code).unwrap();
+    output(&vm)
+}
+
+#[test]
+fn unpatched() {
+    assert_eq!(output(&program(LOOP)), "0\n1\n2\n");
+}
+
+#[test]
+fn insert_before_both_jumps() {
+    assert_eq!(inserted(STORE, PUT_X), "x\n0\n1\n2\n");
+}
+
+#[test]
+fn insert_at_backward_jump_target() {
+    // the backward jump lands on the inserted code
+    assert_eq!(inserted(BACKWARD_TARGET, PUT_X), "x\n0\nx\n1\nx\n2\nx\n");
+}
+
+#[test]
+fn insert_at_forward_jump() {
+    assert_eq!(inserted(FORWARD_JUMP, PUT_X), "x\n0\nx\n1\nx\n2\nx\n");
+}
+
+#[test]
+fn insert_between_jumps() {
+    assert_eq!(inserted(OUTPUT, PUT_X), "x\n0\nx\n1\nx\n2\n");
+}
+
+#[test]
+fn insert_at_backward_jump() {
+    assert_eq!(inserted(BACKWARD_JUMP, PUT_X), "0\nx\n1\nx\n2\nx\n");
+}
+
+#[test]
+fn insert_at_forward_jump_target() {
+    // the forward jump lands on the inserted code
+    assert_eq!(inserted(FORWARD_TARGET, PUT_X), "0\n1\n2\nx\n");
+}
+
+#[test]
+fn replace_before_backward_jump() {
+    assert_eq!(replaced(OUTPUT, &[0x08, 0x1B, b'x', 0x00]), "0\nx\n1\nx\n2\nx\n");
+}
+
+#[test]
+fn replace_backward_jump() {
+    // longer code instead of the jump, the forward jump behind it has to move
+    assert_eq!(replaced(BACKWARD_JUMP, PUT_X), "0\nx\n");
+}
+
+#[test]
+fn replace_with_shorter_code() {
+    // ReturnProc instead of the backward jump, the forward jump target moves back
+    assert_eq!(replaced(BACKWARD_JUMP, &[0x17]), "0\n");
+}
+
+#[test]
+fn replace_forward_jump_target() {
+    assert_eq!(replaced(FORWARD_TARGET, &[0x1B, b'x', 0x00, 0x17]), "0\n1\n2\nx\n");
+}
+
+#[test]
+fn patch_inside_instruction() {
+    let mut vm = program(LOOP);
+    assert!(vm.insert_instructions(BACKWARD_JUMP + 1, PUT_X).is_err());
+    assert!(vm.replace_instruction(BACKWARD_JUMP + 1, PUT_X).is_err());
+    assert_eq!(vm.program(), program(LOOP).program());
+}